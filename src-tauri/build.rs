@@ -1,3 +1,4 @@
 fn main() {
+    #[cfg(feature = "webview")]
     tauri_build::build()
 }
@@ -1,3 +1,6 @@
 pub static APP_DISPLAY_NAME: &str = "窗口置顶工具";
+/// 与 tauri.conf.json 中的 identifier 保持一致，不经过 tauri 运行时用于定位配置目录
+pub static APP_IDENTIFIER: &str = "com.top-set-tool.app";
 pub static APP_MAIN_WINDOW_LABEL: &str = "main";
+pub static APP_TRAY_ID: &str = "main";
 pub static APP_WINDOW_SIZE: (i32, i32) = (300, 300);
@@ -1,5 +1,7 @@
 use crate::tool::app_tool::{self, AppSettings};
-use std::sync::Mutex;
+use crate::tool::border_overlay::BorderOverlay;
+use crate::tool::pin_registry::PinWatchdog;
+use std::sync::{Arc, Mutex};
 use tauri::State;
 use tauri_plugin_autostart::ManagerExt;
 
@@ -48,12 +50,24 @@ pub fn get_app_setting(setting: State<'_, Mutex<AppSettings>>) -> Result<AppSett
 #[tauri::command]
 pub fn save_app_setting(
     old_setting: State<'_, Mutex<AppSettings>>,
+    watchdog: State<'_, Arc<PinWatchdog>>,
+    border: State<'_, Arc<BorderOverlay>>,
     settings: AppSettings,
     app: tauri::AppHandle,
 ) -> Result<(), String> {
     println!("settings:{:?}", settings);
+    let border_style = settings.pin_border.style().map_err(|err| err.to_string())?;
     let mut old_setting = old_setting.lock().unwrap();
     old_setting.auto_start = settings.auto_start;
+    old_setting.icon_disk_cache = settings.icon_disk_cache;
+    old_setting.reassert_policy = settings.reassert_policy;
+    old_setting.pin_exit_policy = settings.pin_exit_policy;
+    old_setting.suspend_on_fullscreen = settings.suspend_on_fullscreen;
+    old_setting.pin_border = settings.pin_border.clone();
+    old_setting.rpc_enabled = settings.rpc_enabled;
+    watchdog.set_policy(settings.reassert_policy);
+    watchdog.set_suspend_on_fullscreen(settings.suspend_on_fullscreen);
+    border.set_style(border_style);
     if old_setting.app_exit_type != settings.app_exit_type {
         let autostart_manager = app.autolaunch();
         if settings.auto_start {
@@ -64,5 +78,10 @@ pub fn save_app_setting(
         }
         old_setting.app_exit_type = settings.app_exit_type.clone();
     }
+    //快捷键通过 rebind_hotkey 单独修改，以当前生效的为准
+    let settings = AppSettings {
+        hotkeys: old_setting.hotkeys.clone(),
+        ..settings
+    };
     app_tool::save_setting(&app, &settings).map_err(|err| err.to_string())
 }
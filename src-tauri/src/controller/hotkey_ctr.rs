@@ -0,0 +1,33 @@
+use crate::tool::app_tool::{self, AppSettings};
+use crate::tool::hotkey_tool::{self, HotkeyAction, HotkeyBinding};
+use std::sync::Mutex;
+use tauri::State;
+
+#[tauri::command]
+pub fn list_hotkeys(setting: State<'_, Mutex<AppSettings>>) -> Vec<HotkeyBinding> {
+    hotkey_tool::bindings(&setting.lock().unwrap())
+}
+
+/// 修改一个操作的快捷键，`accelerator` 为空表示解除绑定
+#[tauri::command]
+pub fn rebind_hotkey(
+    action: HotkeyAction,
+    accelerator: String,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    hotkey_tool::rebind_hotkey(&app, action, &accelerator).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn reset_hotkeys(app: tauri::AppHandle) -> Result<(), String> {
+    hotkey_tool::reset_hotkeys(&app).map_err(|err| err.to_string())
+}
+
+/// 按当前设置重新注册全局快捷键，注册失败（如快捷键已被其它程序占用）时通过 sys_error 通知前端
+#[tauri::command]
+pub fn register_hotkeys(setting: State<'_, Mutex<AppSettings>>, app: tauri::AppHandle) {
+    let setting = setting.lock().unwrap().clone();
+    if let Err(e) = hotkey_tool::register_hotkeys(&app, &setting) {
+        app_tool::send_error_to_frontend(&app, e);
+    }
+}
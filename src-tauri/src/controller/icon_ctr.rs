@@ -0,0 +1,57 @@
+use crate::tool::icon_cache::IconCache;
+use crate::tool::window_backend::WindowBackendState;
+use std::sync::Arc;
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager};
+
+/// 自定义协议名，前端通过 `convertFileSrc(key, "icon")` 拼出图标地址
+pub const ICON_PROTOCOL: &str = "icon";
+/// 未指定 size 时的图标边长
+const DEFAULT_ICON_SIZE: u32 = 32;
+const MIN_ICON_SIZE: u32 = 16;
+const MAX_ICON_SIZE: u32 = 256;
+
+/// 处理 `icon://<key>?size=32` 请求，返回对应尺寸的 PNG
+///
+/// 图标键包含程序文件的修改时间，同一个地址的内容不会变化，可以让 webview 长期缓存
+pub fn icon_protocol(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let key = request.uri().path().trim_start_matches('/');
+    let size = request
+        .uri()
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("size="))
+        .and_then(|size| size.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_ICON_SIZE)
+        .clamp(MIN_ICON_SIZE, MAX_ICON_SIZE);
+
+    let (Some(icon_cache), Some(backend)) = (
+        app.try_state::<Arc<IconCache>>(),
+        app.try_state::<WindowBackendState>(),
+    ) else {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "窗口后端尚未初始化");
+    };
+    let result = icon_cache.get_png(key, size, |path| {
+        backend.0.load_icon(path, size)?.into_png()
+    });
+    match result {
+        Ok(png) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "image/png")
+            .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .body(png.to_vec())
+            .unwrap_or_default(),
+        Err(e) => error_response(StatusCode::NOT_FOUND, &e.to_string()),
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(message.as_bytes().to_vec())
+        .unwrap_or_default()
+}
@@ -0,0 +1,64 @@
+use crate::constant;
+use crate::tool::app_tool;
+use crate::tool::layout_profile::{self, ApplyReport, LayoutProfile, LayoutStore};
+use crate::tool::pin_registry::PinRegistry;
+use crate::tool::window_backend::WindowBackendState;
+use std::sync::Arc;
+use tauri::State;
+
+#[tauri::command]
+pub fn list_layouts(store: State<'_, LayoutStore>) -> Result<Vec<LayoutProfile>, String> {
+    store.list().map_err(|err| err.to_string())
+}
+
+/// 把当前窗口的位置、置顶和不透明度保存为布局，同名的布局会被覆盖
+#[tauri::command]
+pub fn save_layout(
+    name: String,
+    app: tauri::AppHandle,
+    backend: State<'_, WindowBackendState>,
+    store: State<'_, LayoutStore>,
+) -> Result<LayoutProfile, String> {
+    let result: anyhow::Result<LayoutProfile> = (|| {
+        let hwnd_self = app_tool::get_self_window_id(&app, constant::APP_MAIN_WINDOW_LABEL);
+        let profile = layout_profile::snapshot(backend.0.as_ref(), &name, hwnd_self)?;
+        store.save(&profile)?;
+        Ok(profile)
+    })();
+    result.map_err(|err| err.to_string())
+}
+
+/// 应用布局，返回还原的窗口数以及没有找到或还原失败的窗口
+#[tauri::command]
+pub fn apply_layout(
+    name: String,
+    app: tauri::AppHandle,
+    backend: State<'_, WindowBackendState>,
+    registry: State<'_, Arc<PinRegistry>>,
+    store: State<'_, LayoutStore>,
+) -> Result<ApplyReport, String> {
+    let profile = store.load(&name).map_err(|err| err.to_string())?;
+    let hwnd_self = app_tool::get_self_window_id(&app, constant::APP_MAIN_WINDOW_LABEL);
+    Ok(layout_profile::apply(
+        backend.0.as_ref(),
+        &registry,
+        &profile,
+        hwnd_self,
+    ))
+}
+
+#[tauri::command]
+pub fn delete_layout(name: String, store: State<'_, LayoutStore>) -> Result<(), String> {
+    store.delete(&name).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn rename_layout(
+    name: String,
+    new_name: String,
+    store: State<'_, LayoutStore>,
+) -> Result<LayoutProfile, String> {
+    store
+        .rename(&name, &new_name)
+        .map_err(|err| err.to_string())
+}
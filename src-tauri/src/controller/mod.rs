@@ -1,3 +1,8 @@
 pub mod app_ctr;
+pub mod hotkey_ctr;
+pub mod icon_ctr;
+pub mod layout_ctr;
+pub mod rpc_ctr;
+pub mod rule_ctr;
 pub mod update_ctr;
 pub mod win_app_ctr;
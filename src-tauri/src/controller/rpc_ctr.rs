@@ -0,0 +1,54 @@
+use crate::constant;
+use crate::controller::app_ctr;
+use crate::tool::app_tool::{self, AppSettings};
+use crate::tool::pin_registry::PinRegistry;
+use crate::tool::rpc_tool::{self, EventHub, RpcError};
+use crate::tool::window_backend::WindowBackendState;
+use crate::tool::window_watcher::WindowWatcher;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager};
+
+/// 启动本机 JSON-RPC 控制接口，并把窗口和置顶的变化推送给订阅者
+///
+/// 方法与前端使用的命令同名，参数也相同；看门狗事件由 `setup` 直接发布到 `hub`
+pub fn start_rpc_server(app: &AppHandle, hub: Arc<EventHub>) {
+    rpc_tool::publish_changes(
+        &hub,
+        &app.state::<Arc<WindowWatcher>>(),
+        &app.state::<Arc<PinRegistry>>(),
+    );
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(rpc_tool::serve(hub, move |method, params| {
+        dispatch(&app, method, params)
+    }));
+}
+
+fn dispatch(app: &AppHandle, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "get_app_setting" => rpc_tool::result(app_ctr::get_app_setting(app.state())),
+        "save_app_setting" => {
+            #[derive(Deserialize)]
+            struct Params {
+                settings: AppSettings,
+            }
+            let p: Params = rpc_tool::params(params)?;
+            rpc_tool::result(app_ctr::save_app_setting(
+                app.state(),
+                app.state(),
+                app.state(),
+                p.settings,
+                app.clone(),
+            ))
+        }
+        method => {
+            let backend = app.state::<WindowBackendState>();
+            let registry = app.state::<Arc<PinRegistry>>();
+            let hwnd_self = app_tool::get_self_window_id(app, constant::APP_MAIN_WINDOW_LABEL);
+            rpc_tool::dispatch_windows(backend.0.as_ref(), &registry, hwnd_self, method, params)
+                .unwrap_or_else(|| Err(RpcError::method_not_found(method)))
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::tool::rule_tool::{PinRule, RuleEngine};
+use crate::tool::window_watcher::WindowWatcher;
+use std::sync::Arc;
+use tauri::State;
+
+#[tauri::command]
+pub fn list_rules(engine: State<'_, Arc<RuleEngine>>) -> Vec<PinRule> {
+    engine.rules()
+}
+
+/// 新增规则，立即应用到当前已打开的窗口
+#[tauri::command]
+pub fn add_rule(
+    rule: PinRule,
+    engine: State<'_, Arc<RuleEngine>>,
+    watcher: State<'_, Arc<WindowWatcher>>,
+) -> Result<PinRule, String> {
+    let rule = engine.add_rule(rule).map_err(|err| err.to_string())?;
+    engine.apply_to(&watcher.windows());
+    Ok(rule)
+}
+
+#[tauri::command]
+pub fn update_rule(
+    rule: PinRule,
+    engine: State<'_, Arc<RuleEngine>>,
+    watcher: State<'_, Arc<WindowWatcher>>,
+) -> Result<(), String> {
+    engine.update_rule(rule).map_err(|err| err.to_string())?;
+    engine.apply_to(&watcher.windows());
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_rule(id: u64, engine: State<'_, Arc<RuleEngine>>) -> Result<(), String> {
+    engine.delete_rule(id).map_err(|err| err.to_string())
+}
@@ -1,29 +1,170 @@
 use crate::constant;
-use crate::tool::win_app_tool::{self, WindowInfo};
+use crate::tool::app_tool;
+use crate::tool::pin_registry::{self, PinInfo, PinRegistry};
+use crate::tool::window_backend::{self, BackendCapabilities, WindowBackendState, WindowInfo};
+use crate::tool::window_watcher::{WindowChangeEvent, WindowWatcher};
+use std::sync::Arc;
+use tauri::{ipc::Channel, State};
 
 #[tauri::command]
-pub fn list_windows(app: tauri::AppHandle) -> Vec<WindowInfo> {
-    let hwnd_self =
-        win_app_tool::get_win32_hwnd_by_tauri_hwnd(&app, constant::APP_MAIN_WINDOW_LABEL);
-    win_app_tool::enum_windows(hwnd_self)
+pub fn list_windows(
+    app: tauri::AppHandle,
+    backend: State<'_, WindowBackendState>,
+    registry: State<'_, Arc<PinRegistry>>,
+) -> Vec<WindowInfo> {
+    let hwnd_self = app_tool::get_self_window_id(&app, constant::APP_MAIN_WINDOW_LABEL);
+    let mut windows = window_backend::list_windows(backend.0.as_ref(), hwnd_self);
+    for window in &mut windows {
+        registry.decorate(window);
+    }
+    windows
 }
 
+/// 设置或取消置顶，`duration_secs`（时长）或 `deadline`（Unix 时间戳，毫秒）用于定时置顶，到期自动取消
 #[tauri::command]
-pub fn toggle_topmost(hwnd: isize, enable: bool, app: tauri::AppHandle) {
-    if enable {
-        let hwnd_self =
-            win_app_tool::get_win32_hwnd_by_tauri_hwnd(&app, constant::APP_MAIN_WINDOW_LABEL);
-        let hwnd_self = match hwnd_self {
-            Some(hwnd_self) => Some(hwnd_self.0),
-            None => None,
-        };
-        win_app_tool::show_and_topmost(hwnd, hwnd_self);
-    } else {
-        win_app_tool::show_and_not_topmost(hwnd);
-    }
+pub fn toggle_topmost(
+    hwnd: isize,
+    enable: bool,
+    duration_secs: Option<u64>,
+    deadline: Option<u64>,
+    app: tauri::AppHandle,
+    backend: State<'_, WindowBackendState>,
+    registry: State<'_, Arc<PinRegistry>>,
+) -> Result<(), String> {
+    let hwnd_self = app_tool::get_self_window_id(&app, constant::APP_MAIN_WINDOW_LABEL);
+    let expires_at = deadline
+        .or(duration_secs.map(|secs| pin_registry::millis_after(pin_registry::now_millis(), secs)));
+    pin_registry::set_pinned(
+        backend.0.as_ref(),
+        &registry,
+        hwnd,
+        enable,
+        expires_at,
+        hwnd_self,
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// 取消列表中所有窗口的置顶，包括不是本程序置顶的窗口
+#[tauri::command]
+pub fn cancel_all_topmost(
+    hwnd_list: Vec<isize>,
+    backend: State<'_, WindowBackendState>,
+    registry: State<'_, Arc<PinRegistry>>,
+) -> Result<(), String> {
+    pin_registry::unpin_windows(backend.0.as_ref(), &registry, &hwnd_list)
+        .map_err(|err| err.to_string())
+}
+
+/// 设置窗口的不透明度，`alpha` 范围 0.0~1.0（最低限制为 20%），返回实际设置的值
+///
+/// 置顶窗口取消置顶或程序退出时还原为原来的样式
+#[tauri::command]
+pub fn set_window_opacity(
+    hwnd: isize,
+    alpha: f64,
+    backend: State<'_, WindowBackendState>,
+    registry: State<'_, Arc<PinRegistry>>,
+) -> Result<f64, String> {
+    pin_registry::set_opacity(backend.0.as_ref(), &registry, hwnd, alpha)
+        .map_err(|err| err.to_string())
+}
+
+/// 开启或关闭置顶窗口的鼠标穿透
+#[tauri::command]
+pub fn set_click_through(
+    hwnd: isize,
+    enable: bool,
+    backend: State<'_, WindowBackendState>,
+    registry: State<'_, Arc<PinRegistry>>,
+) -> Result<(), String> {
+    pin_registry::set_click_through(backend.0.as_ref(), &registry, hwnd, enable)
+        .map_err(|err| err.to_string())
+}
+
+/// 关闭所有窗口的鼠标穿透
+#[tauri::command]
+pub fn clear_click_through(app: tauri::AppHandle) -> Result<(), String> {
+    app_tool::clear_click_through(&app).map_err(|err| err.to_string())
+}
+
+/// 取消定时，窗口保持置顶
+#[tauri::command]
+pub fn cancel_pin_timer(hwnd: isize, registry: State<'_, Arc<PinRegistry>>) -> Result<(), String> {
+    registry
+        .set_expiry(hwnd, None)
+        .map_err(|err| err.to_string())
+}
+
+/// 延长定时置顶，返回新的到期时间
+#[tauri::command]
+pub fn extend_pin_timer(
+    hwnd: isize,
+    secs: u64,
+    registry: State<'_, Arc<PinRegistry>>,
+) -> Result<u64, String> {
+    registry
+        .extend_expiry(hwnd, secs)
+        .map_err(|err| err.to_string())
+}
+
+/// 本程序置顶的所有窗口
+#[tauri::command]
+pub fn list_pins(registry: State<'_, Arc<PinRegistry>>) -> Vec<PinInfo> {
+    registry.list()
+}
+
+/// 取消本程序做出的所有置顶，窗口还原为置顶前的状态
+#[tauri::command]
+pub fn unpin_all(
+    backend: State<'_, WindowBackendState>,
+    registry: State<'_, Arc<PinRegistry>>,
+) -> Result<(), String> {
+    pin_registry::unpin_all(backend.0.as_ref(), &registry).map_err(|err| err.to_string())
+}
+
+/// 取消某个进程所有窗口的置顶
+#[tauri::command]
+pub fn unpin_by_process(
+    pid: u32,
+    backend: State<'_, WindowBackendState>,
+    registry: State<'_, Arc<PinRegistry>>,
+) -> Result<(), String> {
+    let pins = registry.take_by_process(pid);
+    pin_registry::restore_pins(backend.0.as_ref(), &pins).map_err(|err| err.to_string())
+}
+
+/// 隐藏主窗口后点击选择一个窗口，`pin` 为 true 时直接置顶，按 Esc 取消时返回 None
+#[tauri::command]
+pub async fn pick_window(app: tauri::AppHandle, pin: bool) -> Result<Option<WindowInfo>, String> {
+    //拾取会一直等到用户点击，不能占用命令线程
+    tauri::async_runtime::spawn_blocking(move || app_tool::pick_window(&app, pin))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn get_backend_capabilities(backend: State<'_, WindowBackendState>) -> BackendCapabilities {
+    backend.0.capabilities()
 }
 
+/// 订阅窗口的新增、关闭和变化事件，每个页面只保留最新的订阅，页面刷新或重新挂载后替换旧的订阅
 #[tauri::command]
-pub fn cancel_all_topmost(hwnd_list: Vec<isize>) {
-    win_app_tool::cancel_all_topmost(hwnd_list);
+pub fn watch_windows(
+    webview: tauri::Webview,
+    watcher: State<'_, Arc<WindowWatcher>>,
+    registry: State<'_, Arc<PinRegistry>>,
+    on_event: Channel<WindowChangeEvent>,
+) {
+    let registry = registry.inner().clone();
+    watcher.subscribe_as(webview.label(), move |event| {
+        let mut event = event.clone();
+        if let WindowChangeEvent::Added { window } | WindowChangeEvent::Changed { window } =
+            &mut event
+        {
+            registry.decorate(window);
+        }
+        on_event.send(event).is_ok()
+    });
 }
@@ -1,27 +1,55 @@
 #![allow(dead_code)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 mod constant;
+#[cfg(feature = "webview")]
 mod controller;
 mod tool;
 extern crate dotenv;
+use crate::tool::cli_tool;
+#[cfg(feature = "webview")]
 use crate::tool::{
-    app_tool::{self, AppExitType, AppSettings},
-    update_tool,
+    app_tool::{self, AppExitType, AppSettings, PinExitPolicy},
+    border_overlay::BorderOverlay,
+    error_tool, hotkey_tool, ipc_tool,
+    layout_profile::LayoutStore,
+    pin_registry::{self, PinRegistry, PinWatchdog},
+    rpc_tool::{self, EventHub},
+    rule_tool::RuleEngine,
+    tray_tool, update_tool,
+    window_backend::{self, WindowBackendState},
+    window_watcher::WindowWatcher,
+};
+#[cfg(feature = "webview")]
+use controller::{
+    app_ctr, hotkey_ctr, icon_ctr, layout_ctr, rpc_ctr, rule_ctr, update_ctr, win_app_ctr,
 };
-use anyhow::Context;
-use controller::{app_ctr, update_ctr, win_app_ctr};
 use dotenv::dotenv;
 use std::env;
-use std::sync::Mutex;
-use tauri::{
-    menu::{Menu, MenuItem},
-    tray::{MouseButton, TrayIconBuilder, TrayIconEvent},
-    Manager,
-};
+#[cfg(feature = "webview")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "webview")]
+use tauri::{Emitter, Manager};
+#[cfg(feature = "webview")]
 use tauri_plugin_autostart::{MacosLauncher, ManagerExt};
-use tauri_utils::config::WindowConfig;
-use windows::Win32::Foundation::HWND;
 
+/// 带子命令启动时作为命令行客户端运行，返回退出码；没有子命令时返回 None
+pub fn run_cli() -> Option<i32> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    cli_tool::run(&args)
+}
+
+/// 不带界面编译时只运行窗口管理核心，通过命令行和控制接口使用
+#[cfg(not(feature = "webview"))]
+pub async fn run() {
+    dotenv().ok();
+
+    if let Err(e) = tool::daemon_tool::run().await {
+        eprintln!("程序启动失败：{:#}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "webview")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub async fn run() {
     dotenv().ok();
@@ -32,7 +60,10 @@ pub async fn run() {
     {
         builder = builder.plugin(tauri_plugin_single_instance::init(|app, _, _| {
             let result: anyhow::Result<()> = (|| {
-                app_tool::get_window_by_label(app, constant::APP_MAIN_WINDOW_LABEL)?.set_focus()?;
+                //无界面启动时没有主窗口
+                if let Some(window) = app.get_webview_window(constant::APP_MAIN_WINDOW_LABEL) {
+                    window.set_focus()?;
+                }
                 Ok(())
             })();
             if let Err(e) = result {
@@ -48,26 +79,149 @@ pub async fn run() {
             Some(vec!["--flag1", "--flag2"]),
         ))
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .register_asynchronous_uri_scheme_protocol(
+            icon_ctr::ICON_PROTOCOL,
+            |ctx, request, responder| {
+                let app = ctx.app_handle().clone();
+                //提取图标可能较慢，放到独立线程中，避免阻塞 webview
+                std::thread::spawn(move || {
+                    responder.respond(icon_ctr::icon_protocol(&app, &request));
+                });
+            },
+        )
         .invoke_handler(tauri::generate_handler![
             app_ctr::minimize_window,
             app_ctr::close_window,
             app_ctr::destroy_window,
             app_ctr::get_app_setting,
             app_ctr::save_app_setting,
+            hotkey_ctr::list_hotkeys,
+            hotkey_ctr::rebind_hotkey,
+            hotkey_ctr::reset_hotkeys,
+            hotkey_ctr::register_hotkeys,
             update_ctr::fetch_update,
             update_ctr::install_update,
             win_app_ctr::list_windows,
             win_app_ctr::toggle_topmost,
-            win_app_ctr::cancel_all_topmost
+            win_app_ctr::cancel_all_topmost,
+            win_app_ctr::list_pins,
+            win_app_ctr::unpin_all,
+            win_app_ctr::unpin_by_process,
+            win_app_ctr::set_window_opacity,
+            win_app_ctr::set_click_through,
+            win_app_ctr::clear_click_through,
+            win_app_ctr::cancel_pin_timer,
+            win_app_ctr::extend_pin_timer,
+            win_app_ctr::pick_window,
+            win_app_ctr::get_backend_capabilities,
+            win_app_ctr::watch_windows,
+            rule_ctr::list_rules,
+            rule_ctr::add_rule,
+            rule_ctr::update_rule,
+            rule_ctr::delete_rule,
+            layout_ctr::list_layouts,
+            layout_ctr::save_layout,
+            layout_ctr::apply_layout,
+            layout_ctr::delete_layout,
+            layout_ctr::rename_layout
         ])
         .setup(|app| {
             let result: anyhow::Result<()> = (|| {
+                //有界面的实例由单实例插件处理，端点仍有响应说明无界面的实例在运行
+                if ipc_tool::is_serving(cli_tool::CLI_ENDPOINT) {
+                    return Err(anyhow::anyhow!("本程序已在后台运行（无界面）"));
+                }
                 #[cfg(desktop)]
                 app.manage(update_tool::PendingUpdate(Mutex::new(None)));
+                //后台线程中的错误推送给前端提示
+                let error_app = app.handle().clone();
+                error_tool::set_error_sink(move |message| {
+                    app_tool::send_error_to_frontend(&error_app, anyhow::anyhow!(message));
+                });
+
+                //无界面启动时不创建主窗口，托盘、规则和命令行/控制接口照常运行
+                let headless = env::args().any(|arg| arg == cli_tool::HEADLESS_ARG);
+                //主窗口要先于窗口监视创建，枚举窗口时才能排除自身
+                let main_window = if headless {
+                    None
+                } else {
+                    Some(app_tool::create_main_window(app.handle())?)
+                };
 
                 let setting = app_tool::load_setting(app.handle())?;
                 app.manage(Mutex::new(setting.clone()));
 
+                let icon_cache = Arc::new(app_tool::create_icon_cache(app.handle(), &setting));
+                let backend = window_backend::create_backend(icon_cache.clone())?;
+                app.manage(icon_cache);
+                //无界面启动时为空，从托盘创建主窗口后由 show_main_window 更新窗口监视
+                let hwnd_self =
+                    app_tool::get_self_window_id(app.handle(), constant::APP_MAIN_WINDOW_LABEL);
+                let watcher = WindowWatcher::start(backend.clone(), hwnd_self);
+                //上次运行异常退出时遗留的置顶，按退出策略还原
+                let journal_path = app_tool::pin_journal_path(app.handle())?;
+                let restore = setting.pin_exit_policy == PinExitPolicy::Restore;
+                if let Err(e) =
+                    pin_registry::recover_journal(backend.as_ref(), &journal_path, restore)
+                {
+                    error_tool::report_error(format!("还原上次的置顶失败：{}", e));
+                }
+                let registry = Arc::new(PinRegistry::with_journal(journal_path));
+                let rpc_enabled =
+                    setting.rpc_enabled || env::args().any(|arg| arg == cli_tool::RPC_ARG);
+                let rpc_hub = rpc_enabled.then(|| Arc::new(EventHub::default()));
+                let (app_handle, event_hub) = (app.handle().clone(), rpc_hub.clone());
+                let watchdog = PinWatchdog::start(
+                    backend.clone(),
+                    registry.clone(),
+                    &watcher,
+                    setting.reassert_policy,
+                    move |event| {
+                        let Ok(payload) = event.payload() else {
+                            return;
+                        };
+                        let _ = app_handle.emit(event.name(), &payload);
+                        if let Some(hub) = &event_hub {
+                            hub.publish(rpc_tool::TOPIC_PINS, event.name(), payload);
+                        }
+                    },
+                );
+                watchdog.set_suspend_on_fullscreen(setting.suspend_on_fullscreen);
+                let border_style = setting.pin_border.style().unwrap_or_else(|e| {
+                    eprintln!("置顶边框设置无效：{}", e);
+                    None
+                });
+                let border = BorderOverlay::start(backend.clone(), registry.clone(), border_style);
+                let rule_engine = Arc::new(RuleEngine::load(
+                    backend.clone(),
+                    registry.clone(),
+                    Some(app_tool::pin_rules_path(app.handle())?),
+                )?);
+                rule_engine.attach(&watcher);
+                //命令行客户端通过本机套接字把命令转发过来
+                let cli_app = app.handle().clone();
+                tauri::async_runtime::spawn(cli_tool::serve(
+                    backend.clone(),
+                    registry.clone(),
+                    move || app_tool::get_self_window_id(&cli_app, constant::APP_MAIN_WINDOW_LABEL),
+                ));
+                app.manage(rule_engine);
+                app.manage(LayoutStore::new(app_tool::layout_dir(app.handle())?));
+                app.manage(registry);
+                app.manage(watchdog);
+                app.manage(border);
+                app.manage(watcher);
+                app.manage(WindowBackendState(backend));
+                if let Some(hub) = rpc_hub {
+                    rpc_ctr::start_rpc_server(app.handle(), hub);
+                }
+
+                //此时前端还收不到错误，页面加载后会再注册一次并提示冲突
+                if let Err(e) = hotkey_tool::register_hotkeys(app.handle(), &setting) {
+                    eprintln!("注册全局快捷键失败：{:#}", e);
+                }
+
                 let _ = app
                     .handle()
                     .plugin(tauri_plugin_updater::Builder::new().build());
@@ -81,118 +235,15 @@ pub async fn run() {
                     let _ = autostart_manager.disable();
                 }
 
-                let main_window =
-                    app_tool::get_window_by_label(app.handle(), constant::APP_MAIN_WINDOW_LABEL)?;
-                //将窗口置于右下角 任务栏上方
-                let rect = app_tool::get_work_area_for_window(HWND(main_window.hwnd()?.0 as isize));
-                main_window.set_position(tauri::Position::Physical(
-                    tauri::PhysicalPosition::new(
-                        rect.right - constant::APP_WINDOW_SIZE.0 - 10,
-                        rect.bottom - constant::APP_WINDOW_SIZE.1 - 2,
-                    ),
-                ))?;
                 //配置文件先配置隐藏 等位置设置好后再显示 防止窗口闪烁
-                main_window.show()?;
+                //由命令行在后台拉起时不显示，从托盘打开
+                if let Some(main_window) = main_window {
+                    if !env::args().any(|arg| arg == cli_tool::HIDDEN_ARG) {
+                        main_window.show()?;
+                    }
+                }
                 //tray
-                let show_menu = MenuItem::with_id(app, "show", "显示", true, None::<&str>)?;
-                let setting_menu = MenuItem::with_id(app, "setting", "设置", true, None::<&str>)?;
-                let check_update_menu =
-                    MenuItem::with_id(app, "check_update", "检查更新", true, None::<&str>)?;
-                let restart_menu = MenuItem::with_id(app, "restart", "重启", true, None::<&str>)?;
-                let quit_menu = MenuItem::with_id(app, "quit", "退出", true, None::<&str>)?;
-                let menu = Menu::with_items(
-                    app,
-                    &[
-                        &show_menu,
-                        &setting_menu,
-                        &check_update_menu,
-                        &restart_menu,
-                        &quit_menu,
-                    ],
-                )?;
-
-                let _tray = TrayIconBuilder::new()
-                    .tooltip(constant::APP_DISPLAY_NAME)
-                    .icon(
-                        app.default_window_icon()
-                            .context("获取程序图标失败")?
-                            .clone(),
-                    )
-                    .menu(&menu)
-                    .show_menu_on_left_click(true)
-                    .on_menu_event(|app, event| {
-                        let result: anyhow::Result<()> = (|| {
-                            match event.id.as_ref() {
-                                "show" => {
-                                    let window = app_tool::get_window_by_label(
-                                        app,
-                                        constant::APP_MAIN_WINDOW_LABEL,
-                                    )?;
-                                    if window.is_minimized()? {
-                                        window.unminimize()?;
-                                    }
-                                    window.show()?;
-                                    window.set_focus()?;
-                                }
-                                "setting" => {
-                                    let mut config = WindowConfig::default();
-                                    config.title = "系统设置".to_string();
-                                    config.label = "setting".to_string();
-                                    config.url = tauri::WebviewUrl::App("/#/setting".into());
-                                    config.maximizable = false;
-                                    config.resizable = false;
-                                    config.width = 300.0;
-                                    config.height = 300.0;
-                                    config.visible = false;
-                                    config.decorations = false;
-                                    config.transparent = true;
-                                    config.always_on_top = true;
-                                    app_tool::show_window(app, config)?;
-                                }
-                                "check_update" => {
-                                    update_tool::check_update(app);
-                                }
-                                "restart" => {
-                                    app.restart();
-                                }
-                                "quit" => {
-                                    app_tool::close_all_and_exit(app);
-                                }
-                                _ => {}
-                            }
-                            Ok(())
-                        })();
-                        if let Err(e) = result {
-                            app_tool::send_error_to_frontend(app, e);
-                        }
-                    })
-                    .on_tray_icon_event(|tray, event| {
-                        let app = tray.app_handle();
-                        match event {
-                            TrayIconEvent::DoubleClick {
-                                button: MouseButton::Left,
-                                ..
-                            } => {
-                                if let Some(window) = app.get_webview_window("main") {
-                                    if let Ok(hide) = window.is_visible() {
-                                        if !hide {
-                                            let _ = window.show();
-                                        }
-                                    }
-                                    if let Ok(min) = window.is_minimized() {
-                                        if min {
-                                            let _ = window.unminimize();
-                                        }
-                                    }
-                                    let _ = window.set_focus();
-                                } else {
-                                }
-                            }
-                            _ => {}
-                        }
-                        tauri_plugin_positioner::on_tray_event(app, &event);
-                    })
-                    .build(app)?;
+                tray_tool::create_tray(app.handle())?;
                 Ok(())
             })();
 
@@ -3,5 +3,9 @@
 
 #[tokio::main]
 async fn main() {
+    //带子命令时作为命令行客户端，把命令转发给正在运行的实例后退出
+    if let Some(code) = top_set_tool_lib::run_cli() {
+        std::process::exit(code);
+    }
     top_set_tool_lib::run().await;
 }
@@ -1,37 +1,29 @@
 #![allow(dead_code)]
+use crate::constant;
+use crate::tool::border_overlay::BorderOverlay;
+use crate::tool::error_tool;
+use crate::tool::icon_cache::{IconCache, ICON_CACHE_CAPACITY};
+use crate::tool::pin_registry::{self, PinRegistry};
+use crate::tool::setting_tool;
+pub use crate::tool::setting_tool::{AppExitType, AppSettings, PinExitPolicy};
+use crate::tool::window_backend::{self, WindowBackendState, WindowInfo};
+use crate::tool::window_watcher::WindowWatcher;
 use anyhow::Context;
+use std::sync::{Arc, Mutex};
 use tauri::menu::Menu;
 use tauri::{AppHandle, Manager};
 use tauri::{Emitter, Monitor};
 use tauri_plugin_autostart::ManagerExt;
 use tauri_plugin_positioner::{Position, WindowExt};
 use tauri_utils::config::WindowConfig;
+#[cfg(target_os = "windows")]
 use windows::Win32::Foundation::{HWND, RECT};
+#[cfg(target_os = "windows")]
 use windows::Win32::Graphics::Gdi::{
     GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTONEAREST,
 };
 
-const APP_SETTING_FILE: &str = ".app_settings.json";
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
-pub enum AppExitType {
-    Exit,
-    Minimize,
-}
-
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct AppSettings {
-    pub auto_start: bool,
-    pub app_exit_type: AppExitType,
-}
-
-impl Default for AppSettings {
-    fn default() -> Self {
-        AppSettings {
-            auto_start: false,
-            app_exit_type: AppExitType::Minimize,
-        }
-    }
-}
+const ICON_CACHE_DIR: &str = "icons";
 
 pub fn get_screen_info(app: &AppHandle) -> Option<Monitor> {
     let window = app.get_webview_window("main")?;
@@ -53,6 +45,7 @@ pub fn set_auto_start(app: &AppHandle, enable: bool) {
     }
 }
 
+#[cfg(target_os = "windows")]
 pub fn get_work_area_for_window(hwnd: HWND) -> RECT {
     unsafe {
         let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
@@ -67,6 +60,24 @@ pub fn get_work_area_for_window(hwnd: HWND) -> RECT {
     }
 }
 
+/// 获取窗口所在显示器工作区（不含任务栏）的右下角坐标
+pub fn get_work_area_bottom_right(window: &tauri::WebviewWindow) -> anyhow::Result<(i32, i32)> {
+    #[cfg(target_os = "windows")]
+    {
+        let rect = get_work_area_for_window(HWND(window.hwnd()?.0 as isize));
+        Ok((rect.right, rect.bottom))
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let monitor = window.current_monitor()?.context("获取显示器失败")?;
+        let area = monitor.work_area();
+        Ok((
+            area.position.x + area.size.width as i32,
+            area.position.y + area.size.height as i32,
+        ))
+    }
+}
+
 pub fn send_error_to_frontend(app: &AppHandle, err: anyhow::Error) {
     let _ = app.emit("sys_error", err.to_string());
 }
@@ -76,6 +87,102 @@ pub fn get_window_by_label(app: &AppHandle, label: &str) -> anyhow::Result<tauri
     Ok(window)
 }
 
+/// 获取本程序窗口在窗口后端中的标识，用于枚举时排除自身
+pub fn get_self_window_id(app: &AppHandle, label: &str) -> Option<isize> {
+    let window = app.get_webview_window(label)?;
+    #[cfg(target_os = "windows")]
+    {
+        window.hwnd().ok().map(|hwnd| hwnd.0 as isize)
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = window;
+        None
+    }
+}
+
+/// 按配置文件创建主窗口并放到右下角任务栏上方，创建后不显示
+///
+/// 主窗口配置为不自动创建，以 `--headless` 启动时从托盘打开才创建
+pub fn create_main_window(app: &AppHandle) -> anyhow::Result<tauri::WebviewWindow> {
+    let config = app
+        .config()
+        .app
+        .windows
+        .iter()
+        .find(|config| config.label == constant::APP_MAIN_WINDOW_LABEL)
+        .context("找不到主窗口配置")?;
+    let main_window = tauri::WebviewWindowBuilder::from_config(app, config)?.build()?;
+    //将窗口置于右下角 任务栏上方
+    let (right, bottom) = get_work_area_bottom_right(&main_window)?;
+    main_window.set_position(tauri::Position::Physical(tauri::PhysicalPosition::new(
+        right - constant::APP_WINDOW_SIZE.0 - 10,
+        bottom - constant::APP_WINDOW_SIZE.1 - 2,
+    )))?;
+    Ok(main_window)
+}
+
+/// 显示主窗口，还没有创建时先创建
+pub fn show_main_window(app: &AppHandle) -> anyhow::Result<()> {
+    let window = match app.get_webview_window(constant::APP_MAIN_WINDOW_LABEL) {
+        Some(window) => window,
+        None => {
+            let window = create_main_window(app)?;
+            //无界面启动时窗口监视开始时还没有主窗口，创建后不再列出
+            if let Some(watcher) = app.try_state::<Arc<WindowWatcher>>() {
+                watcher.set_exclude(get_self_window_id(app, constant::APP_MAIN_WINDOW_LABEL));
+            }
+            window
+        }
+    };
+    if window.is_minimized()? {
+        window.unminimize()?;
+    }
+    window.show()?;
+    window.set_focus()?;
+    Ok(())
+}
+
+/// 隐藏主窗口后等待用户点击选择窗口，`pin` 为 true 时直接置顶选中的窗口，按 Esc 取消时返回 None
+pub fn pick_window(app: &AppHandle, pin: bool) -> anyhow::Result<Option<WindowInfo>> {
+    //无界面启动时主窗口可能还没有创建
+    let main_window = app
+        .get_webview_window(constant::APP_MAIN_WINDOW_LABEL)
+        .filter(|window| window.is_visible().unwrap_or(false));
+    //主窗口挡在目标窗口上方时无法点到目标
+    if let Some(main_window) = &main_window {
+        main_window.hide()?;
+    }
+    let hwnd_self = get_self_window_id(app, constant::APP_MAIN_WINDOW_LABEL);
+    let backend = app.state::<WindowBackendState>();
+    let backend = backend.0.as_ref();
+    let registry = app.state::<Arc<PinRegistry>>();
+    let result = window_backend::pick_window(backend, hwnd_self).and_then(|picked| {
+        let Some(mut window) = picked else {
+            return Ok(None);
+        };
+        if pin && !window.is_top_most {
+            pin_registry::set_pinned(backend, &registry, window.hwnd, true, None, hwnd_self)?;
+            window = backend.window_info(window.hwnd).unwrap_or(window);
+        }
+        registry.decorate(&mut window);
+        Ok(Some(window))
+    });
+    if let Some(main_window) = &main_window {
+        main_window.show()?;
+    }
+    result
+}
+
+/// 关闭所有窗口的鼠标穿透，通知前端关闭的窗口数
+pub fn clear_click_through(app: &AppHandle) -> anyhow::Result<()> {
+    let backend = app.state::<WindowBackendState>();
+    let registry = app.state::<Arc<PinRegistry>>();
+    let count = pin_registry::clear_click_through(backend.0.as_ref(), &registry)?;
+    let _ = app.emit("click_through_cleared", count);
+    Ok(())
+}
+
 pub fn show_window(app: &AppHandle, config: WindowConfig) -> anyhow::Result<()> {
     if let Some(window) = app.get_webview_window(&config.label) {
         if window.is_minimized()? {
@@ -114,35 +221,65 @@ pub fn destroy_window(app: &AppHandle, label: &str) -> anyhow::Result<()> {
 }
 
 pub fn load_setting(app: &AppHandle) -> anyhow::Result<AppSettings> {
-    let app_dir = app.path().app_config_dir()?;
-    let setting_path = app_dir.join(APP_SETTING_FILE);
+    setting_tool::load_setting(&app.path().app_config_dir()?)
+}
 
-    if !app_dir.exists() {
-        std::fs::create_dir_all(&app_dir)?;
-    }
-    if !setting_path.exists() {
-        let setting = AppSettings::default();
-        save_setting(app, &setting)?;
-        return Ok(setting);
-    }
+pub fn save_setting(app: &AppHandle, settings: &AppSettings) -> anyhow::Result<()> {
+    setting_tool::save_setting(&app.path().app_config_dir()?, settings)
+}
 
-    let file = std::fs::File::open(setting_path)?;
-    let reader = std::io::BufReader::new(file);
-    let settings = serde_json::from_reader(reader)?;
-    Ok(settings)
+/// 自动置顶规则文件，与设置文件放在同一目录
+pub fn pin_rules_path(app: &AppHandle) -> anyhow::Result<std::path::PathBuf> {
+    let app_dir = app.path().app_config_dir()?;
+    Ok(app_dir.join(setting_tool::PIN_RULES_FILE))
 }
 
-pub fn save_setting(app: &AppHandle, settings: &AppSettings) -> anyhow::Result<()> {
+/// 窗口布局目录，每个布局一个文件
+pub fn layout_dir(app: &AppHandle) -> anyhow::Result<std::path::PathBuf> {
     let app_dir = app.path().app_config_dir()?;
-    let setting_path = app_dir.join(APP_SETTING_FILE);
+    Ok(app_dir.join(setting_tool::LAYOUT_DIR))
+}
 
-    let file = std::fs::File::create(setting_path)?;
-    serde_json::to_writer_pretty(file, settings)?;
+/// 置顶日志文件，记录当前生效的置顶，用于崩溃后还原
+pub fn pin_journal_path(app: &AppHandle) -> anyhow::Result<std::path::PathBuf> {
+    let app_dir = app.path().app_config_dir()?;
+    Ok(app_dir.join(setting_tool::PIN_JOURNAL_FILE))
+}
 
-    Ok(())
+/// 按退出策略处理本程序做出的置顶
+pub fn release_pins(app: &AppHandle) {
+    //系统边框的颜色会一直保留到窗口关闭，退出前先去掉所有边框
+    if let Some(border) = app.try_state::<Arc<BorderOverlay>>() {
+        border.set_style(None);
+    }
+    let (Some(setting), Some(backend), Some(registry)) = (
+        app.try_state::<Mutex<AppSettings>>(),
+        app.try_state::<WindowBackendState>(),
+        app.try_state::<Arc<PinRegistry>>(),
+    ) else {
+        return;
+    };
+    let restore = setting.lock().unwrap().pin_exit_policy == PinExitPolicy::Restore;
+    if let Err(e) = pin_registry::release_on_exit(backend.0.as_ref(), &registry, restore) {
+        error_tool::report_error(format!("还原置顶失败：{}", e));
+    }
+}
+
+pub fn create_icon_cache(app: &AppHandle, setting: &AppSettings) -> IconCache {
+    let disk_dir = if setting.icon_disk_cache {
+        app.path()
+            .app_cache_dir()
+            .ok()
+            .map(|dir| dir.join(ICON_CACHE_DIR))
+    } else {
+        None
+    };
+    IconCache::new(ICON_CACHE_CAPACITY, disk_dir)
 }
 
 pub fn close_all_and_exit(app: &tauri::AppHandle) {
+    release_pins(app);
+
     //关闭所有窗口
     for (_, window) in app.webview_windows() {
         let _ = window.close();
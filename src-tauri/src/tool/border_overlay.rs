@@ -0,0 +1,197 @@
+use crate::tool::error_tool;
+use crate::tool::pin_registry::PinRegistry;
+use crate::tool::window_backend::{BorderStyle, Rect, WindowBackend};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// 检查置顶窗口位置的间隔，窗口移动时边框跟随的延迟
+const BORDER_INTERVAL: Duration = Duration::from_millis(50);
+/// 边框的最大粗细（像素）
+pub const MAX_BORDER_THICKNESS: u32 = 16;
+
+/// 置顶窗口的高亮边框设置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BorderSetting {
+    pub enabled: bool,
+    /// 颜色，如 `#409EFF`
+    pub color: String,
+    /// 粗细（像素），Windows 11 使用系统边框时固定为 1
+    pub thickness: u32,
+}
+
+impl Default for BorderSetting {
+    fn default() -> Self {
+        BorderSetting {
+            enabled: false,
+            color: "#409EFF".to_string(),
+            thickness: 3,
+        }
+    }
+}
+
+impl BorderSetting {
+    /// 转换为后端使用的样式，未启用时返回 None
+    pub fn style(&self) -> anyhow::Result<Option<BorderStyle>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let hex = self.color.trim().trim_start_matches('#');
+        let color = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)
+            .ok_or_else(|| anyhow::anyhow!("边框颜色格式错误：{}", self.color))?;
+        Ok(Some(BorderStyle {
+            color,
+            thickness: self.thickness.clamp(1, MAX_BORDER_THICKNESS),
+        }))
+    }
+}
+
+/// 置顶窗口的高亮边框
+///
+/// 在后台线程中定时检查已登记的置顶窗口，给新置顶的窗口加上边框，窗口移动、缩放或最小化后重绘，
+/// 取消置顶或暂停置顶后去掉边框
+pub struct BorderOverlay {
+    backend: Arc<dyn WindowBackend>,
+    registry: Arc<PinRegistry>,
+    style: Mutex<Option<BorderStyle>>,
+    /// 边框样式被修改，唤醒因关闭边框而等待的后台线程
+    style_changed: Condvar,
+    /// 已绘制边框的窗口 → 绘制时窗口的区域
+    drawn: Mutex<HashMap<isize, Option<Rect>>>,
+}
+
+impl BorderOverlay {
+    pub fn new(
+        backend: Arc<dyn WindowBackend>,
+        registry: Arc<PinRegistry>,
+        style: Option<BorderStyle>,
+    ) -> Self {
+        BorderOverlay {
+            backend,
+            registry,
+            style: Mutex::new(style),
+            style_changed: Condvar::new(),
+            drawn: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 创建并在后台线程中启动，关闭边框期间线程挂起
+    pub fn start(
+        backend: Arc<dyn WindowBackend>,
+        registry: Arc<PinRegistry>,
+        style: Option<BorderStyle>,
+    ) -> Arc<Self> {
+        let overlay = Arc::new(BorderOverlay::new(backend, registry, style));
+        let worker = overlay.clone();
+        std::thread::spawn(move || loop {
+            worker.wait_enabled();
+            std::thread::sleep(BORDER_INTERVAL);
+            worker.sync();
+        });
+        overlay
+    }
+
+    /// 修改边框样式，为空表示关闭边框；已绘制的边框在下次检查时按新样式重绘
+    pub fn set_style(&self, style: Option<BorderStyle>) {
+        *self.style.lock().unwrap() = style;
+        self.style_changed.notify_all();
+        self.clear_all();
+    }
+
+    /// 阻塞到启用了边框为止
+    fn wait_enabled(&self) {
+        let style = self.style.lock().unwrap();
+        let _style = self
+            .style_changed
+            .wait_while(style, |style| style.is_none())
+            .unwrap();
+    }
+
+    /// 去掉所有边框，退出程序时调用
+    pub fn clear_all(&self) {
+        let mut drawn = self.drawn.lock().unwrap();
+        for hwnd in drawn.keys() {
+            let _ = self.backend.clear_border(*hwnd);
+        }
+        drawn.clear();
+    }
+
+    /// 按当前的置顶登记增删或重绘边框
+    pub fn sync(&self) {
+        let style = *self.style.lock().unwrap();
+        let targets: HashSet<isize> = match style {
+            Some(_) => self
+                .registry
+                .list()
+                .into_iter()
+                .filter(|pin| !pin.suspended)
+                .map(|pin| pin.hwnd)
+                .collect(),
+            None => HashSet::new(),
+        };
+        let mut drawn = self.drawn.lock().unwrap();
+        drawn.retain(|hwnd, _| {
+            targets.contains(hwnd) || {
+                let _ = self.backend.clear_border(*hwnd);
+                false
+            }
+        });
+        let Some(style) = style else {
+            return;
+        };
+        for hwnd in targets {
+            let rect = self.backend.window_rect(hwnd);
+            if drawn.get(&hwnd) == Some(&rect) {
+                continue;
+            }
+            //失败时同样记录区域，窗口不变时不再重试
+            if let Err(e) = self.backend.draw_border(hwnd, &style) {
+                error_tool::report_error(format!("绘制置顶边框失败：{}", e));
+            }
+            drawn.insert(hwnd, rect);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::fake_backend::FakeBackend;
+    use crate::tool::pin_registry::PinInfo;
+    use crate::tool::window_backend::WindowInfo;
+    use std::sync::mpsc;
+
+    #[test]
+    fn disabled_overlay_waits_for_style() {
+        let backend = Arc::new(FakeBackend::new(vec![WindowInfo {
+            hwnd: 1,
+            ..Default::default()
+        }]));
+        let registry = Arc::new(PinRegistry::new());
+        registry.pin(PinInfo::new(&backend.window(1).unwrap(), false));
+        let overlay = Arc::new(BorderOverlay::new(backend.clone(), registry, None));
+
+        let (tx, rx) = mpsc::channel();
+        let worker = overlay.clone();
+        std::thread::spawn(move || {
+            worker.wait_enabled();
+            let _ = tx.send(());
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        let style = BorderStyle {
+            color: 0x409EFF,
+            thickness: 3,
+        };
+        overlay.set_style(Some(style));
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        overlay.sync();
+        assert_eq!(backend.border(1), Some(style));
+        overlay.set_style(None);
+        assert_eq!(backend.border(1), None);
+    }
+}
@@ -0,0 +1,628 @@
+use crate::tool::error_tool;
+use crate::tool::ipc_tool::{self, IpcClient};
+use crate::tool::pin_registry::{self, PinInfo, PinRegistry};
+use crate::tool::rule_tool::glob_match;
+use crate::tool::window_backend::{self, WindowBackend, WindowInfo};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 命令行使用的进程间通信端点名称
+pub const CLI_ENDPOINT: &str = "cli";
+/// 由命令行拉起实例时传入的参数，实例启动后不显示主窗口
+pub const HIDDEN_ARG: &str = "--hidden";
+/// 无界面启动：不创建主窗口，只运行托盘、自动置顶规则和命令行/控制接口
+pub const HEADLESS_ARG: &str = "--headless";
+/// 开启本机控制接口，不修改设置；无界面编译时无法在设置中开启
+pub const RPC_ARG: &str = "--rpc";
+/// 拉起实例后等待其就绪的最长时间
+const START_TIMEOUT: Duration = Duration::from_secs(15);
+const START_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 退出码：成功
+pub const EXIT_OK: i32 = 0;
+/// 退出码：命令执行失败，如没有匹配的窗口
+pub const EXIT_FAILED: i32 = 1;
+/// 退出码：命令行参数错误
+pub const EXIT_USAGE: i32 = 2;
+/// 退出码：无法连接或拉起实例
+pub const EXIT_UNAVAILABLE: i32 = 3;
+
+pub const USAGE: &str = "用法：top-set-tool <命令> [选项]
+
+命令：
+  list                      列出所有窗口
+  pins                      列出本程序置顶的窗口
+  pin <筛选条件> [--for <秒>] [--opacity <0.2~1.0>]
+                            置顶匹配的窗口，--for 指定定时置顶的时长
+  unpin <筛选条件>          取消匹配窗口的置顶
+  unpin-all                 取消本程序做出的所有置顶
+  help                      显示本帮助
+
+筛选条件（可组合，都满足时匹配）：
+  --hwnd <句柄>             窗口句柄
+  --exe <程序名>            程序名，通配符，可带 .exe
+  --title <标题>            窗口标题，通配符
+  --class <类名>            窗口类名，通配符
+
+通用选项：
+  --json                    以 JSON 格式输出
+
+启动选项（不带命令时）：
+  --hidden                  启动后不显示主窗口
+  --headless                不创建主窗口，通过托盘、命令行或控制接口使用
+  --rpc                     本次运行开启本机控制接口
+
+没有正在运行的实例时会先在后台启动本程序。
+退出码：0 成功，1 执行失败，2 参数错误，3 无法连接到本程序";
+
+/// 转发给正在运行的实例执行的命令
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum CliCommand {
+    List,
+    Pins,
+    Pin {
+        filter: WindowFilter,
+        duration_secs: Option<u64>,
+        opacity: Option<f64>,
+    },
+    Unpin {
+        filter: WindowFilter,
+    },
+    UnpinAll,
+}
+
+/// 窗口筛选条件，填写了的条件都满足时匹配；程序名、标题和类名使用通配符，不区分大小写
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct WindowFilter {
+    pub hwnd: Option<isize>,
+    pub exe: Option<String>,
+    pub title: Option<String>,
+    pub class: Option<String>,
+}
+
+impl WindowFilter {
+    fn is_empty(&self) -> bool {
+        self.hwnd.is_none() && self.exe.is_none() && self.title.is_none() && self.class.is_none()
+    }
+
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        //Windows 下程序名不含 .exe，允许用户照着文件名填写
+        let exe_matches = |pattern: &str| {
+            let lower = pattern.to_lowercase();
+            let pattern = lower.strip_suffix(".exe").unwrap_or(&lower);
+            glob_match(pattern, &window.name)
+        };
+        self.hwnd.is_none_or(|hwnd| hwnd == window.hwnd)
+            && self.exe.as_deref().is_none_or(exe_matches)
+            && self
+                .title
+                .as_deref()
+                .is_none_or(|title| glob_match(title, &window.title))
+            && self
+                .class
+                .as_deref()
+                .is_none_or(|class| glob_match(class, &window.class))
+    }
+}
+
+/// 解析后的命令行
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliAction {
+    Help,
+    Run { command: CliCommand, json: bool },
+}
+
+/// 解析命令行参数（不含程序路径），没有子命令时返回 None，按正常方式启动程序
+pub fn parse(args: &[String]) -> anyhow::Result<Option<CliAction>> {
+    let Some(name) = args.first() else {
+        return Ok(None);
+    };
+    //--hidden 等以 - 开头的参数属于程序本身
+    if name.starts_with('-') && !matches!(name.as_str(), "-h" | "--help") {
+        return Ok(None);
+    }
+    let mut filter = WindowFilter::default();
+    let (mut json, mut duration_secs, mut opacity) = (false, None, None);
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || {
+            rest.next()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("{} 缺少参数值", arg))
+        };
+        match arg.as_str() {
+            "--json" => json = true,
+            "--hwnd" => filter.hwnd = Some(parse_value(arg, &value()?)?),
+            "--exe" => filter.exe = Some(value()?),
+            "--title" => filter.title = Some(value()?),
+            "--class" => filter.class = Some(value()?),
+            "--for" => duration_secs = Some(parse_value(arg, &value()?)?),
+            "--opacity" => opacity = Some(parse_value(arg, &value()?)?),
+            _ => return Err(anyhow::anyhow!("无法识别的参数：{}", arg)),
+        }
+    }
+    let filter_given = !filter.is_empty();
+    let command = match name.as_str() {
+        "help" | "-h" | "--help" => return Ok(Some(CliAction::Help)),
+        "list" => CliCommand::List,
+        "pins" => CliCommand::Pins,
+        "pin" => CliCommand::Pin {
+            filter,
+            duration_secs,
+            opacity,
+        },
+        "unpin" => CliCommand::Unpin { filter },
+        "unpin-all" => CliCommand::UnpinAll,
+        _ => return Err(anyhow::anyhow!("无法识别的命令：{}", name)),
+    };
+    match &command {
+        CliCommand::Pin { filter, .. } | CliCommand::Unpin { filter } if filter.is_empty() => {
+            return Err(anyhow::anyhow!("{} 至少需要一个筛选条件", name));
+        }
+        CliCommand::Pin { .. } => {}
+        _ if duration_secs.is_some() || opacity.is_some() => {
+            return Err(anyhow::anyhow!("--for 和 --opacity 只能用于 pin"));
+        }
+        CliCommand::Unpin { .. } => {}
+        _ if !filter_given => {}
+        _ => return Err(anyhow::anyhow!("{} 不支持筛选条件", name)),
+    }
+    Ok(Some(CliAction::Run { command, json }))
+}
+
+fn parse_value<T: std::str::FromStr>(arg: &str, value: &str) -> anyhow::Result<T> {
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("{} 的参数值无效：{}", arg, value))
+}
+
+/// 在正在运行的实例中执行命令，返回给命令行的数据
+pub fn execute(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    command: &CliCommand,
+    hwnd_self: Option<isize>,
+) -> anyhow::Result<Value> {
+    let matched = |filter: &WindowFilter| -> anyhow::Result<Vec<WindowInfo>> {
+        let windows: Vec<WindowInfo> = window_backend::list_windows(backend, hwnd_self)
+            .into_iter()
+            .filter(|window| filter.matches(window))
+            .collect();
+        if windows.is_empty() {
+            return Err(anyhow::anyhow!("没有匹配的窗口"));
+        }
+        Ok(windows)
+    };
+    //操作后重新读取窗口，返回最新的置顶状态
+    let refreshed = |windows: Vec<WindowInfo>| -> Vec<WindowInfo> {
+        windows
+            .into_iter()
+            .map(|window| {
+                let mut window = backend.window_info(window.hwnd).unwrap_or(window);
+                registry.decorate(&mut window);
+                window
+            })
+            .collect()
+    };
+    let data = match command {
+        CliCommand::List => {
+            let mut windows = window_backend::list_windows(backend, hwnd_self);
+            for window in &mut windows {
+                registry.decorate(window);
+            }
+            serde_json::to_value(windows)?
+        }
+        CliCommand::Pins => serde_json::to_value(registry.list())?,
+        CliCommand::Pin {
+            filter,
+            duration_secs,
+            opacity,
+        } => {
+            let windows = matched(filter)?;
+            let expires_at = duration_secs
+                .map(|secs| pin_registry::millis_after(pin_registry::now_millis(), secs));
+            for window in &windows {
+                pin_registry::set_pinned(
+                    backend,
+                    registry,
+                    window.hwnd,
+                    true,
+                    expires_at,
+                    hwnd_self,
+                )?;
+                if let Some(opacity) = opacity {
+                    pin_registry::set_opacity(backend, registry, window.hwnd, *opacity)?;
+                }
+            }
+            serde_json::to_value(refreshed(windows))?
+        }
+        CliCommand::Unpin { filter } => {
+            let windows: Vec<WindowInfo> = matched(filter)?
+                .into_iter()
+                .filter(|window| window.is_top_most)
+                .collect();
+            for window in &windows {
+                pin_registry::set_pinned(backend, registry, window.hwnd, false, None, hwnd_self)?;
+            }
+            serde_json::to_value(refreshed(windows))?
+        }
+        CliCommand::UnpinAll => {
+            let count = registry.hwnds().len();
+            pin_registry::unpin_all(backend, registry)?;
+            serde_json::to_value(count)?
+        }
+    };
+    Ok(data)
+}
+
+/// 处理命令行客户端的请求，响应为 `{"Ok": 数据}` 或 `{"Err": 错误信息}`
+pub fn handle_request(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    request: &str,
+    hwnd_self: Option<isize>,
+) -> Value {
+    let result: Result<Value, String> = serde_json::from_str::<CliCommand>(request)
+        .map_err(|e| format!("无法识别的命令：{}", e))
+        .and_then(|command| {
+            execute(backend, registry, &command, hwnd_self).map_err(|e| e.to_string())
+        });
+    serde_json::to_value(result).unwrap_or_default()
+}
+
+/// 接受命令行客户端的连接，在本实例中执行转发过来的命令
+///
+/// `hwnd_self` 在每条命令执行时获取本程序的主窗口，无界面启动时主窗口之后才创建
+pub async fn serve<F>(backend: Arc<dyn WindowBackend>, registry: Arc<PinRegistry>, hwnd_self: F)
+where
+    F: Fn() -> Option<isize> + Send + Sync + 'static,
+{
+    let result = ipc_tool::serve(CLI_ENDPOINT, move |request, _| {
+        Some(handle_request(
+            backend.as_ref(),
+            &registry,
+            request,
+            hwnd_self(),
+        ))
+    })
+    .await;
+    if let Err(e) = result {
+        error_tool::report_error(format!("命令行服务启动失败：{:#}", e));
+    }
+}
+
+/// 命令行入口，`args` 不含程序路径；不是命令行调用时返回 None，否则返回退出码
+pub fn run(args: &[String]) -> Option<i32> {
+    let action = match parse(args) {
+        Ok(None) => return None,
+        Ok(Some(action)) => action,
+        Err(e) => {
+            attach_console();
+            eprintln!("{}\n\n{}", e, USAGE);
+            return Some(EXIT_USAGE);
+        }
+    };
+    attach_console();
+    let CliAction::Run { command, json } = action else {
+        println!("{}", USAGE);
+        return Some(EXIT_OK);
+    };
+    let mut client = match connect_or_start() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("无法连接到本程序：{:#}", e);
+            return Some(EXIT_UNAVAILABLE);
+        }
+    };
+    let (code, text) = outcome(&command, json, client.call(&command));
+    if code == EXIT_OK {
+        println!("{}", text);
+    } else {
+        eprintln!("{}", text);
+    }
+    Some(code)
+}
+
+/// 把实例的响应转换为退出码和输出的文本，退出码不为 0 时文本是错误信息
+fn outcome(
+    command: &CliCommand,
+    json: bool,
+    response: anyhow::Result<Result<Value, String>>,
+) -> (i32, String) {
+    match response {
+        Ok(Ok(data)) if json => (
+            EXIT_OK,
+            serde_json::to_string_pretty(&data).unwrap_or_default(),
+        ),
+        Ok(Ok(data)) => match format_output(command, data) {
+            Ok(text) => (EXIT_OK, text),
+            Err(e) => (EXIT_FAILED, format!("无法解析本程序的响应：{}", e)),
+        },
+        Ok(Err(e)) => (EXIT_FAILED, e),
+        Err(e) => (EXIT_UNAVAILABLE, format!("与本程序通信失败：{:#}", e)),
+    }
+}
+
+/// Windows 下发布版没有控制台，附加到启动本程序的控制台才能输出结果
+fn attach_console() {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// 连接到正在运行的实例，没有实例时在后台启动本程序并等待其就绪
+fn connect_or_start() -> anyhow::Result<IpcClient> {
+    match IpcClient::connect(CLI_ENDPOINT) {
+        Ok(client) => return Ok(client),
+        Err(e)
+            if !matches!(
+                e.kind(),
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Err(e.into());
+        }
+        Err(_) => {}
+    }
+    start_instance()?;
+    let started = Instant::now();
+    loop {
+        std::thread::sleep(START_POLL_INTERVAL);
+        match IpcClient::connect(CLI_ENDPOINT) {
+            Ok(client) => return Ok(client),
+            Err(e) if started.elapsed() > START_TIMEOUT => {
+                return Err(anyhow::anyhow!("等待本程序启动超时：{}", e));
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+fn start_instance() -> anyhow::Result<()> {
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .arg(HIDDEN_ARG)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        command.creation_flags(DETACHED_PROCESS);
+    }
+    //放到独立的进程组，关闭终端时不会连带退出
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command.spawn()?;
+    Ok(())
+}
+
+/// 把实例返回的数据格式化为文本
+fn format_output(command: &CliCommand, data: Value) -> anyhow::Result<String> {
+    let window_line = |window: &WindowInfo| {
+        format!(
+            "{}\t{}\t{}\t{}",
+            window.hwnd,
+            if window.is_top_most { "置顶" } else { "-" },
+            window.name,
+            window.title
+        )
+    };
+    let text = match command {
+        CliCommand::List => {
+            let windows: Vec<WindowInfo> = serde_json::from_value(data)?;
+            windows
+                .iter()
+                .map(window_line)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        CliCommand::Pins => {
+            let pins: Vec<PinInfo> = serde_json::from_value(data)?;
+            pins.iter()
+                .map(|pin| format!("{}\t{}\t{}", pin.hwnd, pin.exe, pin.title))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        CliCommand::Pin { .. } | CliCommand::Unpin { .. } => {
+            let windows: Vec<WindowInfo> = serde_json::from_value(data)?;
+            let action = if matches!(command, CliCommand::Pin { .. }) {
+                "置顶"
+            } else {
+                "取消置顶"
+            };
+            let mut lines = vec![format!("已{} {} 个窗口", action, windows.len())];
+            lines.extend(windows.iter().map(window_line));
+            lines.join("\n")
+        }
+        CliCommand::UnpinAll => {
+            let count: usize = serde_json::from_value(data)?;
+            format!("已取消 {} 个窗口的置顶", count)
+        }
+    };
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::fake_backend::FakeBackend;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn window(hwnd: isize, name: &str, title: &str, topmost: bool) -> WindowInfo {
+        WindowInfo {
+            hwnd,
+            pid: hwnd as u32,
+            name: name.to_string(),
+            title: title.to_string(),
+            class: format!("{}Class", name),
+            is_top_most: topmost,
+            ..Default::default()
+        }
+    }
+
+    fn run_command(line: &str) -> CliCommand {
+        match parse(&args(line)).unwrap() {
+            Some(CliAction::Run { command, .. }) => command,
+            action => panic!("{} 解析为 {:?}", line, action),
+        }
+    }
+
+    #[test]
+    fn parse_commands_and_options() {
+        assert_eq!(parse(&[]).unwrap(), None);
+        assert_eq!(parse(&args("--hidden")).unwrap(), None);
+        assert_eq!(parse(&args("--help")).unwrap(), Some(CliAction::Help));
+        assert_eq!(
+            parse(&args("list --json")).unwrap(),
+            Some(CliAction::Run {
+                command: CliCommand::List,
+                json: true
+            })
+        );
+        assert_eq!(
+            run_command("pin --exe notepad.exe --hwnd 42 --for 600 --opacity 0.8"),
+            CliCommand::Pin {
+                filter: WindowFilter {
+                    hwnd: Some(42),
+                    exe: Some("notepad.exe".to_string()),
+                    ..Default::default()
+                },
+                duration_secs: Some(600),
+                opacity: Some(0.8),
+            }
+        );
+        assert_eq!(run_command("unpin-all"), CliCommand::UnpinAll);
+        for line in [
+            "bogus",
+            "list --bogus",
+            "pin",
+            "pin --exe",
+            "pin --hwnd abc",
+            "unpin --title a --for 10",
+            "list --exe a",
+            "pins --opacity 0.5",
+        ] {
+            assert!(parse(&args(line)).is_err(), "{} 应当报错", line);
+        }
+    }
+
+    #[test]
+    fn filter_matches_all_given_conditions() {
+        let notepad = window(1, "notepad", "readme.md - Notepad", false);
+        let filter = |exe: Option<&str>, title: Option<&str>, class: Option<&str>| WindowFilter {
+            exe: exe.map(String::from),
+            title: title.map(String::from),
+            class: class.map(String::from),
+            ..Default::default()
+        };
+        assert!(WindowFilter::default().matches(&notepad));
+        assert!(filter(Some("Notepad.EXE"), None, None).matches(&notepad));
+        assert!(filter(Some("note*"), Some("*.md*"), Some("notepadclass")).matches(&notepad));
+        assert!(!filter(Some("notepad"), Some("*.txt*"), None).matches(&notepad));
+        assert!(!filter(None, None, Some("other")).matches(&notepad));
+        let by_hwnd = WindowFilter {
+            hwnd: Some(2),
+            ..Default::default()
+        };
+        assert!(!by_hwnd.matches(&notepad));
+    }
+
+    #[test]
+    fn execute_pins_and_unpins_matching_windows() {
+        let backend = FakeBackend::new(vec![
+            window(1, "code", "a.md", false),
+            window(2, "code", "b.rs", false),
+            window(3, "notepad", "c.md", false),
+        ]);
+        let registry = PinRegistry::new();
+        let pin = run_command("pin --title *.md --opacity 0.5");
+        let data = execute(&backend, &registry, &pin, None).unwrap();
+        let pinned: Vec<WindowInfo> = serde_json::from_value(data).unwrap();
+        assert_eq!(pinned.iter().map(|w| w.hwnd).collect::<Vec<_>>(), [1, 3]);
+        assert!(pinned.iter().all(|w| w.is_top_most));
+        assert!(backend.is_topmost(1) && !backend.is_topmost(2) && backend.is_topmost(3));
+        assert_eq!(backend.opacity(3), Some(0.5));
+
+        let pins = execute(&backend, &registry, &CliCommand::Pins, None).unwrap();
+        assert_eq!(pins.as_array().unwrap().len(), 2);
+
+        //只取消已置顶的窗口
+        let unpin = run_command("unpin --exe code");
+        let data = execute(&backend, &registry, &unpin, None).unwrap();
+        let unpinned: Vec<WindowInfo> = serde_json::from_value(data).unwrap();
+        assert_eq!(unpinned.len(), 1);
+        assert!(!backend.is_topmost(1) && !registry.is_pinned(1));
+
+        let err = execute(&backend, &registry, &run_command("pin --exe vim"), None).unwrap_err();
+        assert_eq!(err.to_string(), "没有匹配的窗口");
+
+        let data = execute(&backend, &registry, &CliCommand::UnpinAll, None).unwrap();
+        assert_eq!(data, serde_json::json!(1));
+        assert!(!backend.is_topmost(3) && registry.list().is_empty());
+    }
+
+    #[test]
+    fn execute_with_timer_sets_expiry() {
+        let backend = FakeBackend::new(vec![window(1, "code", "a.md", false)]);
+        let registry = PinRegistry::new();
+        let pin = run_command("pin --hwnd 1 --for 60");
+        execute(&backend, &registry, &pin, None).unwrap();
+        let expires_at = registry.list()[0].expires_at.unwrap();
+        assert!(expires_at > pin_registry::now_millis());
+    }
+
+    #[test]
+    fn format_output_as_text() {
+        let windows = vec![
+            window(1, "code", "a.md", true),
+            window(2, "notepad", "b", false),
+        ];
+        let data = serde_json::to_value(&windows).unwrap();
+        assert_eq!(
+            format_output(&CliCommand::List, data.clone()).unwrap(),
+            "1\t置顶\tcode\ta.md\n2\t-\tnotepad\tb"
+        );
+        let pin = run_command("pin --exe code");
+        assert_eq!(
+            format_output(&pin, data).unwrap(),
+            "已置顶 2 个窗口\n1\t置顶\tcode\ta.md\n2\t-\tnotepad\tb"
+        );
+        assert_eq!(
+            format_output(&CliCommand::UnpinAll, serde_json::json!(3)).unwrap(),
+            "已取消 3 个窗口的置顶"
+        );
+        assert!(format_output(&CliCommand::List, serde_json::json!("x")).is_err());
+    }
+
+    #[test]
+    fn exit_codes() {
+        //参数错误在连接实例之前返回
+        assert_eq!(run(&args("bogus")), Some(EXIT_USAGE));
+        assert_eq!(run(&args("help")), Some(EXIT_OK));
+        assert_eq!(run(&args("--hidden")), None);
+
+        let list = CliCommand::List;
+        let (code, text) = outcome(&list, true, Ok(Ok(serde_json::json!([]))));
+        assert_eq!((code, text.as_str()), (EXIT_OK, "[]"));
+        let (code, text) = outcome(&list, false, Ok(Err("没有匹配的窗口".to_string())));
+        assert_eq!((code, text.as_str()), (EXIT_FAILED, "没有匹配的窗口"));
+        let (code, _) = outcome(&list, false, Ok(Ok(serde_json::json!(1))));
+        assert_eq!(code, EXIT_FAILED);
+        let (code, _) = outcome(&list, false, Err(anyhow::anyhow!("连接已断开")));
+        assert_eq!(code, EXIT_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::tool::border_overlay::BorderOverlay;
+use crate::tool::cli_tool;
+use crate::tool::icon_cache::{IconCache, ICON_CACHE_CAPACITY};
+use crate::tool::ipc_tool;
+use crate::tool::pin_registry::{self, PinRegistry, PinWatchdog};
+use crate::tool::rpc_tool::{self, EventHub, RpcError};
+use crate::tool::rule_tool::RuleEngine;
+use crate::tool::setting_tool::{self, PinExitPolicy};
+use crate::tool::window_backend;
+use crate::tool::window_watcher::WindowWatcher;
+use std::sync::Arc;
+
+/// 不带界面运行窗口管理核心：窗口监视、置顶看门狗、高亮边框、自动置顶规则，
+/// 以及命令行和本机控制接口，直到收到 Ctrl+C
+///
+/// 与有界面时共用配置目录下的设置、规则和置顶日志；设置只能在有界面时修改
+pub async fn run() -> anyhow::Result<()> {
+    //命令行端点有响应说明已有实例（无界面或有界面）在运行，不抢占它的端点
+    if ipc_tool::is_serving(cli_tool::CLI_ENDPOINT) {
+        anyhow::bail!("本程序已在运行");
+    }
+    let app_dir = setting_tool::config_dir()?;
+    let setting = setting_tool::load_setting(&app_dir)?;
+
+    //没有界面时不显示图标，不需要磁盘缓存
+    let icon_cache = Arc::new(IconCache::new(ICON_CACHE_CAPACITY, None));
+    let backend = window_backend::create_backend(icon_cache)?;
+    let watcher = WindowWatcher::start(backend.clone(), None);
+    //上次运行异常退出时遗留的置顶，按退出策略还原
+    let journal_path = app_dir.join(setting_tool::PIN_JOURNAL_FILE);
+    let restore = setting.pin_exit_policy == PinExitPolicy::Restore;
+    if let Err(e) = pin_registry::recover_journal(backend.as_ref(), &journal_path, restore) {
+        eprintln!("还原上次的置顶失败：{}", e);
+    }
+    let registry = Arc::new(PinRegistry::with_journal(journal_path));
+    let rpc_enabled = setting.rpc_enabled || std::env::args().any(|arg| arg == cli_tool::RPC_ARG);
+    let rpc_hub = rpc_enabled.then(|| Arc::new(EventHub::default()));
+    let event_hub = rpc_hub.clone();
+    let watchdog = PinWatchdog::start(
+        backend.clone(),
+        registry.clone(),
+        &watcher,
+        setting.reassert_policy,
+        move |event| {
+            if let (Some(hub), Ok(payload)) = (&event_hub, event.payload()) {
+                hub.publish(rpc_tool::TOPIC_PINS, event.name(), payload);
+            }
+        },
+    );
+    watchdog.set_suspend_on_fullscreen(setting.suspend_on_fullscreen);
+    let border_style = setting.pin_border.style().unwrap_or_else(|e| {
+        eprintln!("置顶边框设置无效：{}", e);
+        None
+    });
+    let border = BorderOverlay::start(backend.clone(), registry.clone(), border_style);
+    let rule_engine = Arc::new(RuleEngine::load(
+        backend.clone(),
+        registry.clone(),
+        Some(app_dir.join(setting_tool::PIN_RULES_FILE)),
+    )?);
+    rule_engine.attach(&watcher);
+
+    tokio::spawn(cli_tool::serve(backend.clone(), registry.clone(), || None));
+    if let Some(hub) = rpc_hub {
+        rpc_tool::publish_changes(&hub, &watcher, &registry);
+        let (rpc_backend, rpc_registry) = (backend.clone(), registry.clone());
+        tokio::spawn(rpc_tool::serve(hub, move |method, params| match method {
+            "get_app_setting" => rpc_tool::result(Ok::<_, String>(&setting)),
+            method => rpc_tool::dispatch_windows(
+                rpc_backend.as_ref(),
+                &rpc_registry,
+                None,
+                method,
+                params,
+            )
+            .unwrap_or_else(|| Err(RpcError::method_not_found(method))),
+        }));
+    }
+
+    tokio::signal::ctrl_c().await?;
+
+    //按退出策略处理本程序做出的置顶，与有界面时退出的处理相同
+    border.set_style(None);
+    if let Err(e) = pin_registry::release_on_exit(backend.as_ref(), &registry, restore) {
+        eprintln!("还原置顶失败：{}", e);
+    }
+    Ok(())
+}
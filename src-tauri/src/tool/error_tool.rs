@@ -0,0 +1,43 @@
+use std::sync::OnceLock;
+
+type ErrorSink = Box<dyn Fn(String) + Send + Sync>;
+
+/// 后台错误的接收者，有界面时推送给前端
+static ERROR_SINK: OnceLock<ErrorSink> = OnceLock::new();
+
+/// 设置后台错误的接收者，只能设置一次，之后的设置被忽略
+pub fn set_error_sink<F>(sink: F)
+where
+    F: Fn(String) + Send + Sync + 'static,
+{
+    let _ = ERROR_SINK.set(Box::new(sink));
+}
+
+/// 报告后台线程中出现的错误
+///
+/// 这些错误没有调用方可以返回，发布版又没有控制台，输出到标准错误的同时交给接收者
+pub fn report_error(message: String) {
+    eprintln!("{}", message);
+    if let Some(sink) = ERROR_SINK.get() {
+        sink(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn errors_go_to_the_sink() {
+        static REPORTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        set_error_sink(|message| REPORTED.lock().unwrap().push(message));
+        //只能设置一次
+        set_error_sink(|_| panic!("不应替换已有的接收者"));
+        report_error("恢复置顶失败：窗口不存在".to_string());
+        assert!(REPORTED
+            .lock()
+            .unwrap()
+            .contains(&"恢复置顶失败：窗口不存在".to_string()));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::tool::window_backend::{
+    BackendCapabilities, BorderStyle, RawWindowEvent, Rect, WindowBackend, WindowInfo,
+};
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+/// 内存中的假窗口
+#[derive(Debug, Clone, Default)]
+pub struct FakeWindow {
+    pub info: WindowInfo,
+    pub minimized: bool,
+    /// 模拟遮挡它的其它置顶窗口
+    pub obscured_by: Option<isize>,
+    pub rect: Rect,
+    pub opacity: f64,
+    pub click_through: bool,
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    windows: Vec<FakeWindow>,
+    foreground: Option<isize>,
+    /// 各显示器的区域，窗口中心所在的显示器即窗口所在的显示器
+    monitors: Vec<Rect>,
+    /// 子窗口或被拥有的窗口 → 父窗口，子窗口不出现在窗口列表中
+    parents: HashMap<isize, isize>,
+    /// 模拟拾取窗口时用户点击的窗口，为空表示按了 Esc
+    click_target: Option<isize>,
+    /// 绘制了高亮边框的窗口
+    borders: HashMap<isize, BorderStyle>,
+    sender: Option<Sender<RawWindowEvent>>,
+}
+
+impl FakeState {
+    fn notify(&self, event: RawWindowEvent) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(event);
+        }
+    }
+}
+
+/// 纯内存实现的窗口后端，行为确定，用于在没有桌面环境的机器上测试
+#[derive(Debug, Default)]
+pub struct FakeBackend {
+    state: Mutex<FakeState>,
+}
+
+impl FakeBackend {
+    pub fn new(windows: Vec<WindowInfo>) -> Self {
+        let backend = FakeBackend::default();
+        for info in windows {
+            backend.add_window(info);
+        }
+        backend
+    }
+
+    pub fn add_window(&self, info: WindowInfo) {
+        let mut state = self.state.lock().unwrap();
+        let hwnd = info.hwnd;
+        let existed = state.windows.iter().any(|w| w.info.hwnd == hwnd);
+        state.windows.retain(|w| w.info.hwnd != hwnd);
+        state.windows.push(FakeWindow {
+            info,
+            minimized: false,
+            obscured_by: None,
+            rect: Rect::default(),
+            opacity: 1.0,
+            click_through: false,
+        });
+        state.notify(if existed {
+            RawWindowEvent::Changed(hwnd)
+        } else {
+            RawWindowEvent::Created(hwnd)
+        });
+    }
+
+    pub fn remove_window(&self, hwnd: isize) {
+        let mut state = self.state.lock().unwrap();
+        state.windows.retain(|w| w.info.hwnd != hwnd);
+        if state.foreground == Some(hwnd) {
+            state.foreground = None;
+        }
+        state.notify(RawWindowEvent::Destroyed(hwnd));
+    }
+
+    pub fn set_title(&self, hwnd: isize, title: &str) -> anyhow::Result<()> {
+        self.with_window(hwnd, |w| w.info.title = title.to_string())
+    }
+
+    pub fn set_minimized(&self, hwnd: isize, minimized: bool) -> anyhow::Result<()> {
+        self.with_window(hwnd, |w| w.minimized = minimized)
+    }
+
+    pub fn set_obscured(&self, hwnd: isize, by: Option<isize>) -> anyhow::Result<()> {
+        self.with_window(hwnd, |w| w.obscured_by = by)
+    }
+
+    pub fn set_rect(&self, hwnd: isize, rect: Rect) -> anyhow::Result<()> {
+        self.with_window(hwnd, |w| w.rect = rect)
+    }
+
+    pub fn set_monitors(&self, monitors: Vec<Rect>) {
+        self.state.lock().unwrap().monitors = monitors;
+    }
+
+    pub fn set_parent(&self, hwnd: isize, parent: isize) {
+        self.state.lock().unwrap().parents.insert(hwnd, parent);
+    }
+
+    pub fn set_click_target(&self, hwnd: Option<isize>) {
+        self.state.lock().unwrap().click_target = hwnd;
+    }
+
+    pub fn border(&self, hwnd: isize) -> Option<BorderStyle> {
+        self.state.lock().unwrap().borders.get(&hwnd).copied()
+    }
+
+    pub fn is_click_through(&self, hwnd: isize) -> bool {
+        self.find(hwnd).is_some_and(|w| w.click_through)
+    }
+
+    pub fn is_minimized(&self, hwnd: isize) -> bool {
+        self.find(hwnd).map(|w| w.minimized).unwrap_or(false)
+    }
+
+    pub fn foreground(&self) -> Option<isize> {
+        self.state.lock().unwrap().foreground
+    }
+
+    pub fn window(&self, hwnd: isize) -> Option<WindowInfo> {
+        self.find(hwnd).map(|w| w.info)
+    }
+
+    fn find(&self, hwnd: isize) -> Option<FakeWindow> {
+        let state = self.state.lock().unwrap();
+        state.windows.iter().find(|w| w.info.hwnd == hwnd).cloned()
+    }
+
+    fn with_window<F>(&self, hwnd: isize, f: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(&mut FakeWindow),
+    {
+        let mut state = self.state.lock().unwrap();
+        let window = state
+            .windows
+            .iter_mut()
+            .find(|w| w.info.hwnd == hwnd)
+            .ok_or_else(|| anyhow::anyhow!("窗口不存在：{}", hwnd))?;
+        let old = window.info.clone();
+        f(window);
+        if window.info != old {
+            state.notify(RawWindowEvent::Changed(hwnd));
+        }
+        Ok(())
+    }
+}
+
+impl WindowBackend for FakeBackend {
+    fn enum_windows(&self, exclude: Option<isize>) -> Vec<WindowInfo> {
+        let state = self.state.lock().unwrap();
+        state
+            .windows
+            .iter()
+            .filter(|w| Some(w.info.hwnd) != exclude)
+            .map(|w| w.info.clone())
+            .collect()
+    }
+
+    fn is_topmost(&self, hwnd: isize) -> bool {
+        self.find(hwnd).map(|w| w.info.is_top_most).unwrap_or(false)
+    }
+
+    fn is_obscured(&self, hwnd: isize, ignore: &[isize]) -> bool {
+        self.find(hwnd)
+            .and_then(|w| w.obscured_by)
+            .is_some_and(|by| !ignore.contains(&by))
+    }
+
+    fn window_exists(&self, hwnd: isize) -> bool {
+        self.find(hwnd).is_some()
+    }
+
+    fn set_topmost(&self, hwnd: isize, enable: bool) -> anyhow::Result<()> {
+        //重新置顶会回到置顶窗口的最上层
+        self.with_window(hwnd, |w| {
+            w.info.is_top_most = enable;
+            w.obscured_by = None;
+        })
+    }
+
+    fn restore(&self, hwnd: isize) -> anyhow::Result<()> {
+        self.with_window(hwnd, |w| w.minimized = false)
+    }
+
+    fn focus(&self, hwnd: isize) -> anyhow::Result<()> {
+        self.with_window(hwnd, |_| {})?;
+        self.state.lock().unwrap().foreground = Some(hwnd);
+        Ok(())
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            backend: "fake".to_string(),
+            can_set_topmost: true,
+            can_set_opacity: true,
+            can_pick_window: true,
+            can_click_through: true,
+            can_draw_border: true,
+            can_move_window: true,
+        }
+    }
+
+    fn window_info(&self, hwnd: isize) -> Option<WindowInfo> {
+        self.window(hwnd)
+    }
+
+    fn opacity(&self, hwnd: isize) -> Option<f64> {
+        self.find(hwnd).map(|w| w.opacity)
+    }
+
+    fn set_opacity(&self, hwnd: isize, opacity: f64) -> anyhow::Result<()> {
+        self.with_window(hwnd, |w| w.opacity = opacity)
+    }
+
+    fn foreground_window(&self) -> Option<isize> {
+        self.foreground()
+    }
+
+    fn set_click_through(&self, hwnd: isize, enable: bool) -> anyhow::Result<()> {
+        self.with_window(hwnd, |w| w.click_through = enable)
+    }
+
+    fn draw_border(&self, hwnd: isize, style: &BorderStyle) -> anyhow::Result<()> {
+        self.find(hwnd)
+            .ok_or_else(|| anyhow::anyhow!("窗口不存在：{}", hwnd))?;
+        self.state.lock().unwrap().borders.insert(hwnd, *style);
+        Ok(())
+    }
+
+    fn clear_border(&self, hwnd: isize) -> anyhow::Result<()> {
+        self.state.lock().unwrap().borders.remove(&hwnd);
+        Ok(())
+    }
+
+    fn window_rect(&self, hwnd: isize) -> Option<Rect> {
+        self.find(hwnd).filter(|w| !w.minimized).map(|w| w.rect)
+    }
+
+    fn monitor_rect(&self, hwnd: isize) -> Option<Rect> {
+        let rect = self.find(hwnd)?.rect;
+        let (x, y) = ((rect.left + rect.right) / 2, (rect.top + rect.bottom) / 2);
+        let state = self.state.lock().unwrap();
+        state
+            .monitors
+            .iter()
+            .find(|m| m.left <= x && x < m.right && m.top <= y && y < m.bottom)
+            .copied()
+    }
+
+    fn monitors(&self) -> Vec<Rect> {
+        self.state.lock().unwrap().monitors.clone()
+    }
+
+    fn set_window_rect(&self, hwnd: isize, rect: &Rect) -> anyhow::Result<()> {
+        self.with_window(hwnd, |w| {
+            w.minimized = false;
+            w.rect = *rect;
+        })
+    }
+
+    fn parent_window(&self, hwnd: isize) -> Option<isize> {
+        self.state.lock().unwrap().parents.get(&hwnd).copied()
+    }
+
+    fn capture_click(&self) -> anyhow::Result<Option<isize>> {
+        Ok(self.state.lock().unwrap().click_target)
+    }
+
+    fn watch(&self, sender: Sender<RawWindowEvent>) -> anyhow::Result<()> {
+        self.state.lock().unwrap().sender = Some(sender);
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use crate::constant;
+use crate::tool::app_tool::{self, AppSettings};
+use crate::tool::pin_registry::{self, PinRegistry};
+use crate::tool::window_backend::{WindowBackendState, OPACITY_STEP};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+/// 可以绑定快捷键的操作
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HotkeyAction {
+    /// 置顶或取消置顶前台窗口
+    TogglePin,
+    UnpinAll,
+    ShowMainWindow,
+    /// 在置顶的窗口之间轮流切换前台
+    CycleFocus,
+    /// 增加前台窗口的不透明度
+    IncreaseOpacity,
+    /// 降低前台窗口的不透明度
+    DecreaseOpacity,
+    /// 关闭所有窗口的鼠标穿透
+    ClearClickThrough,
+}
+
+impl HotkeyAction {
+    pub const ALL: [HotkeyAction; 7] = [
+        HotkeyAction::TogglePin,
+        HotkeyAction::UnpinAll,
+        HotkeyAction::ShowMainWindow,
+        HotkeyAction::CycleFocus,
+        HotkeyAction::IncreaseOpacity,
+        HotkeyAction::DecreaseOpacity,
+        HotkeyAction::ClearClickThrough,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            HotkeyAction::TogglePin => "置顶前台窗口",
+            HotkeyAction::UnpinAll => "取消所有置顶",
+            HotkeyAction::ShowMainWindow => "显示主窗口",
+            HotkeyAction::CycleFocus => "切换置顶窗口",
+            HotkeyAction::IncreaseOpacity => "增加不透明度",
+            HotkeyAction::DecreaseOpacity => "降低不透明度",
+            HotkeyAction::ClearClickThrough => "取消所有鼠标穿透",
+        }
+    }
+
+    /// 默认只绑定置顶前台窗口，其它操作由用户自行设置，避免占用其它程序的快捷键
+    pub fn default_accelerator(self) -> &'static str {
+        match self {
+            HotkeyAction::TogglePin => "CommandOrControl+Alt+P",
+            _ => "",
+        }
+    }
+}
+
+/// 默认的快捷键绑定
+pub fn default_hotkeys() -> BTreeMap<HotkeyAction, String> {
+    HotkeyAction::ALL
+        .iter()
+        .map(|action| (*action, action.default_accelerator().to_string()))
+        .collect()
+}
+
+/// 一个操作当前绑定的快捷键，返回给前端展示
+#[derive(Debug, Clone, Serialize)]
+pub struct HotkeyBinding {
+    pub action: HotkeyAction,
+    pub label: String,
+    /// 为空表示未绑定
+    pub accelerator: String,
+    pub default_accelerator: String,
+}
+
+/// 所有操作的快捷键，设置文件中没有的操作使用默认快捷键（除置顶前台窗口外均未绑定）
+pub fn bindings(setting: &AppSettings) -> Vec<HotkeyBinding> {
+    HotkeyAction::ALL
+        .iter()
+        .map(|action| HotkeyBinding {
+            action: *action,
+            label: action.label().to_string(),
+            accelerator: setting
+                .hotkeys
+                .get(action)
+                .map(|accelerator| accelerator.trim().to_string())
+                .unwrap_or_else(|| action.default_accelerator().to_string()),
+            default_accelerator: action.default_accelerator().to_string(),
+        })
+        .collect()
+}
+
+/// 解析快捷键字符串，如 `CommandOrControl+Alt+P`
+pub fn parse_accelerator(accelerator: &str) -> anyhow::Result<Shortcut> {
+    Shortcut::from_str(accelerator)
+        .map_err(|e| anyhow::anyhow!("快捷键格式错误：{}（{}）", accelerator, e))
+}
+
+/// 按设置重新注册所有全局快捷键，单个快捷键失败不影响其它快捷键，返回所有失败的原因
+pub fn register_hotkeys(app: &AppHandle, setting: &AppSettings) -> anyhow::Result<()> {
+    let global_shortcut = app.global_shortcut();
+    global_shortcut.unregister_all()?;
+    let mut errors = Vec::new();
+    for binding in bindings(setting) {
+        if binding.accelerator.is_empty() {
+            continue;
+        }
+        let action = binding.action;
+        let result = parse_accelerator(&binding.accelerator).and_then(|shortcut| {
+            global_shortcut
+                .on_shortcut(shortcut, move |app, _, event| {
+                    if event.state == ShortcutState::Pressed {
+                        if let Err(e) = run_action(app, action) {
+                            app_tool::send_error_to_frontend(app, e);
+                        }
+                    }
+                })
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "快捷键 {} 注册失败，可能已被其它程序占用：{}",
+                        binding.accelerator,
+                        e
+                    )
+                })
+        });
+        if let Err(e) = result {
+            errors.push(format!("{}：{}", binding.label, e));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(errors.join("\n")))
+    }
+}
+
+/// 修改一个操作的快捷键并立即生效，`accelerator` 为空表示解除绑定
+pub fn rebind_hotkey(
+    app: &AppHandle,
+    action: HotkeyAction,
+    accelerator: &str,
+) -> anyhow::Result<()> {
+    let accelerator = accelerator.trim();
+    let state = app.state::<Mutex<AppSettings>>();
+    let mut setting = state.lock().unwrap();
+    if !accelerator.is_empty() {
+        let shortcut = parse_accelerator(accelerator)?;
+        let conflict = bindings(&setting).into_iter().find(|binding| {
+            binding.action != action
+                && parse_accelerator(&binding.accelerator).is_ok_and(|s| s.id() == shortcut.id())
+        });
+        if let Some(binding) = conflict {
+            return Err(anyhow::anyhow!(
+                "快捷键 {} 已绑定到“{}”",
+                accelerator,
+                binding.label
+            ));
+        }
+    }
+    let mut hotkeys = setting.hotkeys.clone();
+    hotkeys.insert(action, accelerator.to_string());
+    apply_hotkeys(app, &mut setting, hotkeys)
+}
+
+/// 所有快捷键恢复为默认值并立即生效
+pub fn reset_hotkeys(app: &AppHandle) -> anyhow::Result<()> {
+    let state = app.state::<Mutex<AppSettings>>();
+    let mut setting = state.lock().unwrap();
+    apply_hotkeys(app, &mut setting, default_hotkeys())
+}
+
+/// 先注册新的快捷键，成功后才保存；注册或保存失败时恢复原来的快捷键，设置不变
+fn apply_hotkeys(
+    app: &AppHandle,
+    setting: &mut AppSettings,
+    hotkeys: BTreeMap<HotkeyAction, String>,
+) -> anyhow::Result<()> {
+    let updated = AppSettings {
+        hotkeys,
+        ..setting.clone()
+    };
+    let result =
+        register_hotkeys(app, &updated).and_then(|()| app_tool::save_setting(app, &updated));
+    if let Err(e) = result {
+        //原来就注册失败的快捷键已经提示过，不再重复返回
+        let _ = register_hotkeys(app, setting);
+        return Err(e);
+    }
+    *setting = updated;
+    Ok(())
+}
+
+/// 通过快捷键切换了前台窗口的置顶，推送给前端提示
+#[derive(Debug, Clone, Serialize)]
+pub struct HotkeyPinEvent {
+    pub hwnd: isize,
+    pub display_name: String,
+    pub enabled: bool,
+}
+
+/// 通过快捷键调整了前台窗口的不透明度，推送给前端提示
+#[derive(Debug, Clone, Serialize)]
+pub struct HotkeyOpacityEvent {
+    pub hwnd: isize,
+    pub display_name: String,
+    pub opacity: f64,
+}
+
+pub fn run_action(app: &AppHandle, action: HotkeyAction) -> anyhow::Result<()> {
+    match action {
+        HotkeyAction::TogglePin => toggle_foreground_topmost(app),
+        HotkeyAction::UnpinAll => {
+            let backend = app.state::<WindowBackendState>();
+            let registry = app.state::<Arc<PinRegistry>>();
+            pin_registry::unpin_all(backend.0.as_ref(), &registry)
+        }
+        HotkeyAction::ShowMainWindow => app_tool::show_main_window(app),
+        HotkeyAction::CycleFocus => focus_next_pinned(app),
+        HotkeyAction::IncreaseOpacity => adjust_foreground_opacity(app, OPACITY_STEP),
+        HotkeyAction::DecreaseOpacity => adjust_foreground_opacity(app, -OPACITY_STEP),
+        HotkeyAction::ClearClickThrough => app_tool::clear_click_through(app),
+    }
+}
+
+/// 前台窗口，不包括本程序的主窗口
+fn foreground_window(app: &AppHandle) -> anyhow::Result<isize> {
+    let backend = app.state::<WindowBackendState>();
+    let hwnd_self = app_tool::get_self_window_id(app, constant::APP_MAIN_WINDOW_LABEL);
+    backend
+        .0
+        .foreground_window()
+        .filter(|hwnd| Some(*hwnd) != hwnd_self)
+        .context("没有可操作的前台窗口")
+}
+
+/// 切换前台窗口的置顶状态
+pub fn toggle_foreground_topmost(app: &AppHandle) -> anyhow::Result<()> {
+    let hwnd = foreground_window(app)?;
+    let backend = app.state::<WindowBackendState>();
+    let registry = app.state::<Arc<PinRegistry>>();
+    let backend = backend.0.as_ref();
+    let hwnd_self = app_tool::get_self_window_id(app, constant::APP_MAIN_WINDOW_LABEL);
+    let enable = !backend.is_topmost(hwnd);
+    pin_registry::set_pinned(backend, &registry, hwnd, enable, None, hwnd_self)?;
+
+    let display_name = backend
+        .window_info(hwnd)
+        .map(|window| window.display_name)
+        .unwrap_or_default();
+    let _ = app.emit(
+        "hotkey_pin_toggled",
+        HotkeyPinEvent {
+            hwnd,
+            display_name,
+            enabled: enable,
+        },
+    );
+    Ok(())
+}
+
+/// 按置顶顺序把下一个置顶窗口切换到前台
+fn focus_next_pinned(app: &AppHandle) -> anyhow::Result<()> {
+    let backend = app.state::<WindowBackendState>();
+    let registry = app.state::<Arc<PinRegistry>>();
+    let hwnd = registry
+        .next_after(backend.0.foreground_window())
+        .context("没有置顶的窗口")?;
+    backend.0.restore(hwnd)?;
+    backend.0.focus(hwnd)
+}
+
+fn adjust_foreground_opacity(app: &AppHandle, delta: f64) -> anyhow::Result<()> {
+    let hwnd = foreground_window(app)?;
+    let backend = app.state::<WindowBackendState>();
+    let registry = app.state::<Arc<PinRegistry>>();
+    let current = backend.0.opacity(hwnd).unwrap_or(1.0);
+    let opacity = pin_registry::set_opacity(backend.0.as_ref(), &registry, hwnd, current + delta)?;
+    let display_name = backend
+        .0
+        .window_info(hwnd)
+        .map(|window| window.display_name)
+        .unwrap_or_default();
+    let _ = app.emit(
+        "hotkey_opacity_changed",
+        HotkeyOpacityEvent {
+            hwnd,
+            display_name,
+            opacity,
+        },
+    );
+    Ok(())
+}
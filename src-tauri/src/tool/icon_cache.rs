@@ -0,0 +1,256 @@
+use lru::LruCache;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+/// 内存中最多缓存的图标数量（不同尺寸分别计数）
+pub const ICON_CACHE_CAPACITY: usize = 256;
+/// 磁盘缓存的大小上限（字节），超出时删除最早写入的图标
+pub const ICON_DISK_CACHE_LIMIT: u64 = 32 * 1024 * 1024;
+
+/// 根据程序路径和文件修改时间计算图标键
+///
+/// 程序文件更新后 mtime 改变，键随之改变，旧图标自然失效，前端也可以放心地长期缓存。
+/// 使用 FNV-1a 保证不同版本的程序算出的键一致，磁盘缓存可以跨版本复用
+pub fn icon_key(path: &str) -> String {
+    let mtime = std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mtime = mtime.to_le_bytes();
+    for byte in path.as_bytes().iter().chain(mtime.iter()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// 内存缓存的键：（图标键, 尺寸）
+type SizedKey = (String, u32);
+
+/// 程序图标缓存
+///
+/// 枚举窗口时只登记“图标键 → 程序路径”，真正的图标在前端通过 `icon://` 请求时才提取。
+/// 内存中按（图标键, 尺寸）缓存最近使用的 PNG，可选地落盘到缓存目录，下次启动直接读取。
+/// 只响应登记过的图标键，`icon://` 请求中的键不会直接拼进文件路径
+pub struct IconCache {
+    sources: Mutex<HashMap<String, String>>,
+    entries: Mutex<LruCache<SizedKey, Arc<Vec<u8>>>>,
+    disk_dir: Option<PathBuf>,
+    disk_limit: u64,
+    /// 磁盘缓存当前占用的字节数
+    disk_usage: Mutex<u64>,
+}
+
+impl IconCache {
+    pub fn new(capacity: usize, disk_dir: Option<PathBuf>) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        let disk_usage = match &disk_dir {
+            Some(dir) => {
+                let _ = std::fs::create_dir_all(dir);
+                prune_disk(dir, ICON_DISK_CACHE_LIMIT)
+            }
+            None => 0,
+        };
+        IconCache {
+            sources: Mutex::new(HashMap::new()),
+            entries: Mutex::new(LruCache::new(capacity)),
+            disk_dir,
+            disk_limit: ICON_DISK_CACHE_LIMIT,
+            disk_usage: Mutex::new(disk_usage),
+        }
+    }
+
+    /// 只使用内存缓存
+    pub fn in_memory() -> Self {
+        IconCache::new(ICON_CACHE_CAPACITY, None)
+    }
+
+    /// 登记程序路径，返回前端用来请求图标的键
+    pub fn register(&self, path: &str) -> String {
+        let key = icon_key(path);
+        self.sources
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| path.to_string());
+        key
+    }
+
+    /// 图标键对应的程序路径
+    pub fn path_of(&self, key: &str) -> Option<String> {
+        self.sources.lock().unwrap().get(key).cloned()
+    }
+
+    /// 获取指定尺寸的 PNG，缓存未命中时以程序路径调用 `load` 生成 PNG 并写入缓存
+    pub fn get_png<F>(&self, key: &str, size: u32, load: F) -> anyhow::Result<Arc<Vec<u8>>>
+    where
+        F: FnOnce(&str) -> anyhow::Result<Vec<u8>>,
+    {
+        let cache_key = (key.to_string(), size);
+        if let Some(png) = self.entries.lock().unwrap().get(&cache_key) {
+            return Ok(png.clone());
+        }
+
+        //键来自前端的请求，登记过才能用来拼接磁盘缓存的路径
+        let path = self
+            .path_of(key)
+            .ok_or_else(|| anyhow::anyhow!("未知的图标：{}", key))?;
+        let disk_path = self
+            .disk_dir
+            .as_deref()
+            .map(|dir| dir.join(format!("{}-{}.png", key, size)));
+        let png = match disk_path.as_deref().and_then(read_file) {
+            Some(bytes) => bytes,
+            None => {
+                let bytes = load(&path)?;
+                if let Some(disk_path) = &disk_path {
+                    self.write_disk(disk_path, &bytes);
+                }
+                bytes
+            }
+        };
+
+        let png = Arc::new(png);
+        self.entries.lock().unwrap().put(cache_key, png.clone());
+        Ok(png)
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+        if let Some(dir) = &self.disk_dir {
+            let _ = std::fs::remove_dir_all(dir);
+            let _ = std::fs::create_dir_all(dir);
+            *self.disk_usage.lock().unwrap() = 0;
+        }
+    }
+
+    /// 写入磁盘缓存，超出上限时清理；写入失败不影响图标显示
+    fn write_disk(&self, path: &Path, bytes: &[u8]) {
+        if std::fs::write(path, bytes).is_err() {
+            return;
+        }
+        let mut usage = self.disk_usage.lock().unwrap();
+        *usage += bytes.len() as u64;
+        if *usage > self.disk_limit {
+            if let Some(dir) = &self.disk_dir {
+                *usage = prune_disk(dir, self.disk_limit);
+            }
+        }
+    }
+}
+
+/// 按写入时间从早到晚删除缓存的图标，直到不超过上限的四分之三，避免每次写入都要清理；
+/// 返回剩余占用的字节数
+fn prune_disk(dir: &Path, limit: u64) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut files: Vec<(std::time::SystemTime, PathBuf, u64)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let meta = entry.metadata().ok()?;
+            meta.is_file().then(|| {
+                (
+                    meta.modified().unwrap_or(UNIX_EPOCH),
+                    entry.path(),
+                    meta.len(),
+                )
+            })
+        })
+        .collect();
+    let mut usage: u64 = files.iter().map(|(_, _, len)| len).sum();
+    if usage <= limit {
+        return usage;
+    }
+    files.sort();
+    for (_, path, len) in files {
+        if usage <= limit / 4 * 3 {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            usage -= len;
+        }
+    }
+    usage
+}
+
+fn read_file(path: &Path) -> Option<Vec<u8>> {
+    std::fs::read(path).ok().filter(|bytes| !bytes.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("icon_{}_test_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn caches_in_memory_and_on_disk() {
+        let dir = temp_dir("cache");
+        let exe = std::env::current_exe().unwrap();
+        let exe = exe.to_str().unwrap();
+        let loads = Cell::new(0);
+        let load = |path: &str| {
+            assert_eq!(path, exe);
+            loads.set(loads.get() + 1);
+            Ok(vec![1, 2, 3])
+        };
+
+        let cache = IconCache::new(4, Some(dir.clone()));
+        let key = cache.register(exe);
+        assert_eq!(key, icon_key(exe));
+        assert_eq!(*cache.get_png(&key, 32, load).unwrap(), vec![1, 2, 3]);
+        assert_eq!(*cache.get_png(&key, 32, load).unwrap(), vec![1, 2, 3]);
+        assert_eq!(loads.get(), 1);
+        cache.get_png(&key, 16, load).unwrap();
+        assert_eq!(loads.get(), 2);
+
+        //重新启动后从磁盘读取
+        let cache = IconCache::new(4, Some(dir.clone()));
+        cache.register(exe);
+        cache.get_png(&key, 32, load).unwrap();
+        assert_eq!(loads.get(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_never_touch_the_disk() {
+        let dir = temp_dir("unknown");
+        let cache = IconCache::new(4, Some(dir.join("icons")));
+        std::fs::write(dir.join("secret-32.png"), b"secret").unwrap();
+        for key in ["../secret", "0123456789abcdef"] {
+            let result = cache.get_png(key, 32, |_| panic!("不应提取未登记的图标"));
+            assert!(result.is_err(), "{}", key);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn disk_cache_is_bounded() {
+        let dir = temp_dir("bounded");
+        let mut cache = IconCache::new(1, Some(dir.clone()));
+        cache.disk_limit = 20;
+        let key = cache.register("/bin/sh");
+        for size in 1..=10 {
+            cache.get_png(&key, size, |_| Ok(vec![0; 8])).unwrap();
+        }
+        let usage: u64 = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum();
+        assert!(usage <= 20, "{}", usage);
+        assert_eq!(*cache.disk_usage.lock().unwrap(), usage);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,410 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// 单条消息的最大长度，超过时断开连接
+const MAX_MESSAGE_BYTES: u64 = 1024 * 1024;
+/// 每个连接待发送消息的上限，客户端读得太慢时断开连接
+const MAX_PENDING_MESSAGES: usize = 1024;
+/// 客户端等待响应的超时时间
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Windows 下所有管道实例都忙时的重试次数和间隔
+#[cfg(target_os = "windows")]
+const PIPE_BUSY_RETRIES: u32 = 50;
+#[cfg(target_os = "windows")]
+const PIPE_BUSY_INTERVAL: Duration = Duration::from_millis(100);
+#[cfg(target_os = "windows")]
+const ERROR_PIPE_BUSY: i32 = 231;
+/// Windows 下客户端检查管道中有没有响应的间隔
+#[cfg(target_os = "windows")]
+const PIPE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// 本机进程间通信的端点，Windows 下为命名管道，其它系统为 Unix 域套接字
+///
+/// 端点名称包含当前用户，不同用户的实例互不干扰
+pub fn endpoint(name: &str) -> String {
+    let user = std::env::var(if cfg!(target_os = "windows") {
+        "USERNAME"
+    } else {
+        "USER"
+    })
+    .unwrap_or_default()
+    .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    #[cfg(target_os = "windows")]
+    {
+        format!(r"\\.\pipe\top-set-tool-{}-{}", user, name)
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        //XDG_RUNTIME_DIR 只有当前用户可以访问，没有时退回到临时目录
+        match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) if !dir.is_empty() => std::path::Path::new(&dir)
+                .join(format!("top-set-tool-{}.sock", name))
+                .to_string_lossy()
+                .into_owned(),
+            _ => std::env::temp_dir()
+                .join(format!("top-set-tool-{}-{}.sock", user, name))
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+}
+
+/// 连接的对端，处理请求时可以保存下来，之后随时推送消息（如事件通知）
+#[derive(Clone)]
+pub struct IpcPeer {
+    id: u64,
+    sender: mpsc::Sender<Option<Value>>,
+}
+
+impl IpcPeer {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 推送一条消息，连接已断开或积压太多时返回 false
+    pub fn send(&self, message: Value) -> bool {
+        self.sender.try_send(Some(message)).is_ok()
+    }
+
+    /// 不经过连接创建对端，推送的消息从返回的接收端读取
+    #[cfg(test)]
+    pub fn channel(id: u64) -> (Self, mpsc::Receiver<Option<Value>>) {
+        let (sender, receiver) = mpsc::channel(MAX_PENDING_MESSAGES);
+        (IpcPeer { id, sender }, receiver)
+    }
+}
+
+type Handler = Arc<dyn Fn(&str, &IpcPeer) -> Option<Value> + Send + Sync>;
+
+/// 端点 `name` 上是否有正在运行的实例在响应
+pub fn is_serving(name: &str) -> bool {
+    IpcClient::connect(name).is_ok()
+}
+
+/// 在端点 `name` 上接受连接，每行一条请求，`handler` 返回的 JSON 作为一行响应，返回 None 时不响应
+///
+/// 只接受当前用户的连接，端点已被正在运行的实例使用时返回错误，不会抢占；
+/// `handler` 在阻塞线程中执行，可以直接调用窗口后端
+pub async fn serve<F>(name: &str, handler: F) -> anyhow::Result<()>
+where
+    F: Fn(&str, &IpcPeer) -> Option<Value> + Send + Sync + 'static,
+{
+    let handler: Handler = Arc::new(handler);
+    let path = endpoint(name);
+    #[cfg(target_os = "windows")]
+    {
+        let security = pipe_security::PipeSecurity::current_user()?;
+        //first_pipe_instance 防止其它进程抢先创建同名管道，也防止抢占正在运行的实例
+        let mut server = security
+            .create(&path, true)
+            .map_err(|e| anyhow::anyhow!("端点 {} 已被正在运行的实例使用：{}", path, e))?;
+        loop {
+            server.connect().await?;
+            let client = server;
+            server = security.create(&path, false)?;
+            tokio::spawn(handle_connection(client, handler.clone()));
+        }
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        //能连上说明有实例在运行，否则是上次异常退出时遗留的套接字文件
+        if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+            anyhow::bail!("端点 {} 已被正在运行的实例使用", path);
+        }
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        let uid = std::fs::metadata(&path)?.uid();
+        loop {
+            let (stream, _) = listener.accept().await?;
+            //套接字文件的权限之外再检查对端用户，临时目录下的套接字也不会被其它用户使用
+            if stream.peer_cred().map(|cred| cred.uid()).ok() != Some(uid) {
+                continue;
+            }
+            tokio::spawn(handle_connection(stream, handler.clone()));
+        }
+    }
+}
+
+async fn handle_connection<S>(stream: S, handler: Handler)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    static NEXT_PEER_ID: AtomicU64 = AtomicU64::new(1);
+    let (reader, mut writer) = tokio::io::split(stream);
+    let (sender, mut receiver) = mpsc::channel::<Option<Value>>(MAX_PENDING_MESSAGES);
+    let peer = IpcPeer {
+        id: NEXT_PEER_ID.fetch_add(1, Ordering::Relaxed),
+        sender,
+    };
+    //响应和推送都经由同一个队列写出，None 表示写完已有消息后关闭
+    let writer_task = tokio::spawn(async move {
+        while let Some(Some(message)) = receiver.recv().await {
+            let mut data = message.to_string();
+            data.push('\n');
+            if writer.write_all(data.as_bytes()).await.is_err() || writer.flush().await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut reader = tokio::io::BufReader::new(reader);
+    loop {
+        let mut line = String::new();
+        let read = (&mut reader)
+            .take(MAX_MESSAGE_BYTES)
+            .read_line(&mut line)
+            .await;
+        match read {
+            Ok(0) | Err(_) => break,
+            Ok(_) if !line.ends_with('\n') => break,
+            Ok(_) => {}
+        }
+        let (handler, request_peer) = (handler.clone(), peer.clone());
+        let response = tokio::task::spawn_blocking(move || handler(&line, &request_peer)).await;
+        match response {
+            Ok(Some(response)) => {
+                if peer.sender.send(Some(response)).await.is_err() {
+                    break;
+                }
+            }
+            Ok(None) => {}
+            Err(_) => break,
+        }
+    }
+    let _ = peer.sender.send(None).await;
+    let _ = writer_task.await;
+}
+
+/// 连接到正在运行的实例
+pub struct IpcClient {
+    reader: BufReader<Stream>,
+    writer: Stream,
+}
+
+#[cfg(target_os = "windows")]
+type Stream = std::fs::File;
+#[cfg(not(target_os = "windows"))]
+type Stream = std::os::unix::net::UnixStream;
+
+impl IpcClient {
+    /// 端点不存在（没有实例在运行）时返回 `NotFound` 或 `ConnectionRefused`
+    pub fn connect(name: &str) -> std::io::Result<Self> {
+        let path = endpoint(name);
+        #[cfg(target_os = "windows")]
+        let stream = {
+            let mut retries = 0;
+            loop {
+                match std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(&path)
+                {
+                    Err(e)
+                        if e.raw_os_error() == Some(ERROR_PIPE_BUSY)
+                            && retries < PIPE_BUSY_RETRIES =>
+                    {
+                        retries += 1;
+                        std::thread::sleep(PIPE_BUSY_INTERVAL);
+                    }
+                    result => break result?,
+                }
+            }
+        };
+        #[cfg(not(target_os = "windows"))]
+        let stream = {
+            let stream = std::os::unix::net::UnixStream::connect(&path)?;
+            stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+            stream
+        };
+        Ok(IpcClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    /// 发送一条请求并等待响应
+    pub fn call<T: Serialize, R: DeserializeOwned>(&mut self, request: &T) -> anyhow::Result<R> {
+        let mut data = serde_json::to_string(request)?;
+        data.push('\n');
+        self.writer.write_all(data.as_bytes())?;
+        self.writer.flush()?;
+        #[cfg(target_os = "windows")]
+        self.wait_for_response()?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(anyhow::anyhow!("连接已被关闭"));
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// 同步读取命名管道不支持超时，先轮询到管道中有数据再读取
+    #[cfg(target_os = "windows")]
+    fn wait_for_response(&self) -> anyhow::Result<()> {
+        use std::os::windows::io::AsRawHandle;
+        use windows::Win32::Foundation::HANDLE;
+        use windows::Win32::System::Pipes::PeekNamedPipe;
+        if !self.reader.buffer().is_empty() {
+            return Ok(());
+        }
+        let handle = HANDLE(self.reader.get_ref().as_raw_handle() as isize);
+        let started = std::time::Instant::now();
+        loop {
+            let mut available = 0u32;
+            //对端关闭管道时返回错误
+            unsafe { PeekNamedPipe(handle, None, 0, None, Some(&mut available), None)? };
+            if available > 0 {
+                return Ok(());
+            }
+            if started.elapsed() > CLIENT_TIMEOUT {
+                return Err(anyhow::anyhow!("等待响应超时"));
+            }
+            std::thread::sleep(PIPE_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip_through_socket() {
+        let name = format!("test-{}", std::process::id());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server_name = name.clone();
+        runtime.spawn(async move {
+            serve(&server_name, |request, peer| {
+                let request: Value = serde_json::from_str(request).ok()?;
+                //没有 id 的请求不响应
+                let id = request.get("id")?.clone();
+                Some(json!({ "id": id, "peer": peer.id(), "echo": request["data"] }))
+            })
+            .await
+        });
+        let started = std::time::Instant::now();
+        let mut client = loop {
+            match IpcClient::connect(&name) {
+                Ok(client) => break client,
+                Err(_) if started.elapsed() < Duration::from_secs(5) => {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(e) => panic!("连接失败：{}", e),
+            }
+        };
+
+        let response: Value = client.call(&json!({ "id": 1, "data": "中文" })).unwrap();
+        assert_eq!(response["echo"], "中文");
+        let peer = response["peer"].clone();
+        client.writer.write_all(b"{\"data\":0}\n").unwrap();
+        let response: Value = client.call(&json!({ "id": 2, "data": [1] })).unwrap();
+        assert_eq!(response, json!({ "id": 2, "peer": peer, "echo": [1] }));
+
+        //其它连接是不同的对端
+        let mut other = IpcClient::connect(&name).unwrap();
+        let response: Value = other.call(&json!({ "id": 3 })).unwrap();
+        assert_ne!(response["peer"], peer);
+
+        //正在运行时不会被抢占
+        assert!(is_serving(&name));
+        let result = runtime.block_on(serve(&name, |_, _| None));
+        assert!(result.is_err());
+        assert!(client.call::<_, Value>(&json!({ "id": 4 })).is_ok());
+
+        drop(runtime);
+        assert!(client.call::<_, Value>(&json!({ "id": 5 })).is_err());
+        let _ = std::fs::remove_file(endpoint(&name));
+        assert!(!is_serving(&name));
+    }
+}
+
+/// 命名管道的访问控制，只允许当前用户连接
+#[cfg(target_os = "windows")]
+mod pipe_security {
+    use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+    use windows::core::{PCWSTR, PWSTR};
+    use windows::Win32::Foundation::{CloseHandle, LocalFree, BOOL, HANDLE, HLOCAL};
+    use windows::Win32::Security::Authorization::{
+        ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW,
+        SDDL_REVISION_1,
+    };
+    use windows::Win32::Security::{
+        GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY,
+        TOKEN_USER,
+    };
+    use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    pub struct PipeSecurity(PSECURITY_DESCRIPTOR);
+
+    //安全描述符创建后只读，可以跨线程使用
+    unsafe impl Send for PipeSecurity {}
+    unsafe impl Sync for PipeSecurity {}
+
+    impl PipeSecurity {
+        /// 只授予当前用户完全访问权限，不继承默认的 Everyone 读权限
+        pub fn current_user() -> anyhow::Result<Self> {
+            unsafe {
+                let mut token = HANDLE::default();
+                OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+                let mut len = 0u32;
+                let _ = GetTokenInformation(token, TokenUser, None, 0, &mut len);
+                //按 8 字节对齐分配，TOKEN_USER 中包含指针
+                let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+                let result = GetTokenInformation(
+                    token,
+                    TokenUser,
+                    Some(buffer.as_mut_ptr() as *mut _),
+                    len,
+                    &mut len,
+                );
+                let _ = CloseHandle(token);
+                result?;
+                let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+                let mut sid = PWSTR::null();
+                ConvertSidToStringSidW(user.User.Sid, &mut sid)?;
+                let sid_string = sid.to_string();
+                let _ = LocalFree(HLOCAL(sid.0 as _));
+                let sddl: Vec<u16> = format!("D:P(A;;GA;;;{})", sid_string?)
+                    .encode_utf16()
+                    .chain(std::iter::once(0))
+                    .collect();
+                let mut descriptor = PSECURITY_DESCRIPTOR::default();
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                    PCWSTR(sddl.as_ptr()),
+                    SDDL_REVISION_1,
+                    &mut descriptor,
+                    None,
+                )?;
+                Ok(PipeSecurity(descriptor))
+            }
+        }
+
+        pub fn create(&self, path: &str, first: bool) -> std::io::Result<NamedPipeServer> {
+            let mut attributes = SECURITY_ATTRIBUTES {
+                nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+                lpSecurityDescriptor: self.0 .0,
+                bInheritHandle: BOOL(0),
+            };
+            unsafe {
+                ServerOptions::new()
+                    .first_pipe_instance(first)
+                    .create_with_security_attributes_raw(path, &mut attributes as *mut _ as *mut _)
+            }
+        }
+    }
+
+    impl Drop for PipeSecurity {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = LocalFree(HLOCAL(self.0 .0));
+            }
+        }
+    }
+}
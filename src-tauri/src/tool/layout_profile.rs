@@ -0,0 +1,488 @@
+use crate::tool::error_tool;
+use crate::tool::pin_registry::{self, PinRegistry};
+use crate::tool::rule_tool::glob_match;
+use crate::tool::window_backend::{Rect, WindowBackend, WindowInfo};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// 布局文件的扩展名
+const LAYOUT_EXTENSION: &str = "json";
+/// 布局名称的最大字数
+const MAX_NAME_CHARS: usize = 64;
+/// 不透明度相差小于该值时视为相同，不再修改
+const OPACITY_EPSILON: f64 = 0.01;
+
+/// 布局中的一个窗口
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LayoutWindow {
+    /// 程序名
+    pub exe: String,
+    pub display_name: String,
+    pub class: String,
+    /// 标题通配符，保存时为当时的完整标题，可以在布局文件中改为通配符
+    pub title: String,
+    pub rect: Rect,
+    /// 保存时窗口所在显示器的区域，还原时该显示器已不存在则按相对位置移到其它显示器
+    pub monitor: Option<Rect>,
+    pub topmost: bool,
+    /// 不透明度，为空表示无法获取，还原时不修改
+    pub opacity: Option<f64>,
+}
+
+/// 命名的窗口布局，记录窗口的位置、置顶和不透明度
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LayoutProfile {
+    pub name: String,
+    /// 保存时间，Unix 时间戳（毫秒）
+    pub saved_at: u64,
+    pub windows: Vec<LayoutWindow>,
+}
+
+/// 应用布局的结果，推送给前端提示
+#[derive(Debug, Clone, Serialize, PartialEq, Default)]
+pub struct ApplyReport {
+    /// 已还原的窗口数
+    pub applied: usize,
+    /// 没有找到的窗口（显示名称）
+    pub missing: Vec<String>,
+    /// 还原失败的窗口及原因
+    pub failed: Vec<String>,
+}
+
+/// 布局文件的存储，每个布局保存为目录下的 `<名称>.json`
+pub struct LayoutStore {
+    dir: PathBuf,
+}
+
+impl LayoutStore {
+    pub fn new(dir: PathBuf) -> Self {
+        LayoutStore { dir }
+    }
+
+    /// 所有布局，按名称排序；无法读取的文件会被跳过
+    pub fn list(&self) -> anyhow::Result<Vec<LayoutProfile>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut profiles = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(LAYOUT_EXTENSION) {
+                continue;
+            }
+            match read_profile(&path) {
+                Ok(profile) => profiles.push(profile),
+                Err(e) => error_tool::report_error(format!(
+                    "忽略无效的布局文件 {}：{:#}",
+                    path.display(),
+                    e
+                )),
+            }
+        }
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(profiles)
+    }
+
+    pub fn load(&self, name: &str) -> anyhow::Result<LayoutProfile> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(anyhow::anyhow!("布局不存在：{}", name));
+        }
+        read_profile(&path)
+    }
+
+    /// 保存布局，同名的布局会被覆盖
+    pub fn save(&self, profile: &LayoutProfile) -> anyhow::Result<()> {
+        let path = self.path(&profile.name)?;
+        std::fs::create_dir_all(&self.dir)?;
+        //先写临时文件再替换，写到一半失败时不会损坏原有的同名布局
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(profile)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> anyhow::Result<()> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(anyhow::anyhow!("布局不存在：{}", name));
+        }
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// 重命名布局，新名称已被其它布局使用时报错
+    pub fn rename(&self, name: &str, new_name: &str) -> anyhow::Result<LayoutProfile> {
+        let mut profile = self.load(name)?;
+        let new_name = validate_name(new_name)?;
+        if new_name == profile.name {
+            return Ok(profile);
+        }
+        let old_path = self.path(&profile.name)?;
+        let new_path = self.path(new_name)?;
+        //文件名不区分大小写的系统上只改大小写时，目标文件就是原文件
+        if new_path.exists() && !is_same_file(&old_path, &new_path) {
+            return Err(anyhow::anyhow!("布局已存在：{}", new_name));
+        }
+        std::fs::rename(&old_path, &new_path)?;
+        profile.name = new_name.to_string();
+        self.save(&profile)?;
+        Ok(profile)
+    }
+
+    fn path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let name = validate_name(name)?;
+        Ok(self.dir.join(format!("{}.{}", name, LAYOUT_EXTENSION)))
+    }
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// 读取布局文件，布局名称以文件名为准
+fn read_profile(path: &Path) -> anyhow::Result<LayoutProfile> {
+    let file = std::fs::File::open(path)?;
+    let mut profile: LayoutProfile =
+        serde_json::from_reader(std::io::BufReader::new(file)).context("读取布局失败")?;
+    if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+        profile.name = stem.to_string();
+    }
+    Ok(profile)
+}
+
+/// 检查布局名称能否用作文件名，返回去掉首尾空白后的名称
+pub fn validate_name(name: &str) -> anyhow::Result<&str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("布局名称不能为空"));
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(anyhow::anyhow!("布局名称不能超过 {} 个字", MAX_NAME_CHARS));
+    }
+    if name.starts_with('.')
+        || name
+            .chars()
+            .any(|c| c.is_control() || r#"\/:*?"<>|"#.contains(c))
+    {
+        return Err(anyhow::anyhow!(
+            r#"布局名称不能以 . 开头，也不能包含 \ / : * ? " < > | 等字符"#
+        ));
+    }
+    Ok(name)
+}
+
+/// 记录当前所有窗口（不包括本程序的主窗口 `hwnd_self`）的位置、置顶和不透明度，最小化的窗口不记录
+pub fn snapshot(
+    backend: &dyn WindowBackend,
+    name: &str,
+    hwnd_self: Option<isize>,
+) -> anyhow::Result<LayoutProfile> {
+    let name = validate_name(name)?;
+    let windows: Vec<LayoutWindow> = backend
+        .enum_windows(hwnd_self)
+        .into_iter()
+        .filter_map(|window| {
+            let rect = backend.window_rect(window.hwnd)?;
+            Some(LayoutWindow {
+                monitor: backend.monitor_rect(window.hwnd),
+                opacity: backend.opacity(window.hwnd),
+                topmost: window.is_top_most,
+                exe: window.name,
+                display_name: window.display_name,
+                class: window.class,
+                title: window.title,
+                rect,
+            })
+        })
+        .collect();
+    if windows.is_empty() {
+        return Err(anyhow::anyhow!("没有可以保存的窗口"));
+    }
+    Ok(LayoutProfile {
+        name: name.to_string(),
+        saved_at: pin_registry::now_millis(),
+        windows,
+    })
+}
+
+/// 应用布局：找回布局中的窗口，还原位置、大小、置顶和不透明度
+///
+/// 先在程序名和类名相同的窗口中按标题匹配，剩下的记录再按顺序使用同一程序的其它窗口；每个窗口只使用一次
+pub fn apply(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    profile: &LayoutProfile,
+    hwnd_self: Option<isize>,
+) -> ApplyReport {
+    let windows = backend.enum_windows(hwnd_self);
+    let mut used = HashSet::new();
+    let mut targets: Vec<Option<isize>> = Vec::new();
+    for entry in &profile.windows {
+        let hwnd = find_window(entry, &windows, &used, true);
+        used.extend(hwnd);
+        targets.push(hwnd);
+    }
+    for (entry, target) in profile.windows.iter().zip(targets.iter_mut()) {
+        if target.is_none() {
+            *target = find_window(entry, &windows, &used, false);
+            used.extend(*target);
+        }
+    }
+
+    let monitors = backend.monitors();
+    let mut report = ApplyReport::default();
+    for (entry, target) in profile.windows.iter().zip(targets) {
+        let Some(hwnd) = target else {
+            report.missing.push(entry.display_name.clone());
+            continue;
+        };
+        match apply_window(backend, registry, entry, hwnd, &monitors, hwnd_self) {
+            Ok(()) => report.applied += 1,
+            Err(e) => report.failed.push(format!("{}：{}", entry.display_name, e)),
+        }
+    }
+    report
+}
+
+/// 未使用的、程序名和类名相同的窗口，`match_title` 为 true 时还要求标题匹配
+fn find_window(
+    entry: &LayoutWindow,
+    windows: &[WindowInfo],
+    used: &HashSet<isize>,
+    match_title: bool,
+) -> Option<isize> {
+    windows
+        .iter()
+        .find(|window| {
+            !used.contains(&window.hwnd)
+                && window.name.eq_ignore_ascii_case(&entry.exe)
+                && window.class == entry.class
+                && (!match_title
+                    || window.title == entry.title
+                    || glob_match(&entry.title, &window.title))
+        })
+        .map(|window| window.hwnd)
+}
+
+fn apply_window(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    entry: &LayoutWindow,
+    hwnd: isize,
+    monitors: &[Rect],
+    hwnd_self: Option<isize>,
+) -> anyhow::Result<()> {
+    let rect = match entry.monitor {
+        //显示器已拔掉或分辨率变化时，移到窗口中心所在的显示器，都不在时移到第一个显示器
+        Some(saved) if !monitors.is_empty() && !monitors.contains(&saved) => {
+            let (x, y) = (
+                (entry.rect.left + entry.rect.right) / 2,
+                (entry.rect.top + entry.rect.bottom) / 2,
+            );
+            let target = monitors
+                .iter()
+                .find(|m| m.left <= x && x < m.right && m.top <= y && y < m.bottom)
+                .unwrap_or(&monitors[0]);
+            relocate(&entry.rect, &saved, target)
+        }
+        _ => entry.rect,
+    };
+    backend.set_window_rect(hwnd, &rect)?;
+    if entry.topmost != backend.is_topmost(hwnd) {
+        pin_registry::set_pinned(backend, registry, hwnd, entry.topmost, None, hwnd_self)?;
+    }
+    //置顶后再修改不透明度，置顶登记才能记录原始样式
+    if let Some(opacity) = entry.opacity {
+        if backend
+            .opacity(hwnd)
+            .is_none_or(|current| (current - opacity).abs() > OPACITY_EPSILON)
+        {
+            pin_registry::set_opacity(backend, registry, hwnd, opacity)?;
+        }
+    }
+    Ok(())
+}
+
+/// 把 `rect` 从显示器 `from` 移到显示器 `to` 上相同的相对位置，超出 `to` 的部分缩小或移回显示器内
+pub fn relocate(rect: &Rect, from: &Rect, to: &Rect) -> Rect {
+    let width = (rect.right - rect.left).min(to.right - to.left);
+    let height = (rect.bottom - rect.top).min(to.bottom - to.top);
+    let left = (to.left + rect.left - from.left).clamp(to.left, to.right - width);
+    let top = (to.top + rect.top - from.top).clamp(to.top, to.bottom - height);
+    Rect {
+        left,
+        top,
+        right: left + width,
+        bottom: top + height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::fake_backend::FakeBackend;
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> Rect {
+        Rect {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    fn window(hwnd: isize, exe: &str, class: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            hwnd,
+            name: exe.to_string(),
+            display_name: exe.to_string(),
+            class: class.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn entry(exe: &str, title: &str, rect: Rect) -> LayoutWindow {
+        LayoutWindow {
+            exe: exe.to_string(),
+            display_name: format!("{} {}", exe, title),
+            class: "Editor".to_string(),
+            title: title.to_string(),
+            rect,
+            monitor: None,
+            topmost: false,
+            opacity: None,
+        }
+    }
+
+    fn profile(name: &str) -> LayoutProfile {
+        LayoutProfile {
+            name: name.to_string(),
+            saved_at: 0,
+            windows: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn rename_keeps_other_layouts() {
+        let dir = std::env::temp_dir().join(format!("layout_test_{}", std::process::id()));
+        let store = LayoutStore::new(dir.clone());
+        store.save(&profile("work")).unwrap();
+        store.save(&profile("home")).unwrap();
+
+        assert!(store.rename("work", "home").is_err());
+        assert_eq!(store.rename("work", "office").unwrap().name, "office");
+        assert!(store.load("work").is_err());
+        assert_eq!(store.load("office").unwrap().name, "office");
+
+        //只改大小写：区分大小写的文件系统上 Home 是另一个布局，不能覆盖
+        store.save(&profile("Home")).unwrap();
+        let home = dir.join("Home.json");
+        if !is_same_file(&dir.join("home.json"), &home) {
+            assert!(store.rename("home", "Home").is_err());
+            assert_eq!(store.list().unwrap().len(), 3);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_replaces_layout_file() {
+        let dir = std::env::temp_dir().join(format!("layout_save_test_{}", std::process::id()));
+        let store = LayoutStore::new(dir.clone());
+        store.save(&profile("work")).unwrap();
+        let mut work = profile("work");
+        work.saved_at = 1;
+        store.save(&work).unwrap();
+        assert_eq!(store.list().unwrap(), vec![work]);
+        assert!(!dir.join("work.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn apply_matches_title_first_then_same_exe() {
+        let backend = FakeBackend::new(vec![
+            window(1, "code", "Editor", "a.md"),
+            window(2, "code", "Editor", "b.rs"),
+            window(3, "code", "Editor", "c.txt"),
+            window(4, "code", "Other", "d.md"),
+        ]);
+        let registry = PinRegistry::new();
+        let profile = LayoutProfile {
+            windows: vec![
+                entry("code", "gone.rs", rect(0, 0, 10, 10)),
+                entry("Code", "b.rs", rect(0, 0, 20, 20)),
+                entry("code", "*.md", rect(0, 0, 30, 30)),
+                entry("code", "more.rs", rect(0, 0, 40, 40)),
+                entry("vim", "a.md", rect(0, 0, 50, 50)),
+            ],
+            ..profile("work")
+        };
+        let report = apply(&backend, &registry, &profile, None);
+        assert_eq!(report.applied, 3);
+        assert_eq!(report.missing, vec!["code more.rs", "vim a.md"]);
+        assert!(report.failed.is_empty());
+        //标题匹配的窗口优先，剩下的记录按顺序使用同一程序的其它窗口，类名不同的窗口不使用
+        assert_eq!(backend.window_rect(2), Some(rect(0, 0, 20, 20)));
+        assert_eq!(backend.window_rect(1), Some(rect(0, 0, 30, 30)));
+        assert_eq!(backend.window_rect(3), Some(rect(0, 0, 10, 10)));
+        assert_eq!(backend.window_rect(4), Some(Rect::default()));
+    }
+
+    #[test]
+    fn apply_restores_topmost_and_opacity() {
+        let backend = FakeBackend::new(vec![window(1, "code", "Editor", "a.md")]);
+        backend.set_opacity(1, 1.0).unwrap();
+        let registry = PinRegistry::new();
+        let profile = LayoutProfile {
+            windows: vec![LayoutWindow {
+                topmost: true,
+                opacity: Some(0.5),
+                ..entry("code", "a.md", rect(0, 0, 10, 10))
+            }],
+            ..profile("work")
+        };
+        assert_eq!(apply(&backend, &registry, &profile, None).applied, 1);
+        assert!(backend.is_topmost(1) && registry.is_pinned(1));
+        assert_eq!(backend.opacity(1), Some(0.5));
+    }
+
+    #[test]
+    fn apply_moves_windows_off_missing_monitors() {
+        let backend = FakeBackend::new(vec![window(1, "code", "Editor", "a.md")]);
+        backend.set_monitors(vec![rect(0, 0, 1920, 1080)]);
+        let registry = PinRegistry::new();
+        let profile = LayoutProfile {
+            windows: vec![LayoutWindow {
+                monitor: Some(rect(1920, 0, 3840, 1080)),
+                ..entry("code", "a.md", rect(2000, 100, 2800, 700))
+            }],
+            ..profile("work")
+        };
+        apply(&backend, &registry, &profile, None);
+        assert_eq!(backend.window_rect(1), Some(rect(80, 100, 880, 700)));
+    }
+
+    #[test]
+    fn relocate_keeps_relative_position_inside_target() {
+        let (from, to) = (rect(0, 0, 2560, 1440), rect(-1920, 0, 0, 1080));
+        assert_eq!(
+            relocate(&rect(100, 100, 900, 700), &from, &to),
+            rect(-1820, 100, -1020, 700)
+        );
+        //超出目标显示器时移回显示器内，比显示器大时缩小
+        assert_eq!(
+            relocate(&rect(2000, 1000, 2500, 1400), &from, &to),
+            rect(-500, 680, 0, 1080)
+        );
+        assert_eq!(
+            relocate(&rect(0, 0, 2560, 1440), &from, &to),
+            rect(-1920, 0, 0, 1080)
+        );
+    }
+}
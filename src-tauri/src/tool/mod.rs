@@ -1,3 +1,32 @@
+#[cfg(feature = "webview")]
 pub mod app_tool;
+pub mod border_overlay;
+pub mod cli_tool;
+pub mod daemon_tool;
+pub mod error_tool;
+#[cfg(test)]
+pub mod fake_backend;
+#[cfg(feature = "webview")]
+pub mod hotkey_tool;
+pub mod icon_cache;
+pub mod ipc_tool;
+pub mod layout_profile;
+pub mod pin_registry;
+pub mod rpc_tool;
+pub mod rule_tool;
+pub mod setting_tool;
+pub mod tray_badge;
+#[cfg(feature = "webview")]
+pub mod tray_tool;
+#[cfg(feature = "webview")]
 pub mod update_tool;
+#[cfg(target_os = "linux")]
+pub mod wayland_backend;
+#[cfg(target_os = "windows")]
 pub mod win_app_tool;
+#[cfg(target_os = "windows")]
+pub mod win_overlay;
+pub mod window_backend;
+pub mod window_watcher;
+#[cfg(target_os = "linux")]
+pub mod x11_backend;
@@ -0,0 +1,1086 @@
+use crate::tool::error_tool;
+use crate::tool::window_backend::{self, OpacityStyle, WindowBackend, WindowInfo};
+use crate::tool::window_watcher::{WindowChangeEvent, WindowWatcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 看门狗检查置顶状态的间隔
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+/// 默认的两次恢复置顶之间的最小间隔
+const DEFAULT_MIN_INTERVAL_MS: u64 = 2000;
+
+fn default_min_interval_ms() -> u64 {
+    DEFAULT_MIN_INTERVAL_MS
+}
+
+/// 置顶被其它程序抢占后的处理策略
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "mode")]
+pub enum ReassertPolicy {
+    /// 不处理
+    Never,
+    /// 每次置顶后只恢复一次
+    Once,
+    /// 每次都恢复，两次恢复之间至少间隔 `min_interval_ms`，避免和其它置顶程序来回争抢
+    Always {
+        #[serde(default = "default_min_interval_ms")]
+        min_interval_ms: u64,
+    },
+}
+
+impl Default for ReassertPolicy {
+    fn default() -> Self {
+        ReassertPolicy::Always {
+            min_interval_ms: DEFAULT_MIN_INTERVAL_MS,
+        }
+    }
+}
+
+/// 置顶被抢占的原因
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum ReassertReason {
+    /// 窗口的置顶状态被取消
+    LostTopmost,
+    /// 仍是置顶窗口，但被后置顶的窗口遮挡
+    Obscured,
+}
+
+/// 一次恢复置顶，推送给前端
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ReassertEvent {
+    pub hwnd: isize,
+    pub reason: ReassertReason,
+    /// 该窗口累计恢复的次数
+    pub count: u32,
+}
+
+/// 本程序做出的一次置顶
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PinInfo {
+    pub hwnd: isize,
+    pub pid: u32,
+    /// 程序名
+    pub exe: String,
+    pub display_name: String,
+    pub title: String,
+    /// 窗口类名，崩溃后还原时和程序名一起确认窗口句柄没有被复用
+    #[serde(default)]
+    pub class: String,
+    /// 置顶时间，Unix 时间戳（毫秒）
+    pub pinned_at: u64,
+    /// 置顶前窗口是否已经是置顶窗口，取消置顶时据此还原
+    pub original_topmost: bool,
+    /// 被抢占后恢复置顶的次数
+    pub reasserts: u32,
+    /// 定时置顶的到期时间，Unix 时间戳（毫秒），为空表示一直置顶
+    pub expires_at: Option<u64>,
+    /// 全屏程序在前台时暂时取消了置顶，全屏程序离开后恢复
+    #[serde(default)]
+    pub suspended: bool,
+    /// 置顶期间第一次修改不透明度前的原始样式，取消置顶时据此还原，为空表示未修改过
+    #[serde(default)]
+    pub original_opacity: Option<OpacityStyle>,
+    /// 开启了鼠标穿透，取消置顶时自动关闭
+    #[serde(default)]
+    pub click_through: bool,
+}
+
+/// 当前时间，Unix 时间戳（毫秒）
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// `base` 之后 `secs` 秒的时间戳（毫秒），时长过大时取最大值而不是溢出
+pub fn millis_after(base: u64, secs: u64) -> u64 {
+    base.saturating_add(secs.saturating_mul(1000))
+}
+
+impl PinInfo {
+    pub fn new(window: &WindowInfo, original_topmost: bool) -> Self {
+        PinInfo {
+            hwnd: window.hwnd,
+            pid: window.pid,
+            exe: window.name.clone(),
+            display_name: window.display_name.clone(),
+            title: window.title.clone(),
+            class: window.class.clone(),
+            pinned_at: now_millis(),
+            original_topmost,
+            reasserts: 0,
+            expires_at: None,
+            suspended: false,
+            original_opacity: None,
+            click_through: false,
+        }
+    }
+}
+
+/// 置顶窗口增减时的订阅者
+type ChangeListener = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Debug, Clone)]
+struct PinEntry {
+    info: PinInfo,
+    last_reassert: Option<Instant>,
+}
+
+/// 本程序置顶过的窗口，取消置顶以此为准，不依赖前端的窗口列表
+///
+/// 设置了日志文件时，每次登记变化都会写入磁盘，程序崩溃或被结束后下次启动据此还原
+#[derive(Default)]
+pub struct PinRegistry {
+    pins: Mutex<HashMap<isize, PinEntry>>,
+    journal: Option<PathBuf>,
+    listeners: Mutex<Vec<ChangeListener>>,
+}
+
+impl PinRegistry {
+    pub fn new() -> Self {
+        PinRegistry::default()
+    }
+
+    pub fn with_journal(path: PathBuf) -> Self {
+        PinRegistry {
+            journal: Some(path),
+            ..Default::default()
+        }
+    }
+
+    /// 订阅置顶窗口的增减，登记信息的其它变化不通知
+    pub fn subscribe<F>(&self, listener: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.listeners.lock().unwrap().push(Arc::new(listener));
+    }
+
+    /// 在释放登记锁之后调用，订阅者可以再查询登记；
+    /// 订阅者可能等待主线程，调用时不持有订阅者列表的锁，避免和主线程中的置顶操作互相等待
+    fn notify_changed(&self) {
+        let listeners = self.listeners.lock().unwrap().clone();
+        for listener in listeners {
+            listener();
+        }
+    }
+
+    /// 登记置顶窗口，重复登记时保留第一次置顶前的状态和时间，到期时间以新的登记为准
+    pub fn pin(&self, info: PinInfo) {
+        let mut pins = self.pins.lock().unwrap();
+        let added = !pins.contains_key(&info.hwnd);
+        let info = match pins.remove(&info.hwnd) {
+            Some(old) => PinInfo {
+                pinned_at: old.info.pinned_at,
+                original_topmost: old.info.original_topmost,
+                original_opacity: old.info.original_opacity.or(info.original_opacity),
+                click_through: old.info.click_through || info.click_through,
+                ..info
+            },
+            None => info,
+        };
+        pins.insert(
+            info.hwnd,
+            PinEntry {
+                info,
+                last_reassert: None,
+            },
+        );
+        self.write_journal(&pins);
+        drop(pins);
+        if added {
+            self.notify_changed();
+        }
+    }
+
+    /// 取消登记，返回之前的登记信息
+    pub fn unpin(&self, hwnd: isize) -> Option<PinInfo> {
+        let mut pins = self.pins.lock().unwrap();
+        let removed = pins.remove(&hwnd).map(|e| e.info);
+        if removed.is_some() {
+            self.write_journal(&pins);
+            drop(pins);
+            self.notify_changed();
+        }
+        removed
+    }
+
+    /// 修改定时置顶的到期时间，`expires_at` 为空表示取消定时
+    pub fn set_expiry(&self, hwnd: isize, expires_at: Option<u64>) -> anyhow::Result<()> {
+        let mut pins = self.pins.lock().unwrap();
+        let entry = pins
+            .get_mut(&hwnd)
+            .ok_or_else(|| anyhow::anyhow!("窗口未被置顶：{}", hwnd))?;
+        entry.info.expires_at = expires_at;
+        self.write_journal(&pins);
+        Ok(())
+    }
+
+    /// 延长定时置顶，未设置定时的窗口从现在开始计时，返回新的到期时间
+    pub fn extend_expiry(&self, hwnd: isize, secs: u64) -> anyhow::Result<u64> {
+        let mut pins = self.pins.lock().unwrap();
+        let entry = pins
+            .get_mut(&hwnd)
+            .ok_or_else(|| anyhow::anyhow!("窗口未被置顶：{}", hwnd))?;
+        let base = entry.info.expires_at.unwrap_or_else(now_millis);
+        let expires_at = millis_after(base, secs);
+        entry.info.expires_at = Some(expires_at);
+        self.write_journal(&pins);
+        Ok(expires_at)
+    }
+
+    /// 窗口定时置顶的到期时间
+    pub fn expiry(&self, hwnd: isize) -> Option<u64> {
+        let pins = self.pins.lock().unwrap();
+        pins.get(&hwnd).and_then(|e| e.info.expires_at)
+    }
+
+    /// 用登记信息补全窗口的定时置顶到期时间、暂停和鼠标穿透状态
+    pub fn decorate(&self, window: &mut WindowInfo) {
+        let pins = self.pins.lock().unwrap();
+        let info = pins.get(&window.hwnd).map(|e| &e.info);
+        window.pin_expires_at = info.and_then(|info| info.expires_at);
+        window.pin_suspended = info.is_some_and(|info| info.suspended);
+        window.pin_click_through = info.is_some_and(|info| info.click_through);
+    }
+
+    /// 标记是否开启了鼠标穿透
+    pub fn set_click_through(&self, hwnd: isize, click_through: bool) {
+        let mut pins = self.pins.lock().unwrap();
+        if let Some(entry) = pins.get_mut(&hwnd) {
+            entry.info.click_through = click_through;
+            self.write_journal(&pins);
+        }
+    }
+
+    /// 开启了鼠标穿透的窗口
+    pub fn click_through_hwnds(&self) -> Vec<isize> {
+        let pins = self.pins.lock().unwrap();
+        let mut hwnds: Vec<isize> = pins
+            .values()
+            .filter(|e| e.info.click_through)
+            .map(|e| e.info.hwnd)
+            .collect();
+        hwnds.sort();
+        hwnds
+    }
+
+    /// 标记置顶是否因全屏程序而暂停
+    pub fn set_suspended(&self, hwnd: isize, suspended: bool) {
+        let mut pins = self.pins.lock().unwrap();
+        if let Some(entry) = pins.get_mut(&hwnd) {
+            entry.info.suspended = suspended;
+            self.write_journal(&pins);
+        }
+    }
+
+    /// 记录置顶窗口修改不透明度前的样式，已记录过或窗口未置顶时忽略
+    pub fn remember_opacity(&self, hwnd: isize, style: Option<OpacityStyle>) {
+        let mut pins = self.pins.lock().unwrap();
+        if let Some(entry) = pins.get_mut(&hwnd) {
+            if entry.info.original_opacity.is_none() && style.is_some() {
+                entry.info.original_opacity = style;
+                self.write_journal(&pins);
+            }
+        }
+    }
+
+    /// 取消登记所有已到期的定时置顶
+    pub fn take_expired(&self, now: u64) -> Vec<PinInfo> {
+        self.take_where(|info| info.expires_at.is_some_and(|t| t <= now))
+    }
+
+    pub fn is_pinned(&self, hwnd: isize) -> bool {
+        self.pins.lock().unwrap().contains_key(&hwnd)
+    }
+
+    pub fn hwnds(&self) -> Vec<isize> {
+        let mut hwnds: Vec<isize> = self.pins.lock().unwrap().keys().copied().collect();
+        hwnds.sort();
+        hwnds
+    }
+
+    /// 所有置顶，按置顶时间排序
+    pub fn list(&self) -> Vec<PinInfo> {
+        let pins = self.pins.lock().unwrap();
+        let mut list: Vec<PinInfo> = pins.values().map(|e| e.info.clone()).collect();
+        list.sort_by_key(|p| (p.pinned_at, p.hwnd));
+        list
+    }
+
+    /// 按置顶顺序排在 `hwnd` 之后的置顶窗口，到末尾后回到第一个；`hwnd` 未置顶时返回第一个
+    pub fn next_after(&self, hwnd: Option<isize>) -> Option<isize> {
+        let list = self.list();
+        let next = hwnd
+            .and_then(|hwnd| list.iter().position(|p| p.hwnd == hwnd))
+            .map_or(0, |index| (index + 1) % list.len());
+        list.get(next).map(|p| p.hwnd)
+    }
+
+    /// 取消登记所有窗口
+    pub fn take_all(&self) -> Vec<PinInfo> {
+        self.take_where(|_| true)
+    }
+
+    /// 取消登记某个进程的所有窗口
+    pub fn take_by_process(&self, pid: u32) -> Vec<PinInfo> {
+        self.take_where(|info| info.pid == pid)
+    }
+
+    fn take_where<F>(&self, predicate: F) -> Vec<PinInfo>
+    where
+        F: Fn(&PinInfo) -> bool,
+    {
+        let mut pins = self.pins.lock().unwrap();
+        let hwnds: Vec<isize> = pins
+            .values()
+            .filter(|e| predicate(&e.info))
+            .map(|e| e.info.hwnd)
+            .collect();
+        let mut taken: Vec<PinInfo> = hwnds
+            .iter()
+            .filter_map(|hwnd| pins.remove(hwnd))
+            .map(|e| e.info)
+            .collect();
+        taken.sort_by_key(|p| (p.pinned_at, p.hwnd));
+        if !taken.is_empty() {
+            self.write_journal(&pins);
+            drop(pins);
+            self.notify_changed();
+        }
+        taken
+    }
+
+    /// 删除日志文件，保留置顶退出时调用，下次启动不再还原这些窗口
+    ///
+    /// 仍开启鼠标穿透（关闭失败）的窗口保留在日志中，下次启动时再关闭
+    pub fn discard_journal(&self) {
+        let Some(path) = &self.journal else {
+            return;
+        };
+        let pins = self.pins.lock().unwrap();
+        let click_through: HashMap<isize, PinEntry> = pins
+            .iter()
+            .filter(|(_, e)| e.info.click_through)
+            .map(|(hwnd, e)| (*hwnd, e.clone()))
+            .collect();
+        if click_through.is_empty() {
+            let _ = std::fs::remove_file(path);
+        } else {
+            self.write_journal(&click_through);
+        }
+    }
+
+    fn write_journal(&self, pins: &HashMap<isize, PinEntry>) {
+        let Some(path) = &self.journal else {
+            return;
+        };
+        let list: Vec<&PinInfo> = pins.values().map(|e| &e.info).collect();
+        //先写临时文件再替换，写到一半崩溃时不会留下残缺的日志
+        let tmp = path.with_extension("tmp");
+        let result = serde_json::to_vec(&list)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(std::fs::write(&tmp, bytes)?))
+            .and_then(|()| Ok(std::fs::rename(&tmp, path)?));
+        if let Err(e) = result {
+            error_tool::report_error(format!("写入置顶日志失败：{}", e));
+        }
+    }
+
+    /// 按策略判断能否恢复置顶，可以时记录本次恢复并返回累计次数
+    fn begin_reassert(&self, hwnd: isize, policy: ReassertPolicy, now: Instant) -> Option<u32> {
+        let mut pins = self.pins.lock().unwrap();
+        let entry = pins.get_mut(&hwnd)?;
+        //暂停期间是有意取消的置顶，不算被抢占
+        if entry.info.suspended {
+            return None;
+        }
+        let allowed = match policy {
+            ReassertPolicy::Never => false,
+            ReassertPolicy::Once => entry.info.reasserts == 0,
+            ReassertPolicy::Always { min_interval_ms } => entry
+                .last_reassert
+                .is_none_or(|last| now - last >= Duration::from_millis(min_interval_ms)),
+        };
+        if !allowed {
+            return None;
+        }
+        entry.info.reasserts += 1;
+        entry.last_reassert = Some(now);
+        Some(entry.info.reasserts)
+    }
+}
+
+/// 把已取消登记的窗口还原为置顶前的状态，单个窗口失败不影响其它窗口，返回最后一个错误
+pub fn restore_pins(backend: &dyn WindowBackend, pins: &[PinInfo]) -> anyhow::Result<()> {
+    let mut result = Ok(());
+    for pin in pins {
+        if let Err(e) = restore_style(backend, pin) {
+            result = Err(e);
+        }
+        //原本就置顶的窗口保持不变，除非暂停时被取消了置顶
+        if pin.original_topmost && !pin.suspended {
+            continue;
+        }
+        if let Err(e) = backend.set_topmost(pin.hwnd, pin.original_topmost) {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+/// 关闭鼠标穿透并还原不透明度，先关闭穿透，Windows 下还原样式时才能去掉分层属性
+fn restore_style(backend: &dyn WindowBackend, pin: &PinInfo) -> anyhow::Result<()> {
+    if pin.click_through {
+        backend.set_click_through(pin.hwnd, false)?;
+    }
+    if let Some(style) = &pin.original_opacity {
+        backend.restore_opacity(pin.hwnd, style)?;
+    }
+    Ok(())
+}
+
+/// 读取上次运行留下的置顶日志并删除，`restore` 为 true 时把其中的窗口还原为置顶前的状态，
+/// 否则只关闭遗留的鼠标穿透
+///
+/// 窗口句柄可能已被系统复用，只还原进程 id、程序名和类名都仍然一致的窗口，返回还原的窗口数；
+/// 进程 id 未知（为 0）的窗口无法确认，不还原。
+/// 日志无法解析时改名为 `.bad` 保留下来并返回错误
+pub fn recover_journal(
+    backend: &dyn WindowBackend,
+    path: &Path,
+    restore: bool,
+) -> anyhow::Result<usize> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let bytes = std::fs::read(path)?;
+    let pins: Vec<PinInfo> = match serde_json::from_slice(&bytes) {
+        Ok(pins) => pins,
+        Err(e) => {
+            //移到一边，避免本次运行写入的日志覆盖它
+            let bad = path.with_extension("bad");
+            std::fs::rename(path, &bad)?;
+            anyhow::bail!("置顶日志无法解析，已保留为 {}：{}", bad.display(), e);
+        }
+    };
+    std::fs::remove_file(path)?;
+    let alive: Vec<PinInfo> = pins
+        .into_iter()
+        .filter(|pin| {
+            backend.window_info(pin.hwnd).is_some_and(|window| {
+                pin.pid != 0
+                    && window.pid == pin.pid
+                    && window.name == pin.exe
+                    && window.class == pin.class
+            })
+        })
+        .collect();
+    if !restore {
+        //保留置顶，但开启了穿透的窗口无法点击，必须关闭
+        for pin in alive.iter().filter(|pin| pin.click_through) {
+            backend.set_click_through(pin.hwnd, false)?;
+        }
+        return Ok(0);
+    }
+    restore_pins(backend, &alive)?;
+    Ok(alive.iter().filter(|pin| !pin.original_topmost).count())
+}
+
+/// 置顶或取消置顶窗口并同步登记，`expires_at` 为定时置顶的到期时间
+///
+/// 置顶走 `show_and_topmost`（还原、请求前台并让本程序窗口 `hwnd_self` 保持在最上层），
+/// 取消走 `show_and_not_topmost`
+pub fn set_pinned(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    hwnd: isize,
+    enable: bool,
+    expires_at: Option<u64>,
+    hwnd_self: Option<isize>,
+) -> anyhow::Result<()> {
+    //先取消登记，避免看门狗把刚取消的置顶又恢复
+    let unpinned = if enable { None } else { registry.unpin(hwnd) };
+    let original_topmost = backend.is_topmost(hwnd);
+    if let Err(e) = window_backend::toggle_topmost(backend, hwnd, enable, hwnd_self) {
+        //没能取消置顶，窗口仍然置顶，恢复登记
+        if let Some(pin) = unpinned {
+            registry.pin(pin);
+        }
+        return Err(e);
+    }
+    if let Some(pin) = &unpinned {
+        restore_style(backend, pin)?;
+    }
+    if enable {
+        let window = backend.window_info(hwnd).unwrap_or(WindowInfo {
+            hwnd,
+            ..Default::default()
+        });
+        registry.pin(PinInfo {
+            expires_at,
+            ..PinInfo::new(&window, original_topmost)
+        });
+    }
+    Ok(())
+}
+
+/// 修改窗口的不透明度，结果限制在 `MIN_OPACITY`~1.0，返回实际设置的值
+///
+/// 置顶窗口第一次修改时记录原始样式，取消置顶或退出时还原
+pub fn set_opacity(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    hwnd: isize,
+    opacity: f64,
+) -> anyhow::Result<f64> {
+    let opacity = window_backend::clamp_opacity(opacity);
+    if registry.is_pinned(hwnd) {
+        registry.remember_opacity(hwnd, backend.opacity_style(hwnd));
+    }
+    backend.set_opacity(hwnd, opacity)?;
+    Ok(opacity)
+}
+
+/// 开启或关闭鼠标穿透，只能对本程序置顶的窗口开启，取消置顶或退出时自动关闭
+pub fn set_click_through(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    hwnd: isize,
+    enable: bool,
+) -> anyhow::Result<()> {
+    if enable {
+        if !registry.is_pinned(hwnd) {
+            return Err(anyhow::anyhow!("只能对置顶的窗口开启鼠标穿透"));
+        }
+        //Windows 下穿透需要改为分层窗口，先记录原始样式，取消置顶时一并还原
+        registry.remember_opacity(hwnd, backend.opacity_style(hwnd));
+    }
+    backend.set_click_through(hwnd, enable)?;
+    registry.set_click_through(hwnd, enable);
+    Ok(())
+}
+
+/// 关闭所有窗口的鼠标穿透，窗口无法点击时的应急出口，返回关闭的窗口数
+pub fn clear_click_through(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+) -> anyhow::Result<usize> {
+    let hwnds = registry.click_through_hwnds();
+    let mut result = Ok(hwnds.len());
+    for hwnd in hwnds {
+        if let Err(e) = set_click_through(backend, registry, hwnd, false) {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+/// 取消本程序做出的所有置顶
+pub fn unpin_all(backend: &dyn WindowBackend, registry: &PinRegistry) -> anyhow::Result<()> {
+    restore_pins(backend, &registry.take_all())
+}
+
+/// 取消列表中窗口的置顶，本程序置顶的窗口同时还原样式；单个窗口失败不影响其它窗口，返回最后一个错误
+pub fn unpin_windows(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    hwnds: &[isize],
+) -> anyhow::Result<()> {
+    let mut result = Ok(());
+    for &hwnd in hwnds {
+        if let Err(e) = set_pinned(backend, registry, hwnd, false, None, None) {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+/// 退出时处理本程序做出的置顶：`restore` 为 true 时全部还原，否则保留置顶
+///
+/// 无论哪种策略都关闭鼠标穿透，否则退出后这些窗口再也无法点击
+pub fn release_on_exit(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    restore: bool,
+) -> anyhow::Result<()> {
+    if restore {
+        return unpin_all(backend, registry);
+    }
+    let result = clear_click_through(backend, registry).map(|_| ());
+    registry.discard_journal();
+    result
+}
+
+/// 看门狗产生的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum PinEvent {
+    /// 置顶被抢占后已恢复
+    Reasserted(ReassertEvent),
+    /// 定时置顶到期，已取消置顶
+    Expired(PinInfo),
+    /// 全屏程序进入前台，同一显示器上的置顶已暂停
+    Suspended(FullscreenEvent),
+    /// 全屏程序已离开，暂停的置顶已恢复
+    Resumed(FullscreenEvent),
+}
+
+impl PinEvent {
+    /// 推送给前端和订阅者的事件名
+    pub fn name(&self) -> &'static str {
+        match self {
+            PinEvent::Reasserted(_) => "pin_reasserted",
+            PinEvent::Expired(_) => "pin_expired",
+            PinEvent::Suspended(_) => "pins_suspended",
+            PinEvent::Resumed(_) => "pins_resumed",
+        }
+    }
+
+    /// 事件数据
+    pub fn payload(&self) -> serde_json::Result<serde_json::Value> {
+        match self {
+            PinEvent::Reasserted(event) => serde_json::to_value(event),
+            PinEvent::Expired(pin) => serde_json::to_value(pin),
+            PinEvent::Suspended(event) | PinEvent::Resumed(event) => serde_json::to_value(event),
+        }
+    }
+}
+
+/// 置顶因全屏程序暂停或恢复，推送给前端
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FullscreenEvent {
+    /// 全屏窗口，无法确定时为空
+    pub fullscreen: Option<isize>,
+    /// 暂停或恢复的置顶窗口
+    pub hwnds: Vec<isize>,
+}
+
+type PinListener = Box<dyn Fn(&PinEvent) + Send + Sync>;
+
+/// 置顶看门狗
+///
+/// 定时检查已登记的窗口：定时置顶到期时取消置顶，全屏程序在前台时暂停同一显示器上的置顶，
+/// 置顶状态丢失或被其它置顶窗口遮挡时按策略重新置顶。
+/// 运行在后台线程中，与主窗口是否显示无关
+pub struct PinWatchdog {
+    backend: Arc<dyn WindowBackend>,
+    registry: Arc<PinRegistry>,
+    policy: Mutex<ReassertPolicy>,
+    suspend_on_fullscreen: AtomicBool,
+    /// 当前导致置顶暂停的全屏窗口
+    fullscreen: Mutex<Option<isize>>,
+    on_event: PinListener,
+}
+
+impl PinWatchdog {
+    pub fn new<F>(
+        backend: Arc<dyn WindowBackend>,
+        registry: Arc<PinRegistry>,
+        policy: ReassertPolicy,
+        on_event: F,
+    ) -> Self
+    where
+        F: Fn(&PinEvent) + Send + Sync + 'static,
+    {
+        PinWatchdog {
+            backend,
+            registry,
+            policy: Mutex::new(policy),
+            suspend_on_fullscreen: AtomicBool::new(false),
+            fullscreen: Mutex::new(None),
+            on_event: Box::new(on_event),
+        }
+    }
+
+    /// 创建并在后台线程中启动看门狗，窗口关闭时自动取消登记，仅隐藏时保留
+    pub fn start<F>(
+        backend: Arc<dyn WindowBackend>,
+        registry: Arc<PinRegistry>,
+        watcher: &WindowWatcher,
+        policy: ReassertPolicy,
+        on_event: F,
+    ) -> Arc<Self>
+    where
+        F: Fn(&PinEvent) + Send + Sync + 'static,
+    {
+        let watchdog = Arc::new(PinWatchdog::new(backend, registry, policy, on_event));
+        let worker = watchdog.clone();
+        watcher.subscribe(move |event| {
+            if let WindowChangeEvent::Removed { hwnd } = event {
+                worker.forget_closed(*hwnd);
+            }
+            true
+        });
+
+        let worker = watchdog.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(WATCHDOG_INTERVAL);
+            worker.check_expired(now_millis());
+            worker.check_fullscreen();
+            worker.check();
+        });
+        watchdog
+    }
+
+    /// 窗口从列表中移除后，只有确实已关闭时才取消登记；隐藏到托盘的窗口再次显示时仍保持置顶
+    pub fn forget_closed(&self, hwnd: isize) -> Option<PinInfo> {
+        if self.backend.window_exists(hwnd) {
+            return None;
+        }
+        self.registry.unpin(hwnd)
+    }
+
+    pub fn set_policy(&self, policy: ReassertPolicy) {
+        *self.policy.lock().unwrap() = policy;
+    }
+
+    /// 设置全屏程序在前台时是否暂停置顶，关闭时立即恢复已暂停的置顶
+    pub fn set_suspend_on_fullscreen(&self, enable: bool) {
+        self.suspend_on_fullscreen.store(enable, Ordering::Relaxed);
+        if !enable {
+            self.check_fullscreen();
+        }
+    }
+
+    /// 前台窗口铺满所在显示器时，暂停该显示器上的置顶；全屏窗口离开后恢复，返回本次的事件
+    ///
+    /// 置顶窗口自己全屏时不算
+    pub fn check_fullscreen(&self) -> Vec<PinEvent> {
+        let backend = self.backend.as_ref();
+        let fullscreen = if self.suspend_on_fullscreen.load(Ordering::Relaxed) {
+            backend.foreground_window().filter(|hwnd| {
+                !self.registry.is_pinned(*hwnd) && window_backend::is_fullscreen(backend, *hwnd)
+            })
+        } else {
+            None
+        };
+        let monitor = fullscreen.and_then(|hwnd| backend.monitor_rect(hwnd));
+
+        let (mut suspended, mut resumed) = (Vec::new(), Vec::new());
+        for pin in self.registry.list() {
+            let covered = monitor.is_some() && backend.monitor_rect(pin.hwnd) == monitor;
+            if covered == pin.suspended {
+                continue;
+            }
+            if let Err(e) = backend.set_topmost(pin.hwnd, !covered) {
+                error_tool::report_error(format!("切换全屏暂停置顶失败：{}", e));
+                continue;
+            }
+            self.registry.set_suspended(pin.hwnd, covered);
+            if covered {
+                suspended.push(pin.hwnd);
+            } else {
+                resumed.push(pin.hwnd);
+            }
+        }
+
+        let mut previous = self.fullscreen.lock().unwrap();
+        let mut events = Vec::new();
+        if !resumed.is_empty() {
+            events.push(PinEvent::Resumed(FullscreenEvent {
+                fullscreen: *previous,
+                hwnds: resumed,
+            }));
+        }
+        if !suspended.is_empty() {
+            events.push(PinEvent::Suspended(FullscreenEvent {
+                fullscreen,
+                hwnds: suspended,
+            }));
+        }
+        *previous = fullscreen;
+        drop(previous);
+        for event in &events {
+            (self.on_event)(event);
+        }
+        events
+    }
+
+    /// 取消所有已到期的定时置顶，返回到期的置顶
+    pub fn check_expired(&self, now: u64) -> Vec<PinInfo> {
+        let expired = self.registry.take_expired(now);
+        if let Err(e) = restore_pins(self.backend.as_ref(), &expired) {
+            error_tool::report_error(format!("定时置顶到期后取消置顶失败：{}", e));
+        }
+        for pin in &expired {
+            (self.on_event)(&PinEvent::Expired(pin.clone()));
+        }
+        expired
+    }
+
+    /// 检查一遍所有登记的窗口，返回本次恢复置顶的事件
+    pub fn check(&self) -> Vec<ReassertEvent> {
+        let policy = *self.policy.lock().unwrap();
+        if policy == ReassertPolicy::Never {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        //本程序置顶的窗口之间互相遮挡时不争抢
+        let hwnds = self.registry.hwnds();
+        for &hwnd in &hwnds {
+            let reason = if !self.backend.is_topmost(hwnd) {
+                ReassertReason::LostTopmost
+            } else if self.backend.is_obscured(hwnd, &hwnds) {
+                ReassertReason::Obscured
+            } else {
+                continue;
+            };
+            let Some(count) = self.registry.begin_reassert(hwnd, policy, Instant::now()) else {
+                continue;
+            };
+            if let Err(e) = self.backend.set_topmost(hwnd, true) {
+                error_tool::report_error(format!("恢复置顶失败：{}", e));
+                continue;
+            }
+            let event = ReassertEvent {
+                hwnd,
+                reason,
+                count,
+            };
+            (self.on_event)(&PinEvent::Reasserted(event.clone()));
+            events.push(event);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::fake_backend::FakeBackend;
+
+    fn window(hwnd: isize, pid: u32, topmost: bool) -> WindowInfo {
+        WindowInfo {
+            hwnd,
+            pid,
+            name: format!("app{}", hwnd),
+            is_top_most: topmost,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn set_pinned_registers_and_unregisters() {
+        let backend = FakeBackend::new(vec![window(1, 10, false), window(9, 99, false)]);
+        let registry = PinRegistry::new();
+        set_pinned(&backend, &registry, 1, true, Some(5000), Some(9)).unwrap();
+        assert!(backend.is_topmost(1));
+        assert!(backend.is_topmost(9));
+        let pins = registry.list();
+        assert_eq!(pins.len(), 1);
+        assert_eq!(pins[0].exe, "app1");
+        assert_eq!(pins[0].expires_at, Some(5000));
+        assert!(!pins[0].original_topmost);
+
+        set_pinned(&backend, &registry, 1, false, None, Some(9)).unwrap();
+        assert!(!backend.is_topmost(1));
+        assert!(!registry.is_pinned(1));
+        assert!(set_pinned(&backend, &registry, 2, true, None, None).is_err());
+        assert!(!registry.is_pinned(2));
+
+        //取消置顶失败时保留登记
+        set_pinned(&backend, &registry, 1, true, Some(5000), None).unwrap();
+        backend.remove_window(1);
+        assert!(set_pinned(&backend, &registry, 1, false, None, None).is_err());
+        assert_eq!(registry.list()[0].expires_at, Some(5000));
+    }
+
+    #[test]
+    fn unpin_all_restores_original_state() {
+        let backend = FakeBackend::new(vec![window(1, 10, false), window(2, 20, true)]);
+        let registry = PinRegistry::new();
+        for hwnd in [1, 2] {
+            set_pinned(&backend, &registry, hwnd, true, None, None).unwrap();
+        }
+        backend.set_opacity(1, 1.0).unwrap();
+        set_opacity(&backend, &registry, 1, 0.5).unwrap();
+        unpin_all(&backend, &registry).unwrap();
+        assert!(!backend.is_topmost(1));
+        //原本就置顶的窗口保持置顶
+        assert!(backend.is_topmost(2));
+        assert_eq!(backend.opacity(1), Some(1.0));
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn unpin_windows_includes_foreign_topmost() {
+        let backend = FakeBackend::new(vec![
+            window(1, 10, false),
+            window(2, 20, true),
+            window(3, 30, false),
+        ]);
+        let registry = PinRegistry::new();
+        for hwnd in [1, 3] {
+            set_pinned(&backend, &registry, hwnd, true, None, None).unwrap();
+        }
+        //列表中不存在的窗口报错，其它窗口照常处理
+        assert!(unpin_windows(&backend, &registry, &[1, 2, 5]).is_err());
+        assert!(!backend.is_topmost(1) && !backend.is_topmost(2));
+        assert!(backend.is_topmost(3));
+        assert_eq!(registry.hwnds(), vec![3]);
+    }
+
+    #[test]
+    fn pins_do_not_obscure_each_other() {
+        let backend = Arc::new(FakeBackend::new(vec![
+            window(1, 10, false),
+            window(2, 20, false),
+            window(3, 30, true),
+        ]));
+        let registry = Arc::new(PinRegistry::new());
+        for hwnd in [1, 2] {
+            set_pinned(backend.as_ref(), &registry, hwnd, true, None, None).unwrap();
+        }
+        let watchdog = PinWatchdog::new(
+            backend.clone(),
+            registry,
+            ReassertPolicy::Always { min_interval_ms: 0 },
+            |_| {},
+        );
+        backend.set_obscured(1, Some(2)).unwrap();
+        assert!(watchdog.check().is_empty());
+        backend.set_obscured(1, Some(3)).unwrap();
+        let events = watchdog.check();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].reason, ReassertReason::Obscured);
+    }
+
+    #[test]
+    fn forget_closed_keeps_existing_windows() {
+        let backend = Arc::new(FakeBackend::new(vec![window(1, 10, false)]));
+        let registry = Arc::new(PinRegistry::new());
+        set_pinned(backend.as_ref(), &registry, 1, true, None, None).unwrap();
+        let watchdog = PinWatchdog::new(
+            backend.clone(),
+            registry.clone(),
+            ReassertPolicy::Never,
+            |_| {},
+        );
+        assert!(watchdog.forget_closed(1).is_none());
+        assert!(registry.is_pinned(1));
+        backend.remove_window(1);
+        assert_eq!(watchdog.forget_closed(1).map(|pin| pin.hwnd), Some(1));
+        assert!(!registry.is_pinned(1));
+    }
+
+    #[test]
+    fn recover_journal_keeps_corrupt_file() {
+        let dir = std::env::temp_dir().join(format!("pin_journal_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".pin_journal.json");
+        let backend = FakeBackend::new(vec![window(1, 10, false)]);
+
+        let registry = PinRegistry::with_journal(path.clone());
+        set_pinned(&backend, &registry, 1, true, None, None).unwrap();
+        assert!(path.exists());
+        assert!(!path.with_extension("tmp").exists());
+        assert_eq!(recover_journal(&backend, &path, true).unwrap(), 1);
+        assert!(!backend.is_topmost(1));
+
+        std::fs::write(&path, b"[{\"hwnd\":").unwrap();
+        assert!(recover_journal(&backend, &path, true).is_err());
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read(path.with_extension("bad")).unwrap(),
+            b"[{\"hwnd\":"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recover_journal_skips_unconfirmed_windows() {
+        let dir = std::env::temp_dir().join(format!("pin_recover_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".pin_journal.json");
+        let backend = FakeBackend::new(vec![
+            window(1, 10, false),
+            window(2, 0, false),
+            window(3, 30, false),
+            window(4, 40, false),
+        ]);
+        {
+            let registry = PinRegistry::with_journal(path.clone());
+            for hwnd in [1, 2, 3, 4] {
+                set_pinned(&backend, &registry, hwnd, true, None, None).unwrap();
+            }
+        }
+        //句柄被同一进程 id 的其它程序、其它类名的窗口复用
+        backend.add_window(WindowInfo {
+            name: "other".to_string(),
+            ..window(3, 30, true)
+        });
+        backend.add_window(WindowInfo {
+            class: "Other".to_string(),
+            ..window(4, 40, true)
+        });
+        assert_eq!(recover_journal(&backend, &path, true).unwrap(), 1);
+        assert!(!backend.is_topmost(1));
+        assert!(backend.is_topmost(2) && backend.is_topmost(3) && backend.is_topmost(4));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn expiry_saturates_instead_of_overflowing() {
+        assert_eq!(millis_after(5000, 2), 7000);
+        assert_eq!(millis_after(5000, u64::MAX), u64::MAX);
+        let backend = FakeBackend::new(vec![window(1, 10, false)]);
+        let registry = PinRegistry::new();
+        set_pinned(&backend, &registry, 1, true, Some(1000), None).unwrap();
+        assert_eq!(registry.extend_expiry(1, u64::MAX).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn click_through_only_on_pinned_windows() {
+        let backend = FakeBackend::new(vec![window(1, 10, false), window(2, 20, false)]);
+        let registry = PinRegistry::new();
+        assert!(set_click_through(&backend, &registry, 1, true).is_err());
+        assert!(!backend.is_click_through(1));
+        for hwnd in [1, 2] {
+            set_pinned(&backend, &registry, hwnd, true, None, None).unwrap();
+            set_click_through(&backend, &registry, hwnd, true).unwrap();
+        }
+        assert_eq!(registry.click_through_hwnds(), vec![1, 2]);
+
+        assert_eq!(clear_click_through(&backend, &registry).unwrap(), 2);
+        assert!(!backend.is_click_through(1) && !backend.is_click_through(2));
+        assert!(registry.click_through_hwnds().is_empty());
+        //仍然保持置顶
+        assert!(registry.is_pinned(1) && backend.is_topmost(1));
+    }
+
+    #[test]
+    fn exit_always_clears_click_through() {
+        let dir = std::env::temp_dir().join(format!("pin_exit_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".pin_journal.json");
+        let backend = FakeBackend::new(vec![window(1, 10, false), window(2, 20, false)]);
+
+        //保留置顶退出：穿透关闭，置顶保留，日志删除
+        let registry = PinRegistry::with_journal(path.clone());
+        set_pinned(&backend, &registry, 1, true, None, None).unwrap();
+        set_click_through(&backend, &registry, 1, true).unwrap();
+        release_on_exit(&backend, &registry, false).unwrap();
+        assert!(!backend.is_click_through(1));
+        assert!(backend.is_topmost(1));
+        assert!(!path.exists());
+
+        //关闭失败的穿透留在日志中
+        let registry = PinRegistry::with_journal(path.clone());
+        set_pinned(&backend, &registry, 2, true, None, None).unwrap();
+        registry.set_click_through(2, true);
+        registry.discard_journal();
+        assert!(path.exists());
+
+        //异常退出后以保留置顶的策略启动，仍然关闭遗留的穿透
+        backend.set_click_through(2, true).unwrap();
+        assert_eq!(recover_journal(&backend, &path, false).unwrap(), 0);
+        assert!(!backend.is_click_through(2));
+        assert!(backend.is_topmost(2));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,475 @@
+use crate::tool::error_tool;
+use crate::tool::ipc_tool::{self, IpcPeer};
+use crate::tool::pin_registry::{self, PinRegistry};
+use crate::tool::window_backend::{self, WindowBackend};
+use crate::tool::window_watcher::WindowWatcher;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+/// JSON-RPC 控制接口的进程间通信端点名称
+pub const RPC_ENDPOINT: &str = "rpc";
+
+/// 窗口出现、消失或变化，事件名与前端收到的窗口增量事件相同（`Added`/`Removed`/`Changed`）
+pub const TOPIC_WINDOWS: &str = "windows";
+/// 置顶变化（`pins_changed`）以及看门狗事件（`pin_reasserted`、`pin_expired` 等）
+pub const TOPIC_PINS: &str = "pins";
+pub const TOPICS: [&str; 2] = [TOPIC_WINDOWS, TOPIC_PINS];
+
+/// 推送事件使用的通知方法名
+pub const EVENT_METHOD: &str = "event";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// 方法执行失败，如窗口不存在
+pub const SERVER_ERROR: i64 = -32000;
+
+/// JSON-RPC 错误对象
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl std::fmt::Display) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        RpcError::new(METHOD_NOT_FOUND, format!("方法不存在：{}", method))
+    }
+}
+
+/// 把请求参数解析为 `T`，没有参数时按空对象解析
+pub fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+/// 把方法的返回值转换为结果，错误信息作为 `SERVER_ERROR`
+pub fn result<T: Serialize, E: std::fmt::Display>(result: Result<T, E>) -> Result<Value, RpcError> {
+    let value = result.map_err(|e| RpcError::new(SERVER_ERROR, e))?;
+    serde_json::to_value(value).map_err(|e| RpcError::new(SERVER_ERROR, e))
+}
+
+/// 事件订阅，把事件推送给订阅了对应主题的连接
+#[derive(Default)]
+pub struct EventHub {
+    subscribers: Mutex<Vec<(IpcPeer, BTreeSet<String>)>>,
+}
+
+impl EventHub {
+    /// 订阅主题，`topics` 为空时订阅全部，返回该连接当前订阅的所有主题
+    pub fn subscribe(&self, peer: &IpcPeer, topics: &[String]) -> Result<Vec<String>, RpcError> {
+        if let Some(topic) = topics.iter().find(|t| !TOPICS.contains(&t.as_str())) {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("主题不存在：{}，可用的主题：{}", topic, TOPICS.join("、")),
+            ));
+        }
+        let topics: Vec<String> = if topics.is_empty() {
+            TOPICS.iter().map(|t| t.to_string()).collect()
+        } else {
+            topics.to_vec()
+        };
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let index = match subscribers.iter().position(|(p, _)| p.id() == peer.id()) {
+            Some(index) => index,
+            None => {
+                subscribers.push((peer.clone(), BTreeSet::new()));
+                subscribers.len() - 1
+            }
+        };
+        subscribers[index].1.extend(topics);
+        Ok(subscribers[index].1.iter().cloned().collect())
+    }
+
+    /// 取消订阅，`topics` 为空时取消全部
+    pub fn unsubscribe(&self, peer: &IpcPeer, topics: &[String]) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        for (p, subscribed) in subscribers.iter_mut() {
+            if p.id() == peer.id() {
+                subscribed.retain(|t| !topics.is_empty() && !topics.contains(t));
+            }
+        }
+        subscribers.retain(|(_, subscribed)| !subscribed.is_empty());
+    }
+
+    /// 是否有连接订阅了 `topic`，没有时可以跳过准备事件数据
+    pub fn has_subscribers(&self, topic: &str) -> bool {
+        let subscribers = self.subscribers.lock().unwrap();
+        subscribers.iter().any(|(_, topics)| topics.contains(topic))
+    }
+
+    /// 推送事件，连接已断开的订阅会被移除
+    pub fn publish(&self, topic: &str, event: &str, data: Value) {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": EVENT_METHOD,
+            "params": { "topic": topic, "event": event, "data": data },
+        });
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers
+            .retain(|(peer, topics)| !topics.contains(topic) || peer.send(notification.clone()));
+    }
+}
+
+/// 处理一行 JSON-RPC 2.0 消息（单个请求或批量请求），通知（没有 id）不返回响应
+///
+/// `subscribe`/`unsubscribe` 在这里处理，其它方法交给 `dispatch`
+pub fn handle_message(
+    message: &str,
+    peer: &IpcPeer,
+    hub: &EventHub,
+    dispatch: &dyn Fn(&str, Value) -> Result<Value, RpcError>,
+) -> Option<Value> {
+    let message: Value = match serde_json::from_str(message) {
+        Ok(message) => message,
+        Err(e) => return Some(error_response(Value::Null, RpcError::new(PARSE_ERROR, e))),
+    };
+    match message {
+        Value::Array(requests) if requests.is_empty() => Some(error_response(
+            Value::Null,
+            RpcError::new(INVALID_REQUEST, "批量请求不能为空"),
+        )),
+        Value::Array(requests) => {
+            let responses: Vec<Value> = requests
+                .into_iter()
+                .filter_map(|request| handle_request(request, peer, hub, dispatch))
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        request => handle_request(request, peer, hub, dispatch),
+    }
+}
+
+fn handle_request(
+    request: Value,
+    peer: &IpcPeer,
+    hub: &EventHub,
+    dispatch: &dyn Fn(&str, Value) -> Result<Value, RpcError>,
+) -> Option<Value> {
+    let invalid = |message: &str| {
+        Some(error_response(
+            Value::Null,
+            RpcError::new(INVALID_REQUEST, message),
+        ))
+    };
+    let Value::Object(mut request) = request else {
+        return invalid("请求必须是对象");
+    };
+    let id = request.remove("id");
+    if !id
+        .as_ref()
+        .is_none_or(|id| id.is_string() || id.is_number() || id.is_null())
+    {
+        return invalid("id 必须是字符串、数字或 null");
+    }
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return invalid("jsonrpc 必须为 \"2.0\"");
+    }
+    let Some(Value::String(method)) = request.remove("method") else {
+        return invalid("缺少方法名");
+    };
+    let params = request.remove("params").unwrap_or(Value::Null);
+    if !(params.is_object() || params.is_array() || params.is_null()) {
+        return invalid("params 必须是对象或数组");
+    }
+
+    let result = match method.as_str() {
+        "subscribe" | "unsubscribe" => {
+            #[derive(serde::Deserialize, Default)]
+            #[serde(default)]
+            struct TopicParams {
+                topics: Vec<String>,
+            }
+            self::params::<TopicParams>(params).and_then(|p| {
+                if method == "subscribe" {
+                    hub.subscribe(peer, &p.topics).map(|topics| json!(topics))
+                } else {
+                    hub.unsubscribe(peer, &p.topics);
+                    Ok(Value::Null)
+                }
+            })
+        }
+        method => dispatch(method, params),
+    };
+    //没有 id 的是通知，不需要响应
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(error) => error_response(id, error),
+    })
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "error": error, "id": id })
+}
+
+/// 在控制接口端点上接受连接，`dispatch` 处理 `subscribe`/`unsubscribe` 以外的方法
+pub async fn serve<F>(hub: Arc<EventHub>, dispatch: F)
+where
+    F: Fn(&str, Value) -> Result<Value, RpcError> + Send + Sync + 'static,
+{
+    let result = ipc_tool::serve(RPC_ENDPOINT, move |message, peer| {
+        handle_message(message, peer, &hub, &dispatch)
+    })
+    .await;
+    if let Err(e) = result {
+        error_tool::report_error(format!("JSON-RPC 控制接口启动失败：{:#}", e));
+    }
+}
+
+/// 把窗口和置顶的变化推送给订阅者；看门狗事件由创建看门狗的一方直接发布到 `hub`
+pub fn publish_changes(hub: &Arc<EventHub>, watcher: &WindowWatcher, registry: &Arc<PinRegistry>) {
+    let watcher_hub = hub.clone();
+    watcher.subscribe(move |event| {
+        if watcher_hub.has_subscribers(TOPIC_WINDOWS) {
+            if let Ok(Value::Object(mut event)) = serde_json::to_value(event) {
+                let name = event.remove("event").unwrap_or_default();
+                let data = event.remove("data").unwrap_or_default();
+                watcher_hub.publish(TOPIC_WINDOWS, name.as_str().unwrap_or_default(), data);
+            }
+        }
+        true
+    });
+    //监听器由登记表持有，只保留弱引用，避免循环引用
+    let weak_registry = Arc::downgrade(registry);
+    let pins_hub = hub.clone();
+    registry.subscribe(move || {
+        let Some(registry) = weak_registry.upgrade() else {
+            return;
+        };
+        if pins_hub.has_subscribers(TOPIC_PINS) {
+            if let Ok(pins) = serde_json::to_value(registry.list()) {
+                pins_hub.publish(TOPIC_PINS, "pins_changed", pins);
+            }
+        }
+    });
+}
+
+/// 处理窗口和置顶相关的方法，不依赖界面，有界面和无界面运行时共用；不认识的方法返回 None
+pub fn dispatch_windows(
+    backend: &dyn WindowBackend,
+    registry: &PinRegistry,
+    hwnd_self: Option<isize>,
+    method: &str,
+    params: Value,
+) -> Option<Result<Value, RpcError>> {
+    let response = match method {
+        "list_windows" => {
+            let mut windows = window_backend::list_windows(backend, hwnd_self);
+            for window in &mut windows {
+                registry.decorate(window);
+            }
+            result(Ok::<_, String>(windows))
+        }
+        "toggle_topmost" => {
+            #[derive(Deserialize)]
+            struct Params {
+                hwnd: isize,
+                enable: bool,
+                duration_secs: Option<u64>,
+                deadline: Option<u64>,
+            }
+            self::params::<Params>(params).and_then(|p| {
+                let expires_at = p.deadline.or(p
+                    .duration_secs
+                    .map(|secs| pin_registry::millis_after(pin_registry::now_millis(), secs)));
+                result(pin_registry::set_pinned(
+                    backend, registry, p.hwnd, p.enable, expires_at, hwnd_self,
+                ))
+            })
+        }
+        "list_pins" => result(Ok::<_, String>(registry.list())),
+        "cancel_all_topmost" => {
+            #[derive(Deserialize)]
+            struct Params {
+                hwnd_list: Vec<isize>,
+            }
+            self::params::<Params>(params)
+                .and_then(|p| result(pin_registry::unpin_windows(backend, registry, &p.hwnd_list)))
+        }
+        //取消本程序做出的所有置顶
+        "unpin_all" => result(pin_registry::unpin_all(backend, registry)),
+        _ => return None,
+    };
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::fake_backend::FakeBackend;
+    use crate::tool::window_backend::WindowInfo;
+
+    fn window(hwnd: isize) -> WindowInfo {
+        WindowInfo {
+            hwnd,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dispatch_windows_commands() {
+        let backend = FakeBackend::new(vec![window(1), window(2), window(9)]);
+        let registry = PinRegistry::new();
+        let call = |method: &str, params: Value| {
+            dispatch_windows(&backend, &registry, Some(9), method, params)
+        };
+
+        let windows = call("list_windows", Value::Null).unwrap().unwrap();
+        assert_eq!(windows.as_array().unwrap().len(), 2);
+        call(
+            "toggle_topmost",
+            json!({ "hwnd": 1, "enable": true, "duration_secs": 60 }),
+        )
+        .unwrap()
+        .unwrap();
+        call("toggle_topmost", json!({ "hwnd": 2, "enable": true }))
+            .unwrap()
+            .unwrap();
+        assert!(backend.is_topmost(1) && backend.is_topmost(2));
+        let windows = call("list_windows", Value::Null).unwrap().unwrap();
+        assert!(windows[0]["pin_expires_at"].is_u64());
+        let pins = call("list_pins", Value::Null).unwrap().unwrap();
+        assert_eq!(pins.as_array().unwrap().len(), 2);
+
+        call("cancel_all_topmost", json!({ "hwnd_list": [1] }))
+            .unwrap()
+            .unwrap();
+        assert!(!backend.is_topmost(1) && backend.is_topmost(2));
+        call("unpin_all", Value::Null).unwrap().unwrap();
+        assert!(!backend.is_topmost(2));
+        assert!(registry.list().is_empty());
+
+        let error = call("toggle_topmost", json!({ "hwnd": 5, "enable": true }))
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.code, SERVER_ERROR);
+        let error = call("toggle_topmost", json!({ "enable": true }))
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.code, INVALID_PARAMS);
+        assert!(call("get_app_setting", Value::Null).is_none());
+    }
+
+    fn echo(method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "echo" => Ok(params),
+            method => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    fn handle(message: &str, peer: &IpcPeer, hub: &EventHub) -> Option<Value> {
+        handle_message(message, peer, hub, &echo)
+    }
+
+    #[test]
+    fn handle_message_requests() {
+        let hub = EventHub::default();
+        let (peer, _receiver) = IpcPeer::channel(1);
+        assert_eq!(
+            handle(
+                r#"{"jsonrpc":"2.0","method":"echo","params":[1],"id":7}"#,
+                &peer,
+                &hub
+            ),
+            Some(json!({ "jsonrpc": "2.0", "result": [1], "id": 7 }))
+        );
+        //通知没有 id，执行但不响应
+        assert_eq!(
+            handle(r#"{"jsonrpc":"2.0","method":"echo"}"#, &peer, &hub),
+            None
+        );
+        let error_code = |response: Option<Value>| response.unwrap()["error"]["code"].clone();
+        assert_eq!(
+            error_code(handle(
+                r#"{"jsonrpc":"1.0","method":"echo","id":1}"#,
+                &peer,
+                &hub
+            )),
+            json!(INVALID_REQUEST)
+        );
+        assert_eq!(
+            error_code(handle(r#"{"method":"echo","id":1}"#, &peer, &hub)),
+            json!(INVALID_REQUEST)
+        );
+        assert_eq!(error_code(handle("{", &peer, &hub)), json!(PARSE_ERROR));
+        assert_eq!(
+            error_code(handle("[]", &peer, &hub)),
+            json!(INVALID_REQUEST)
+        );
+
+        //批量请求只返回有 id 的请求和无效请求的响应
+        let batch = r#"[
+            {"jsonrpc":"2.0","method":"echo","params":{"a":1},"id":"a"},
+            {"jsonrpc":"2.0","method":"echo"},
+            {"jsonrpc":"2.0","method":"missing","id":2},
+            1
+        ]"#;
+        let responses = handle(batch, &peer, &hub).unwrap();
+        let responses = responses.as_array().unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(
+            responses[0],
+            json!({ "jsonrpc": "2.0", "result": {"a": 1}, "id": "a" })
+        );
+        assert_eq!(responses[1]["error"]["code"], json!(METHOD_NOT_FOUND));
+        assert_eq!(responses[2]["error"]["code"], json!(INVALID_REQUEST));
+        assert_eq!(
+            handle(r#"[{"jsonrpc":"2.0","method":"echo"}]"#, &peer, &hub),
+            None
+        );
+    }
+
+    #[test]
+    fn subscribe_and_publish() {
+        let hub = EventHub::default();
+        let (peer, mut receiver) = IpcPeer::channel(1);
+        let (other, other_receiver) = IpcPeer::channel(2);
+
+        let subscribe =
+            r#"{"jsonrpc":"2.0","method":"subscribe","params":{"topics":["pins"]},"id":1}"#;
+        assert_eq!(
+            handle(subscribe, &peer, &hub).unwrap()["result"],
+            json!(["pins"])
+        );
+        let unknown = r#"{"jsonrpc":"2.0","method":"subscribe","params":{"topics":["x"]},"id":1}"#;
+        assert_eq!(
+            handle(unknown, &peer, &hub).unwrap()["error"]["code"],
+            json!(INVALID_PARAMS)
+        );
+        //不指定主题时订阅全部
+        let all = r#"{"jsonrpc":"2.0","method":"subscribe","id":1}"#;
+        assert_eq!(
+            handle(all, &other, &hub).unwrap()["result"],
+            json!([TOPIC_PINS, TOPIC_WINDOWS])
+        );
+
+        hub.publish(TOPIC_WINDOWS, "Added", json!({}));
+        assert!(receiver.try_recv().is_err());
+        hub.publish(TOPIC_PINS, "pins_changed", json!([]));
+        let message = receiver.try_recv().unwrap().unwrap();
+        assert_eq!(message["method"], json!(EVENT_METHOD));
+        assert_eq!(message["params"]["event"], json!("pins_changed"));
+
+        //连接断开的订阅在下次推送时移除
+        drop(other_receiver);
+        assert!(hub.has_subscribers(TOPIC_WINDOWS));
+        hub.publish(TOPIC_WINDOWS, "Added", json!({}));
+        assert!(!hub.has_subscribers(TOPIC_WINDOWS));
+
+        let unsubscribe =
+            r#"{"jsonrpc":"2.0","method":"unsubscribe","params":{"topics":["pins"]}}"#;
+        assert_eq!(handle(unsubscribe, &peer, &hub), None);
+        assert!(!hub.has_subscribers(TOPIC_PINS));
+    }
+}
@@ -0,0 +1,641 @@
+use crate::tool::error_tool;
+use crate::tool::pin_registry::{self, PinInfo, PinRegistry};
+use crate::tool::window_backend::{WindowBackend, WindowInfo, MIN_OPACITY};
+use crate::tool::window_watcher::{WindowChangeEvent, WindowWatcher};
+use anyhow::Context;
+use chrono::{Datelike, Timelike};
+use evalexpr::{ContextWithMutableVariables, DefaultNumericTypes, HashMapContext, Node, Value};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// 标题的匹配方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum TitleMatch {
+    /// 通配符，`*` 匹配任意多个字符，`?` 匹配单个字符
+    #[default]
+    Glob,
+    Regex,
+}
+
+/// 自动置顶规则
+///
+/// 所有填写了的条件都满足时规则才命中；程序名、显示名称和类名使用通配符匹配，不区分大小写。
+/// `condition` 是返回布尔值的 evalexpr 表达式，可用的变量见 [`RuleVars`]，例如
+/// `exe == "code" && str::regex_matches(title, ".*\\.md") && monitor == 1`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PinRule {
+    pub id: u64,
+    pub name: String,
+    pub enabled: bool,
+    /// 程序名，如 `notepad`（Windows 下不含 .exe）
+    pub exe: Option<String>,
+    pub display_name: Option<String>,
+    pub title: Option<String>,
+    pub title_match: TitleMatch,
+    pub class: Option<String>,
+    /// 条件表达式
+    pub condition: Option<String>,
+    /// 自动置顶后设置的不透明度，范围 `MIN_OPACITY`~1.0，为空表示不修改
+    pub opacity: Option<f64>,
+}
+
+impl Default for PinRule {
+    fn default() -> Self {
+        PinRule {
+            id: 0,
+            name: String::new(),
+            enabled: true,
+            exe: None,
+            display_name: None,
+            title: None,
+            title_match: TitleMatch::Glob,
+            class: None,
+            condition: None,
+            opacity: None,
+        }
+    }
+}
+
+/// 条件表达式中可用的变量
+///
+/// 字符串：`exe`、`display_name`、`title`、`class`、`foreground_exe`（前台窗口的程序名）；
+/// 整数：`pid`、`hwnd`、`monitor`（窗口所在显示器的序号，从 1 开始，未知时为 0）、
+/// `hour`、`minute`、`weekday`（1~7 表示周一到周日）；布尔值：`topmost`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleVars {
+    pub exe: String,
+    pub display_name: String,
+    pub title: String,
+    pub class: String,
+    pub pid: i64,
+    pub hwnd: i64,
+    pub topmost: bool,
+    pub monitor: i64,
+    pub foreground_exe: String,
+    pub hour: i64,
+    pub minute: i64,
+    pub weekday: i64,
+}
+
+impl RuleVars {
+    /// 收集窗口的变量，时间取当前本地时间
+    pub fn collect(backend: &dyn WindowBackend, window: &WindowInfo) -> Self {
+        let monitor = backend
+            .window_rect(window.hwnd)
+            .and_then(|rect| {
+                let (x, y) = ((rect.left + rect.right) / 2, (rect.top + rect.bottom) / 2);
+                backend
+                    .monitors()
+                    .iter()
+                    .position(|m| m.left <= x && x < m.right && m.top <= y && y < m.bottom)
+            })
+            .map_or(0, |index| index as i64 + 1);
+        let foreground_exe = backend
+            .foreground_window()
+            .and_then(|hwnd| backend.window_info(hwnd))
+            .map(|info| info.name)
+            .unwrap_or_default();
+        let now = chrono::Local::now();
+        RuleVars {
+            exe: window.name.clone(),
+            display_name: window.display_name.clone(),
+            title: window.title.clone(),
+            class: window.class.clone(),
+            pid: window.pid as i64,
+            hwnd: window.hwnd as i64,
+            topmost: window.is_top_most,
+            monitor,
+            foreground_exe,
+            hour: now.hour() as i64,
+            minute: now.minute() as i64,
+            weekday: now.weekday().number_from_monday() as i64,
+        }
+    }
+
+    /// 变量名及其值
+    fn values(&self) -> [(&'static str, Value); 12] {
+        [
+            ("exe", Value::String(self.exe.clone())),
+            ("display_name", Value::String(self.display_name.clone())),
+            ("title", Value::String(self.title.clone())),
+            ("class", Value::String(self.class.clone())),
+            ("pid", Value::Int(self.pid)),
+            ("hwnd", Value::Int(self.hwnd)),
+            ("topmost", Value::Boolean(self.topmost)),
+            ("monitor", Value::Int(self.monitor)),
+            ("foreground_exe", Value::String(self.foreground_exe.clone())),
+            ("hour", Value::Int(self.hour)),
+            ("minute", Value::Int(self.minute)),
+            ("weekday", Value::Int(self.weekday)),
+        ]
+    }
+
+    /// 条件表达式中可以使用的变量名
+    pub fn names() -> Vec<&'static str> {
+        RuleVars::default()
+            .values()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    pub fn to_context(&self) -> anyhow::Result<HashMapContext<DefaultNumericTypes>> {
+        let mut context = HashMapContext::<DefaultNumericTypes>::new();
+        for (name, value) in self.values() {
+            context
+                .set_value(name.to_string(), value)
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+        Ok(context)
+    }
+}
+
+/// 预编译了标题正则和条件表达式的规则
+struct CompiledRule {
+    rule: PinRule,
+    title_regex: Option<Regex>,
+    condition: Option<Node<DefaultNumericTypes>>,
+}
+
+impl CompiledRule {
+    fn compile(rule: PinRule) -> anyhow::Result<Self> {
+        let conditions = [
+            &rule.exe,
+            &rule.display_name,
+            &rule.title,
+            &rule.class,
+            &rule.condition,
+        ];
+        if conditions
+            .iter()
+            .all(|c| c.as_deref().is_none_or(str::is_empty))
+        {
+            return Err(anyhow::anyhow!("规则至少需要一个匹配条件"));
+        }
+        if rule
+            .opacity
+            .is_some_and(|opacity| !(MIN_OPACITY..=1.0).contains(&opacity))
+        {
+            return Err(anyhow::anyhow!(
+                "不透明度需要在 {}%~100% 之间",
+                (MIN_OPACITY * 100.0) as u32
+            ));
+        }
+        let title_regex = match (&rule.title, rule.title_match) {
+            (Some(title), TitleMatch::Regex) if !title.is_empty() => {
+                Some(Regex::new(title).with_context(|| format!("标题正则表达式错误：{}", title))?)
+            }
+            _ => None,
+        };
+        let condition = match rule.condition.as_deref().map(str::trim) {
+            Some(expression) if !expression.is_empty() => {
+                let node = evalexpr::build_operator_tree::<DefaultNumericTypes>(expression)
+                    .map_err(|e| anyhow::anyhow!("规则 {} 的条件表达式语法错误：{}", rule.id, e))?;
+                //提前发现写错的变量名，短路求值时试算不一定会执行到
+                let names = RuleVars::names();
+                if let Some(unknown) = node
+                    .iter_variable_identifiers()
+                    .find(|identifier| !names.contains(identifier))
+                {
+                    return Err(anyhow::anyhow!(
+                        "规则 {} 的条件表达式使用了未知的变量：{}",
+                        rule.id,
+                        unknown
+                    ));
+                }
+                //用一组类型相同的空白变量试算一次，提前发现类型不匹配和返回值不是布尔值
+                node.eval_boolean_with_context(&RuleVars::default().to_context()?)
+                    .map_err(|e| anyhow::anyhow!("规则 {} 的条件表达式类型错误：{}", rule.id, e))?;
+                Some(node)
+            }
+            _ => None,
+        };
+        Ok(CompiledRule {
+            rule,
+            title_regex,
+            condition,
+        })
+    }
+
+    /// `context` 为条件表达式的变量，规则没有条件表达式时不使用
+    fn matches(
+        &self,
+        window: &WindowInfo,
+        context: Option<&HashMapContext<DefaultNumericTypes>>,
+    ) -> bool {
+        let field_matches = |pattern: &Option<String>, value: &str| match pattern.as_deref() {
+            None | Some("") => true,
+            Some(pattern) => glob_match(pattern, value),
+        };
+        let title_matches = match &self.title_regex {
+            Some(regex) => regex.is_match(&window.title),
+            None => field_matches(&self.rule.title, &window.title),
+        };
+        self.rule.enabled
+            && field_matches(&self.rule.exe, &window.name)
+            && field_matches(&self.rule.display_name, &window.display_name)
+            && field_matches(&self.rule.class, &window.class)
+            && title_matches
+            && match (&self.condition, context) {
+                (None, _) => true,
+                (Some(node), Some(context)) => {
+                    node.eval_boolean_with_context(context).unwrap_or_else(|e| {
+                        error_tool::report_error(format!(
+                            "规则 {} 的条件表达式执行失败：{}",
+                            self.rule.id, e
+                        ));
+                        false
+                    })
+                }
+                (Some(_), None) => false,
+            }
+    }
+}
+
+/// 不区分大小写的通配符匹配
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    //最近一个 * 的位置，以及它当前匹配到的文本位置，失配时回溯
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// 自动置顶规则引擎
+///
+/// 规则保存在配置目录下，窗口出现时自动置顶命中的窗口。
+/// 每个窗口只自动置顶一次，用户手动取消后不会再被规则置顶
+pub struct RuleEngine {
+    backend: Arc<dyn WindowBackend>,
+    registry: Arc<PinRegistry>,
+    path: Option<PathBuf>,
+    rules: Mutex<Vec<CompiledRule>>,
+    applied: Mutex<HashSet<isize>>,
+}
+
+impl RuleEngine {
+    /// 从 `path` 读取规则，文件不存在时为空规则；`path` 为空时规则只保存在内存中
+    pub fn load(
+        backend: Arc<dyn WindowBackend>,
+        registry: Arc<PinRegistry>,
+        path: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let rules: Vec<PinRule> = match &path {
+            Some(path) if path.exists() => {
+                let file = std::fs::File::open(path)?;
+                serde_json::from_reader(std::io::BufReader::new(file))
+                    .context("读取置顶规则失败")?
+            }
+            _ => Vec::new(),
+        };
+        let rules = rules
+            .into_iter()
+            .filter_map(|rule| match CompiledRule::compile(rule) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    error_tool::report_error(format!("忽略无效的置顶规则：{}", e));
+                    None
+                }
+            })
+            .collect();
+        Ok(RuleEngine {
+            backend,
+            registry,
+            path,
+            rules: Mutex::new(rules),
+            applied: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// 订阅窗口监听，窗口出现或标题变化时应用规则，并立即应用到已有窗口
+    pub fn attach(self: &Arc<Self>, watcher: &WindowWatcher) {
+        let engine = self.clone();
+        watcher.subscribe(move |event| {
+            engine.handle_event(event);
+            true
+        });
+        self.apply_to(&watcher.windows());
+    }
+
+    pub fn rules(&self) -> Vec<PinRule> {
+        let rules = self.rules.lock().unwrap();
+        rules.iter().map(|r| r.rule.clone()).collect()
+    }
+
+    /// 新增规则并分配 id，保存失败时不生效
+    pub fn add_rule(&self, mut rule: PinRule) -> anyhow::Result<PinRule> {
+        let mut rules = self.rules.lock().unwrap();
+        rule.id = rules.iter().map(|r| r.rule.id).max().unwrap_or(0) + 1;
+        let compiled = CompiledRule::compile(rule.clone())?;
+        let mut list: Vec<&PinRule> = rules.iter().map(|r| &r.rule).collect();
+        list.push(&rule);
+        self.save(&list)?;
+        rules.push(compiled);
+        Ok(rule)
+    }
+
+    /// 修改规则，保存失败时不生效
+    pub fn update_rule(&self, rule: PinRule) -> anyhow::Result<()> {
+        let mut rules = self.rules.lock().unwrap();
+        let index = rules
+            .iter()
+            .position(|r| r.rule.id == rule.id)
+            .ok_or_else(|| anyhow::anyhow!("规则不存在：{}", rule.id))?;
+        let compiled = CompiledRule::compile(rule)?;
+        let list: Vec<&PinRule> = rules
+            .iter()
+            .enumerate()
+            .map(|(i, r)| if i == index { &compiled.rule } else { &r.rule })
+            .collect();
+        self.save(&list)?;
+        rules[index] = compiled;
+        Ok(())
+    }
+
+    pub fn delete_rule(&self, id: u64) -> anyhow::Result<()> {
+        let mut rules = self.rules.lock().unwrap();
+        if !rules.iter().any(|r| r.rule.id == id) {
+            return Err(anyhow::anyhow!("规则不存在：{}", id));
+        }
+        let list: Vec<&PinRule> = rules
+            .iter()
+            .map(|r| &r.rule)
+            .filter(|rule| rule.id != id)
+            .collect();
+        self.save(&list)?;
+        rules.retain(|r| r.rule.id != id);
+        Ok(())
+    }
+
+    /// 第一条命中窗口的规则
+    pub fn matching_rule(&self, window: &WindowInfo) -> Option<PinRule> {
+        let rules = self.rules.lock().unwrap();
+        //收集变量需要查询窗口位置和前台窗口，只在有条件表达式时收集
+        let context = if rules.iter().any(|r| r.condition.is_some()) {
+            match RuleVars::collect(self.backend.as_ref(), window).to_context() {
+                Ok(context) => Some(context),
+                Err(e) => {
+                    error_tool::report_error(format!("收集规则变量失败：{}", e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        rules
+            .iter()
+            .find(|r| r.matches(window, context.as_ref()))
+            .map(|r| r.rule.clone())
+    }
+
+    /// 对一批窗口应用规则，返回本次被置顶的窗口
+    pub fn apply_to(&self, windows: &[WindowInfo]) -> Vec<isize> {
+        windows
+            .iter()
+            .filter(|window| self.apply(window))
+            .map(|window| window.hwnd)
+            .collect()
+    }
+
+    pub fn handle_event(&self, event: &WindowChangeEvent) {
+        match event {
+            //部分程序先创建窗口再设置标题，标题变化时也需要重新匹配
+            WindowChangeEvent::Added { window } | WindowChangeEvent::Changed { window } => {
+                self.apply(window);
+            }
+            WindowChangeEvent::Removed { hwnd } => {
+                self.applied.lock().unwrap().remove(hwnd);
+            }
+        }
+    }
+
+    fn apply(&self, window: &WindowInfo) -> bool {
+        if self.applied.lock().unwrap().contains(&window.hwnd) {
+            return false;
+        }
+        let Some(rule) = self.matching_rule(window) else {
+            return false;
+        };
+        if !window.is_top_most {
+            if let Err(e) = self.backend.set_topmost(window.hwnd, true) {
+                error_tool::report_error(format!("自动置顶失败：{}", e));
+                return false;
+            }
+        }
+        self.registry.pin(PinInfo::new(window, window.is_top_most));
+        if let Some(opacity) = rule.opacity {
+            let result = pin_registry::set_opacity(
+                self.backend.as_ref(),
+                &self.registry,
+                window.hwnd,
+                opacity,
+            );
+            if let Err(e) = result {
+                error_tool::report_error(format!("设置不透明度失败：{}", e));
+            }
+        }
+        self.applied.lock().unwrap().insert(window.hwnd);
+        true
+    }
+
+    fn save(&self, rules: &[&PinRule]) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        //先写临时文件再替换，写到一半失败时不会丢掉原有的规则
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&rules)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::fake_backend::FakeBackend;
+
+    fn rule(condition: &str) -> PinRule {
+        PinRule {
+            condition: Some(condition.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn condition_errors_are_reported_on_save() {
+        assert!(CompiledRule::compile(rule("exe == \"code\" && monitor == 1")).is_ok());
+        for condition in ["title > 1", "exe", "monitor + 1", "exe =="] {
+            let Err(err) = CompiledRule::compile(PinRule {
+                id: 3,
+                ..rule(condition)
+            }) else {
+                panic!("{} 应当报错", condition);
+            };
+            assert!(err.to_string().starts_with("规则 3 "), "{}", err);
+        }
+        let Err(err) = CompiledRule::compile(rule("exe == \"code\" && titel == \"a\"")) else {
+            panic!("未知变量应当报错");
+        };
+        assert!(err.to_string().contains("titel"), "{}", err);
+        assert!(CompiledRule::compile(rule("(exe")).is_err());
+    }
+
+    #[test]
+    fn failed_save_keeps_rules_unchanged() {
+        let dir = std::env::temp_dir().join(format!("rule_test_{}", std::process::id()));
+        let engine = RuleEngine::load(
+            Arc::new(FakeBackend::new(Vec::new())),
+            Arc::new(PinRegistry::new()),
+            Some(dir.join("rules.json")),
+        )
+        .unwrap();
+        //目录不存在，保存失败
+        assert!(engine.add_rule(rule("topmost")).is_err());
+        assert!(engine.rules().is_empty());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        let added = engine.add_rule(rule("topmost")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut changed = added.clone();
+        changed.enabled = !added.enabled;
+        assert!(engine.update_rule(changed).is_err());
+        assert!(engine.delete_rule(added.id).is_err());
+        assert_eq!(engine.rules(), vec![added]);
+    }
+
+    fn window(hwnd: isize, exe: &str, class: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            hwnd,
+            pid: hwnd as u32,
+            name: exe.to_string(),
+            class: class.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn engine(backend: &Arc<FakeBackend>, rules: Vec<PinRule>) -> RuleEngine {
+        let engine = RuleEngine::load(backend.clone(), Arc::new(PinRegistry::new()), None).unwrap();
+        for rule in rules {
+            engine.add_rule(rule).unwrap();
+        }
+        engine
+    }
+
+    #[test]
+    fn apply_matches_exe_class_and_title() {
+        let windows = vec![
+            window(1, "notepad", "Notepad", "a.txt - 记事本"),
+            window(
+                2,
+                "code",
+                "Chrome_WidgetWin_1",
+                "main.rs - Visual Studio Code",
+            ),
+            window(
+                3,
+                "code",
+                "Chrome_WidgetWin_1",
+                "README.md - Visual Studio Code",
+            ),
+            window(
+                4,
+                "chrome",
+                "Chrome_WidgetWin_1",
+                "README.md - Google Chrome",
+            ),
+        ];
+        let backend = Arc::new(FakeBackend::new(windows.clone()));
+        let engine = engine(
+            &backend,
+            vec![
+                PinRule {
+                    exe: Some("NOTE*".to_string()),
+                    ..Default::default()
+                },
+                PinRule {
+                    class: Some("chrome_*".to_string()),
+                    title: Some(r"\.md - Visual".to_string()),
+                    title_match: TitleMatch::Regex,
+                    opacity: Some(0.6),
+                    ..Default::default()
+                },
+                PinRule {
+                    enabled: false,
+                    exe: Some("chrome".to_string()),
+                    ..Default::default()
+                },
+            ],
+        );
+        assert_eq!(engine.apply_to(&windows), vec![1, 3]);
+        assert!(backend.is_topmost(1) && backend.is_topmost(3));
+        assert!(!backend.is_topmost(2) && !backend.is_topmost(4));
+        assert_eq!(backend.opacity(3), Some(0.6));
+        assert_eq!(engine.registry.hwnds().len(), 2);
+    }
+
+    #[test]
+    fn apply_once_per_window() {
+        let notepad = window(1, "notepad", "Notepad", "a.txt");
+        let backend = Arc::new(FakeBackend::new(vec![notepad.clone()]));
+        let engine = engine(
+            &backend,
+            vec![PinRule {
+                exe: Some("notepad".to_string()),
+                ..Default::default()
+            }],
+        );
+        assert_eq!(engine.apply_to(std::slice::from_ref(&notepad)), vec![1]);
+        //用户手动取消后，标题变化也不再置顶
+        pin_registry::set_pinned(backend.as_ref(), &engine.registry, 1, false, None, None).unwrap();
+        engine.handle_event(&WindowChangeEvent::Changed {
+            window: window(1, "notepad", "Notepad", "b.txt"),
+        });
+        assert!(!backend.is_topmost(1));
+        assert!(engine.apply_to(std::slice::from_ref(&notepad)).is_empty());
+
+        //窗口关闭后再出现（句柄被复用）时重新应用
+        engine.handle_event(&WindowChangeEvent::Removed { hwnd: 1 });
+        engine.handle_event(&WindowChangeEvent::Added { window: notepad });
+        assert!(backend.is_topmost(1));
+    }
+
+    #[test]
+    fn save_replaces_rule_file() {
+        let dir = std::env::temp_dir().join(format!("rule_save_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rules.json");
+        let backend: Arc<dyn WindowBackend> = Arc::new(FakeBackend::new(Vec::new()));
+        let registry = Arc::new(PinRegistry::new());
+        let engine =
+            RuleEngine::load(backend.clone(), registry.clone(), Some(path.clone())).unwrap();
+        let added = engine.add_rule(rule("topmost")).unwrap();
+        engine.add_rule(rule("pid > 0")).unwrap();
+        engine.delete_rule(added.id).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+        let loaded = RuleEngine::load(backend, registry, Some(path)).unwrap();
+        assert_eq!(loaded.rules(), engine.rules());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
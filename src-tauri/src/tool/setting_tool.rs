@@ -0,0 +1,113 @@
+use crate::constant;
+use crate::tool::border_overlay::BorderSetting;
+#[cfg(feature = "webview")]
+use crate::tool::hotkey_tool::{self, HotkeyAction};
+use crate::tool::pin_registry::ReassertPolicy;
+#[cfg(feature = "webview")]
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const APP_SETTING_FILE: &str = ".app_settings.json";
+pub const PIN_RULES_FILE: &str = ".pin_rules.json";
+pub const PIN_JOURNAL_FILE: &str = ".pin_journal.json";
+pub const LAYOUT_DIR: &str = "layouts";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum AppExitType {
+    Exit,
+    Minimize,
+}
+
+/// 程序退出时如何处理本程序做出的置顶
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum PinExitPolicy {
+    /// 保留置顶
+    Keep,
+    /// 还原为置顶前的状态
+    Restore,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub auto_start: bool,
+    pub app_exit_type: AppExitType,
+    /// 是否把程序图标缓存到磁盘，重启后生效
+    pub icon_disk_cache: bool,
+    /// 置顶被其它程序抢占后的处理策略
+    pub reassert_policy: ReassertPolicy,
+    /// 退出（包括崩溃后下次启动）时如何处理置顶
+    pub pin_exit_policy: PinExitPolicy,
+    /// 全屏程序（游戏、幻灯片放映等）在前台时暂停同一显示器上的置顶
+    pub suspend_on_fullscreen: bool,
+    /// 操作 → 全局快捷键，如 `CommandOrControl+Alt+P`，为空表示不使用；没有的操作使用默认快捷键
+    #[cfg(feature = "webview")]
+    pub hotkeys: BTreeMap<HotkeyAction, String>,
+    /// 置顶窗口的高亮边框
+    pub pin_border: BorderSetting,
+    /// 开启本机 JSON-RPC 控制接口，只有当前用户可以连接，重启后生效
+    pub rpc_enabled: bool,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            auto_start: false,
+            app_exit_type: AppExitType::Minimize,
+            icon_disk_cache: true,
+            reassert_policy: ReassertPolicy::default(),
+            pin_exit_policy: PinExitPolicy::Restore,
+            suspend_on_fullscreen: true,
+            #[cfg(feature = "webview")]
+            hotkeys: hotkey_tool::default_hotkeys(),
+            pin_border: BorderSetting::default(),
+            rpc_enabled: false,
+        }
+    }
+}
+
+/// 读取配置目录 `app_dir` 下的设置，没有设置文件时创建默认设置
+pub fn load_setting(app_dir: &Path) -> anyhow::Result<AppSettings> {
+    let setting_path = app_dir.join(APP_SETTING_FILE);
+
+    if !app_dir.exists() {
+        std::fs::create_dir_all(app_dir)?;
+    }
+    if !setting_path.exists() {
+        let setting = AppSettings::default();
+        save_setting(app_dir, &setting)?;
+        return Ok(setting);
+    }
+
+    let file = std::fs::File::open(setting_path)?;
+    let reader = std::io::BufReader::new(file);
+    let settings = serde_json::from_reader(reader)?;
+    Ok(settings)
+}
+
+pub fn save_setting(app_dir: &Path, settings: &AppSettings) -> anyhow::Result<()> {
+    let setting_path = app_dir.join(APP_SETTING_FILE);
+
+    let file = std::fs::File::create(setting_path)?;
+    serde_json::to_writer_pretty(file, settings)?;
+
+    Ok(())
+}
+
+/// 不经过 tauri 时使用的配置目录，与 tauri 的 `app_config_dir` 相同，两种方式运行时共用配置
+pub fn config_dir() -> anyhow::Result<PathBuf> {
+    let env_dir = |name: &str| {
+        std::env::var_os(name)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(target_os = "windows") {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+    };
+    let base = base.ok_or_else(|| anyhow::anyhow!("找不到配置目录"))?;
+    Ok(base.join(constant::APP_IDENTIFIER))
+}
@@ -0,0 +1,151 @@
+use crate::tool::window_backend::IconImage;
+use image::{Pixel, Rgba, RgbaImage};
+
+/// 托盘图标的边长，程序图标先缩放到该尺寸再绘制角标
+pub const TRAY_ICON_SIZE: u32 = 64;
+/// 数量超过该值时角标显示为 9+
+pub const MAX_BADGE_COUNT: usize = 9;
+/// 角标的背景色
+const BADGE_COLOR: [u8; 3] = [0xF5, 0x6C, 0x6C];
+const WHITE: [u8; 3] = [0xFF, 0xFF, 0xFF];
+
+/// 3×5 点阵字形，每行取低 3 位，从左到右
+type Glyph = [u8; 5];
+
+const DIGITS: [Glyph; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+const PLUS: Glyph = [0b000, 0b010, 0b111, 0b010, 0b000];
+
+/// 在图标右下角绘制置顶数量的角标，数量为 0 时原样返回
+pub fn draw_badge(icon: IconImage, count: usize) -> anyhow::Result<IconImage> {
+    if count == 0 {
+        return Ok(icon);
+    }
+    let icon = icon.resized(TRAY_ICON_SIZE)?;
+    let mut img = RgbaImage::from_raw(icon.width, icon.height, icon.rgba)
+        .ok_or_else(|| anyhow::anyhow!("image error"))?;
+
+    let size = TRAY_ICON_SIZE as f32;
+    let radius = size * 5.0 / 16.0;
+    let center = size - radius;
+    //白色描边，深色任务栏和红色图标上都能分辨出角标
+    fill_circle(&mut img, center, radius, WHITE);
+    fill_circle(
+        &mut img,
+        center,
+        radius - (size / 32.0).max(1.0),
+        BADGE_COLOR,
+    );
+
+    let glyphs = if count > MAX_BADGE_COUNT {
+        vec![DIGITS[9], PLUS]
+    } else {
+        vec![DIGITS[count]]
+    };
+    //字形之间空一列，文字高度约为角标的 0.6，宽度不超过 0.75
+    let columns = glyphs.len() as f32 * 4.0 - 1.0;
+    let scale = (radius * 1.2 / 5.0)
+        .min(radius * 1.5 / columns)
+        .floor()
+        .max(1.0) as u32;
+    let left = (center - columns * scale as f32 / 2.0).round() as u32;
+    let top = (center - 5.0 * scale as f32 / 2.0).round() as u32;
+    for (index, glyph) in glyphs.iter().enumerate() {
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                if bits >> (2 - col) & 1 == 0 {
+                    continue;
+                }
+                let x = left + (index as u32 * 4 + col) * scale;
+                let y = top + row as u32 * scale;
+                fill_square(&mut img, x, y, scale, WHITE);
+            }
+        }
+    }
+    Ok(IconImage {
+        width: img.width(),
+        height: img.height(),
+        rgba: img.into_raw(),
+    })
+}
+
+/// 以 (`center`, `center`) 为圆心画实心圆，边缘按覆盖比例抗锯齿
+fn fill_circle(img: &mut RgbaImage, center: f32, radius: f32, color: [u8; 3]) {
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let (dx, dy) = (x as f32 + 0.5 - center, y as f32 + 0.5 - center);
+        let coverage = (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+        if coverage > 0.0 {
+            let [r, g, b] = color;
+            pixel.blend(&Rgba([r, g, b, (coverage * 255.0).round() as u8]));
+        }
+    }
+}
+
+fn fill_square(img: &mut RgbaImage, left: u32, top: u32, size: u32, color: [u8; 3]) {
+    let [r, g, b] = color;
+    for y in top..(top + size).min(img.height()) {
+        for x in left..(left + size).min(img.width()) {
+            img.put_pixel(x, y, Rgba([r, g, b, 0xFF]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLUE: [u8; 4] = [0x20, 0x60, 0xE0, 0xFF];
+
+    fn icon(size: u32) -> IconImage {
+        IconImage {
+            width: size,
+            height: size,
+            rgba: BLUE.repeat((size * size) as usize),
+        }
+    }
+
+    fn pixel(icon: &IconImage, x: u32, y: u32) -> [u8; 4] {
+        let start = ((y * icon.width + x) * 4) as usize;
+        icon.rgba[start..start + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn no_badge_without_pins() {
+        let badged = draw_badge(icon(32), 0).unwrap();
+        assert_eq!((badged.width, badged.height), (32, 32));
+        assert_eq!(badged.rgba, icon(32).rgba);
+    }
+
+    #[test]
+    fn badge_in_bottom_right_corner() {
+        let badged = draw_badge(icon(32), 1).unwrap();
+        assert_eq!(
+            (badged.width, badged.height),
+            (TRAY_ICON_SIZE, TRAY_ICON_SIZE)
+        );
+        assert_eq!(pixel(&badged, 10, 10), BLUE);
+        let [r, g, b] = BADGE_COLOR;
+        //数字下方的角标底色
+        assert_eq!(pixel(&badged, 44, 59), [r, g, b, 0xFF]);
+        //数字 1 的竖线
+        assert_eq!(pixel(&badged, 43, 45), [0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn large_counts_share_one_badge() {
+        let badge = |count| draw_badge(icon(32), count).unwrap().rgba;
+        assert_ne!(badge(1), badge(2));
+        assert_ne!(badge(MAX_BADGE_COUNT), badge(MAX_BADGE_COUNT + 1));
+        assert_eq!(badge(MAX_BADGE_COUNT + 1), badge(25));
+    }
+}
@@ -0,0 +1,457 @@
+use crate::constant;
+use crate::tool::app_tool;
+use crate::tool::error_tool;
+use crate::tool::icon_cache::IconCache;
+use crate::tool::pin_registry::{self, PinInfo, PinRegistry};
+use crate::tool::tray_badge;
+use crate::tool::update_tool;
+use crate::tool::window_backend::{IconImage, WindowBackendState};
+use anyhow::Context;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::image::Image;
+use tauri::menu::{IconMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::{MouseButton, TrayIcon, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Emitter, Manager, Wry};
+use tauri_utils::config::WindowConfig;
+
+/// “置顶的窗口”子菜单中菜单项 id 的前缀，后接窗口句柄，点击后取消该窗口的置顶
+const UNPIN_MENU_PREFIX: &str = "unpin:";
+/// 子菜单中程序图标的边长
+const MENU_ICON_SIZE: u32 = 16;
+/// 菜单中窗口名称的最大字数，超出部分以省略号代替
+const MENU_LABEL_MAX_CHARS: usize = 40;
+/// 托盘提示的最大字数，Windows 下超出的部分会被截掉
+const TOOLTIP_MAX_CHARS: usize = 127;
+/// 记录前台窗口和刷新托盘提示的间隔
+const TRAY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 最近一次的前台窗口，不包括本程序的窗口
+///
+/// 点击托盘图标后前台窗口会变为任务栏或托盘菜单，“置顶前台窗口”以打开菜单前的前台窗口为准
+static LAST_FOREGROUND: Mutex<Option<isize>> = Mutex::new(None);
+
+/// 创建托盘图标，置顶窗口增减时重建菜单和图标角标
+pub fn create_tray(app: &AppHandle) -> anyhow::Result<()> {
+    let menu = build_menu(app)?;
+    TrayIconBuilder::with_id(constant::APP_TRAY_ID)
+        .tooltip(constant::APP_DISPLAY_NAME)
+        .icon(build_icon(app)?)
+        .menu(&menu)
+        .show_menu_on_left_click(true)
+        .on_menu_event(|app, event| {
+            if let Err(e) = handle_menu_event(app, event.id.as_ref()) {
+                app_tool::send_error_to_frontend(app, e);
+            }
+        })
+        .on_tray_icon_event(|tray, event| {
+            on_tray_icon_event(tray, &event);
+            tauri_plugin_positioner::on_tray_event(tray.app_handle(), &event);
+        })
+        .build(app)?;
+
+    let app_handle = app.clone();
+    app.state::<Arc<PinRegistry>>().subscribe(move || {
+        //置顶可能在后台线程中变化，菜单和图标在主线程中重建，不阻塞发起置顶的线程
+        let app = app_handle.clone();
+        let _ = app_handle.run_on_main_thread(move || {
+            refresh_menu(&app);
+            refresh_icon(&app);
+        });
+    });
+
+    let app_handle = app.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(TRAY_INTERVAL);
+        remember_foreground(&app_handle);
+        refresh_tray_tooltip(&app_handle);
+    });
+    Ok(())
+}
+
+/// 按当前的置顶登记重建托盘菜单
+pub fn refresh_menu(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(constant::APP_TRAY_ID) else {
+        return;
+    };
+    let result = build_menu(app).and_then(|menu| Ok(tray.set_menu(Some(menu))?));
+    if let Err(e) = result {
+        error_tool::report_error(format!("刷新托盘菜单失败：{}", e));
+    }
+}
+
+/// 按当前的置顶数量重新生成托盘图标
+pub fn refresh_icon(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(constant::APP_TRAY_ID) else {
+        return;
+    };
+    let result = build_icon(app).and_then(|icon| Ok(tray.set_icon(Some(icon))?));
+    if let Err(e) = result {
+        error_tool::report_error(format!("刷新托盘图标失败：{}", e));
+    }
+}
+
+/// 程序图标，有置顶窗口时在右下角加上数量角标
+fn build_icon(app: &AppHandle) -> anyhow::Result<Image<'static>> {
+    let icon = app.default_window_icon().context("获取程序图标失败")?;
+    let count = app
+        .try_state::<Arc<PinRegistry>>()
+        .map_or(0, |registry| registry.hwnds().len());
+    let icon = tray_badge::draw_badge(
+        IconImage {
+            width: icon.width(),
+            height: icon.height(),
+            rgba: icon.rgba().to_vec(),
+        },
+        count,
+    )?;
+    Ok(Image::new_owned(icon.rgba, icon.width, icon.height))
+}
+
+fn build_menu(app: &AppHandle) -> anyhow::Result<Menu<Wry>> {
+    let show_menu = MenuItem::with_id(app, "show", "显示", true, None::<&str>)?;
+    let pick_menu = MenuItem::with_id(app, "pick_window", "点选窗口置顶", true, None::<&str>)?;
+    let pin_foreground_menu =
+        MenuItem::with_id(app, "pin_foreground", "置顶前台窗口", true, None::<&str>)?;
+    let pinned_menu = build_pinned_menu(app)?;
+    let unpin_all_menu = MenuItem::with_id(app, "unpin_all", "取消所有置顶", true, None::<&str>)?;
+    let clear_click_through_menu = MenuItem::with_id(
+        app,
+        "clear_click_through",
+        "取消所有鼠标穿透",
+        true,
+        None::<&str>,
+    )?;
+    let separator = PredefinedMenuItem::separator(app)?;
+    let setting_menu = MenuItem::with_id(app, "setting", "设置", true, None::<&str>)?;
+    let check_update_menu = MenuItem::with_id(app, "check_update", "检查更新", true, None::<&str>)?;
+    let restart_menu = MenuItem::with_id(app, "restart", "重启", true, None::<&str>)?;
+    let quit_menu = MenuItem::with_id(app, "quit", "退出", true, None::<&str>)?;
+    Ok(Menu::with_items(
+        app,
+        &[
+            &show_menu,
+            &pick_menu,
+            &pin_foreground_menu,
+            &pinned_menu,
+            &unpin_all_menu,
+            &clear_click_through_menu,
+            &separator,
+            &setting_menu,
+            &check_update_menu,
+            &restart_menu,
+            &quit_menu,
+        ],
+    )?)
+}
+
+/// “置顶的窗口”子菜单，每个置顶窗口一项，没有置顶时禁用
+fn build_pinned_menu(app: &AppHandle) -> anyhow::Result<Submenu<Wry>> {
+    let pins = app
+        .try_state::<Arc<PinRegistry>>()
+        .map(|registry| registry.list())
+        .unwrap_or_default();
+    let submenu = Submenu::with_id(
+        app,
+        "pinned",
+        format!("置顶的窗口（{}）", pins.len()),
+        !pins.is_empty(),
+    )?;
+    for pin in &pins {
+        let item = IconMenuItem::with_id(
+            app,
+            format!("{}{}", UNPIN_MENU_PREFIX, pin.hwnd),
+            pin_label(pin),
+            true,
+            pin_icon(app, pin.hwnd),
+            None::<&str>,
+        )?;
+        submenu.append(&item)?;
+    }
+    Ok(submenu)
+}
+
+/// 菜单项显示“显示名称 - 标题”，标题为空或与显示名称相同时只显示名称
+fn pin_label(pin: &PinInfo) -> String {
+    let mut label = if pin.title.is_empty() || pin.title == pin.display_name {
+        pin.display_name.clone()
+    } else if pin.display_name.is_empty() {
+        pin.title.clone()
+    } else {
+        format!("{} - {}", pin.display_name, pin.title)
+    };
+    if label.chars().count() > MENU_LABEL_MAX_CHARS {
+        label = label.chars().take(MENU_LABEL_MAX_CHARS - 1).collect();
+        label.push('…');
+    }
+    label
+}
+
+/// 窗口所属程序的图标，获取失败时菜单项不显示图标
+fn pin_icon(app: &AppHandle, hwnd: isize) -> Option<Image<'static>> {
+    let backend = app.try_state::<WindowBackendState>()?;
+    let icon_cache = app.try_state::<Arc<IconCache>>()?;
+    let window = backend.0.window_info(hwnd)?;
+    let path = icon_cache.path_of(&window.icon_key)?;
+    let icon = backend
+        .0
+        .load_icon(&path, MENU_ICON_SIZE)
+        .and_then(|icon| icon.resized(MENU_ICON_SIZE))
+        .ok()?;
+    Some(Image::new_owned(icon.rgba, icon.width, icon.height))
+}
+
+fn handle_menu_event(app: &AppHandle, id: &str) -> anyhow::Result<()> {
+    if let Some(hwnd) = id.strip_prefix(UNPIN_MENU_PREFIX) {
+        let hwnd: isize = hwnd.parse()?;
+        let backend = app.state::<WindowBackendState>();
+        let registry = app.state::<Arc<PinRegistry>>();
+        let hwnd_self = app_tool::get_self_window_id(app, constant::APP_MAIN_WINDOW_LABEL);
+        return pin_registry::set_pinned(
+            backend.0.as_ref(),
+            &registry,
+            hwnd,
+            false,
+            None,
+            hwnd_self,
+        );
+    }
+    match id {
+        "show" => {
+            app_tool::show_main_window(app)?;
+        }
+        "pick_window" => {
+            //菜单事件在主线程中处理，拾取放到独立线程中等待点击
+            let app = app.clone();
+            std::thread::spawn(move || match app_tool::pick_window(&app, true) {
+                Ok(Some(window)) => {
+                    let _ = app.emit("window_picked", window);
+                }
+                Ok(None) => {}
+                Err(e) => app_tool::send_error_to_frontend(&app, e),
+            });
+        }
+        "pin_foreground" => {
+            pin_last_foreground(app)?;
+        }
+        "unpin_all" => {
+            let backend = app.state::<WindowBackendState>();
+            let registry = app.state::<Arc<PinRegistry>>();
+            pin_registry::unpin_all(backend.0.as_ref(), &registry)?;
+        }
+        "clear_click_through" => {
+            app_tool::clear_click_through(app)?;
+        }
+        "setting" => {
+            let mut config = WindowConfig::default();
+            config.title = "系统设置".to_string();
+            config.label = "setting".to_string();
+            config.url = tauri::WebviewUrl::App("/#/setting".into());
+            config.maximizable = false;
+            config.resizable = false;
+            config.width = 300.0;
+            config.height = 360.0;
+            config.visible = false;
+            config.decorations = false;
+            config.transparent = true;
+            config.always_on_top = true;
+            app_tool::show_window(app, config)?;
+        }
+        "check_update" => {
+            update_tool::check_update(app);
+        }
+        "restart" => {
+            //重启后是新的进程，同样按退出策略处理置顶
+            app_tool::release_pins(app);
+            app.restart();
+        }
+        "quit" => {
+            app_tool::close_all_and_exit(app);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// 置顶打开托盘菜单前的前台窗口
+fn pin_last_foreground(app: &AppHandle) -> anyhow::Result<()> {
+    let hwnd = LAST_FOREGROUND
+        .lock()
+        .unwrap()
+        .context("没有可置顶的前台窗口")?;
+    let backend = app.state::<WindowBackendState>();
+    let registry = app.state::<Arc<PinRegistry>>();
+    let backend = backend.0.as_ref();
+    let window = backend.window_info(hwnd).context("前台窗口已关闭")?;
+    let hwnd_self = app_tool::get_self_window_id(app, constant::APP_MAIN_WINDOW_LABEL);
+    pin_registry::set_pinned(backend, &registry, hwnd, true, None, hwnd_self)?;
+    let _ = app.emit("window_picked", window);
+    Ok(())
+}
+
+/// 记录前台窗口，本程序的窗口（主窗口、托盘菜单等）不记录
+fn remember_foreground(app: &AppHandle) {
+    let Some(backend) = app.try_state::<WindowBackendState>() else {
+        return;
+    };
+    let Some(hwnd) = backend.0.foreground_window() else {
+        return;
+    };
+    let mut last = LAST_FOREGROUND.lock().unwrap();
+    if *last == Some(hwnd) {
+        return;
+    }
+    if backend
+        .0
+        .window_info(hwnd)
+        .is_some_and(|window| window.pid != std::process::id())
+    {
+        *last = Some(hwnd);
+    }
+}
+
+/// 双击托盘图标显示主窗口
+fn on_tray_icon_event(tray: &TrayIcon<Wry>, event: &TrayIconEvent) {
+    let TrayIconEvent::DoubleClick {
+        button: MouseButton::Left,
+        ..
+    } = event
+    else {
+        return;
+    };
+    //无界面启动时主窗口还没有创建
+    if let Err(e) = app_tool::show_main_window(tray.app_handle()) {
+        app_tool::send_error_to_frontend(tray.app_handle(), e);
+    }
+}
+
+/// 刷新托盘提示，列出置顶的窗口和定时置顶的剩余时间
+pub fn refresh_tray_tooltip(app: &AppHandle) {
+    let (Some(tray), Some(registry)) = (
+        app.tray_by_id(constant::APP_TRAY_ID),
+        app.try_state::<Arc<PinRegistry>>(),
+    ) else {
+        return;
+    };
+    let tooltip = tooltip_text(&registry.list(), pin_registry::now_millis());
+    let _ = tray.set_tooltip(Some(tooltip));
+}
+
+/// 托盘提示的文字，超出长度时省略剩余的窗口
+fn tooltip_text(pins: &[PinInfo], now: u64) -> String {
+    let mut tooltip = constant::APP_DISPLAY_NAME.to_string();
+    if !pins.is_empty() {
+        tooltip.push_str(&format!("\n已置顶 {} 个窗口", pins.len()));
+    }
+    for (index, pin) in pins.iter().enumerate() {
+        let mut line = format!("\n{}", pin.display_name);
+        if let Some(expires_at) = pin.expires_at {
+            let secs = expires_at.saturating_sub(now) / 1000;
+            line.push_str(&format!(" 剩余 {}", format_remaining(secs)));
+        }
+        if pin.suspended {
+            line.push_str("（已暂停）");
+        }
+        let rest = format!("\n……等 {} 个", pins.len() - index);
+        let remaining = pins.len() - index - 1;
+        //放不下当前这一行（且后面还有窗口时要留出省略行）就省略剩余的窗口
+        let reserved = if remaining > 0 {
+            rest.chars().count()
+        } else {
+            0
+        };
+        if tooltip.chars().count() + line.chars().count() + reserved > TOOLTIP_MAX_CHARS {
+            tooltip.push_str(&rest);
+            break;
+        }
+        tooltip.push_str(&line);
+    }
+    tooltip
+}
+
+/// 剩余时间格式化为 mm:ss 或 h:mm:ss
+pub fn format_remaining(secs: u64) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::window_backend::WindowInfo;
+
+    fn pin(display_name: &str, title: &str) -> PinInfo {
+        PinInfo::new(
+            &WindowInfo {
+                hwnd: 1,
+                display_name: display_name.to_string(),
+                title: title.to_string(),
+                ..Default::default()
+            },
+            false,
+        )
+    }
+
+    #[test]
+    fn format_remaining_time() {
+        assert_eq!(format_remaining(0), "00:00");
+        assert_eq!(format_remaining(59), "00:59");
+        assert_eq!(format_remaining(600), "10:00");
+        assert_eq!(format_remaining(3599), "59:59");
+        assert_eq!(format_remaining(3600), "1:00:00");
+        assert_eq!(format_remaining(90061), "25:01:01");
+    }
+
+    #[test]
+    fn pin_label_combines_name_and_title() {
+        assert_eq!(pin_label(&pin("记事本", "a.txt")), "记事本 - a.txt");
+        assert_eq!(pin_label(&pin("记事本", "记事本")), "记事本");
+        assert_eq!(pin_label(&pin("记事本", "")), "记事本");
+        assert_eq!(pin_label(&pin("", "a.txt")), "a.txt");
+        let label = pin_label(&pin("记事本", &"长".repeat(60)));
+        assert_eq!(label.chars().count(), MENU_LABEL_MAX_CHARS);
+        assert!(label.ends_with('…'));
+    }
+
+    #[test]
+    fn tooltip_lists_pins_within_limit() {
+        assert_eq!(tooltip_text(&[], 0), constant::APP_DISPLAY_NAME);
+
+        let timed = PinInfo {
+            expires_at: Some(61_500),
+            ..pin("记事本", "a.txt")
+        };
+        let suspended = PinInfo {
+            suspended: true,
+            ..pin("画图", "")
+        };
+        assert_eq!(
+            tooltip_text(&[timed, suspended], 0),
+            format!(
+                "{}\n已置顶 2 个窗口\n记事本 剩余 01:01\n画图（已暂停）",
+                constant::APP_DISPLAY_NAME
+            )
+        );
+        //定时已到但还没有取消时显示 00:00
+        let expired = PinInfo {
+            expires_at: Some(1000),
+            ..pin("记事本", "")
+        };
+        assert!(tooltip_text(&[expired], 5000).ends_with("剩余 00:00"));
+
+        let pins: Vec<PinInfo> = (0..20)
+            .map(|i| pin(&format!("程序名称比较长的窗口 {}", i), ""))
+            .collect();
+        let tooltip = tooltip_text(&pins, 0);
+        assert!(tooltip.chars().count() <= TOOLTIP_MAX_CHARS, "{}", tooltip);
+        let shown = tooltip
+            .lines()
+            .filter(|l| l.starts_with("程序名称"))
+            .count();
+        assert!(tooltip.ends_with(&format!("……等 {} 个", pins.len() - shown)));
+    }
+}
@@ -0,0 +1,467 @@
+use crate::tool::error_tool;
+use crate::tool::window_backend::{BackendCapabilities, RawWindowEvent, WindowBackend, WindowInfo};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_registry::WlRegistry, wl_seat::WlSeat};
+use wayland_client::{
+    delegate_noop, event_created_child, Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols_plasma::plasma_window_management::client::{
+    org_kde_plasma_window::{self, OrgKdePlasmaWindow},
+    org_kde_plasma_window_management::{self, OrgKdePlasmaWindowManagement},
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+// org_kde_plasma_window_management.state 中用到的状态位
+const PLASMA_STATE_ACTIVE: u32 = 0x1;
+const PLASMA_STATE_MINIMIZED: u32 = 0x2;
+const PLASMA_STATE_KEEP_ABOVE: u32 = 0x10;
+const PLASMA_STATE_SKIP_TASKBAR: u32 = 0x1000;
+
+/// window_with_uuid / get_window_by_uuid 从第 13 版开始提供
+const PLASMA_UUID_SINCE: u32 = 13;
+
+#[derive(Clone)]
+enum ToplevelHandle {
+    Wlr(ZwlrForeignToplevelHandleV1),
+    Plasma(OrgKdePlasmaWindow),
+}
+
+struct Toplevel {
+    id: isize,
+    handle: ToplevelHandle,
+    title: String,
+    app_id: String,
+    pid: Option<u32>,
+    keep_above: bool,
+    skip_taskbar: bool,
+    //收到 done/initial_state 后才认为窗口信息完整
+    ready: bool,
+}
+
+#[derive(Default)]
+struct Toplevels {
+    windows: HashMap<ObjectId, Toplevel>,
+    next_id: isize,
+    sender: Option<Sender<RawWindowEvent>>,
+}
+
+impl Toplevels {
+    fn entry(&mut self, object: ObjectId, handle: ToplevelHandle) -> &mut Toplevel {
+        let next_id = &mut self.next_id;
+        self.windows.entry(object).or_insert_with(|| {
+            *next_id += 1;
+            Toplevel {
+                id: *next_id,
+                handle,
+                title: String::new(),
+                app_id: String::new(),
+                pid: None,
+                keep_above: false,
+                skip_taskbar: false,
+                ready: false,
+            }
+        })
+    }
+
+    fn find(&self, id: isize) -> Option<&Toplevel> {
+        self.windows.values().find(|t| t.id == id)
+    }
+
+    fn notify(&self, event: RawWindowEvent) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(event);
+        }
+    }
+
+    fn remove(&mut self, object: &ObjectId) {
+        if let Some(toplevel) = self.windows.remove(object) {
+            self.notify(RawWindowEvent::Destroyed(toplevel.id));
+        }
+    }
+
+    /// 窗口信息发送完毕：首次完整时上报创建，之后上报变化
+    fn done(&mut self, object: &ObjectId) {
+        let Some(toplevel) = self.windows.get_mut(object) else {
+            return;
+        };
+        let event = if toplevel.ready {
+            RawWindowEvent::Changed(toplevel.id)
+        } else {
+            toplevel.ready = true;
+            RawWindowEvent::Created(toplevel.id)
+        };
+        self.notify(event);
+    }
+}
+
+impl Toplevel {
+    fn to_window_info(&self) -> WindowInfo {
+        let exe_name = self
+            .pid
+            .and_then(|pid| std::fs::read_link(format!("/proc/{}/exe", pid)).ok())
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().to_string());
+        WindowInfo {
+            hwnd: self.id,
+            pid: self.pid.unwrap_or(0),
+            title: self.title.clone(),
+            name: exe_name.unwrap_or_else(|| self.app_id.clone()),
+            display_name: self.app_id.clone(),
+            class: self.app_id.clone(),
+            icon_key: String::new(),
+            is_top_most: self.keep_above,
+            pin_expires_at: None,
+            pin_suspended: false,
+            pin_click_through: false,
+        }
+    }
+
+    /// 是否应出现在窗口列表中，本程序自身的窗口只有 KDE 协议会提供 pid 用于排除
+    fn is_listed(&self) -> bool {
+        self.ready
+            && !self.skip_taskbar
+            && !self.title.is_empty()
+            && self.pid != Some(std::process::id())
+    }
+}
+
+/// 事件队列线程持有的状态，窗口列表通过 Arc 与后端共享
+struct WaylandState {
+    toplevels: Arc<Mutex<Toplevels>>,
+    plasma_version: u32,
+}
+
+/// 基于 wlr-foreign-toplevel-management 与 KDE plasma-window-management 的 Wayland 后端
+///
+/// Wayland 没有全局窗口 id，窗口标识为本程序按出现顺序分配的序号。
+/// 只有 KDE 的协议可以修改“保持在其它窗口之上”，wlroots 系合成器下只能列出窗口。
+pub struct WaylandBackend {
+    conn: Connection,
+    toplevels: Arc<Mutex<Toplevels>>,
+    seat: Option<WlSeat>,
+    plasma: bool,
+}
+
+impl WaylandBackend {
+    /// 连接环境变量 `WAYLAND_DISPLAY` 指定的合成器
+    pub fn connect() -> anyhow::Result<Self> {
+        let conn = Connection::connect_to_env().context("连接Wayland合成器失败")?;
+        let (globals, mut event_queue) = registry_queue_init::<WaylandState>(&conn)?;
+        let qh = event_queue.handle();
+
+        let toplevels = Arc::new(Mutex::new(Toplevels::default()));
+        let seat = globals.bind::<WlSeat, _, _>(&qh, 1..=1, ()).ok();
+        //KDE 的协议能修改置顶状态，优先使用
+        let plasma = globals
+            .bind::<OrgKdePlasmaWindowManagement, _, _>(&qh, 1..=16, ())
+            .ok();
+        let plasma_version = plasma.as_ref().map(|p| p.version()).unwrap_or(0);
+        if plasma.is_none() {
+            globals
+                .bind::<ZwlrForeignToplevelManagerV1, _, _>(&qh, 1..=3, ())
+                .context("合成器不支持窗口管理协议")?;
+        }
+
+        let mut state = WaylandState {
+            toplevels: toplevels.clone(),
+            plasma_version,
+        };
+        //两次往返：第一次拿到窗口对象，第二次拿到窗口的标题等信息
+        event_queue.roundtrip(&mut state)?;
+        event_queue.roundtrip(&mut state)?;
+
+        std::thread::spawn(move || loop {
+            if let Err(e) = event_queue.blocking_dispatch(&mut state) {
+                error_tool::report_error(format!("Wayland事件处理失败：{}", e));
+                break;
+            }
+        });
+
+        Ok(WaylandBackend {
+            conn,
+            toplevels,
+            seat,
+            plasma: plasma.is_some(),
+        })
+    }
+
+    fn handle(&self, hwnd: isize) -> anyhow::Result<ToplevelHandle> {
+        let toplevels = self.toplevels.lock().unwrap();
+        toplevels
+            .find(hwnd)
+            .map(|t| t.handle.clone())
+            .ok_or_else(|| anyhow::anyhow!("窗口不存在：{}", hwnd))
+    }
+
+    fn set_plasma_state(&self, hwnd: isize, flags: u32, enable: bool) -> anyhow::Result<()> {
+        match self.handle(hwnd)? {
+            ToplevelHandle::Plasma(window) => {
+                window.set_state(flags, if enable { flags } else { 0 });
+                self.conn.flush()?;
+                Ok(())
+            }
+            ToplevelHandle::Wlr(_) => Err(anyhow::anyhow!("当前合成器不支持置顶窗口")),
+        }
+    }
+}
+
+impl WindowBackend for WaylandBackend {
+    fn enum_windows(&self, exclude: Option<isize>) -> Vec<WindowInfo> {
+        let toplevels = self.toplevels.lock().unwrap();
+        let mut list: Vec<WindowInfo> = toplevels
+            .windows
+            .values()
+            .filter(|t| t.is_listed() && Some(t.id) != exclude)
+            .map(Toplevel::to_window_info)
+            .collect();
+        list.sort_by_key(|w| w.hwnd);
+        list
+    }
+
+    fn window_info(&self, hwnd: isize) -> Option<WindowInfo> {
+        let toplevels = self.toplevels.lock().unwrap();
+        toplevels
+            .find(hwnd)
+            .filter(|t| t.is_listed())
+            .map(Toplevel::to_window_info)
+    }
+
+    fn is_topmost(&self, hwnd: isize) -> bool {
+        let toplevels = self.toplevels.lock().unwrap();
+        toplevels.find(hwnd).map(|t| t.keep_above).unwrap_or(false)
+    }
+
+    fn set_topmost(&self, hwnd: isize, enable: bool) -> anyhow::Result<()> {
+        self.set_plasma_state(hwnd, PLASMA_STATE_KEEP_ABOVE, enable)
+    }
+
+    fn restore(&self, hwnd: isize) -> anyhow::Result<()> {
+        match self.handle(hwnd)? {
+            ToplevelHandle::Wlr(handle) => {
+                handle.unset_minimized();
+                self.conn.flush()?;
+                Ok(())
+            }
+            ToplevelHandle::Plasma(_) => self.set_plasma_state(hwnd, PLASMA_STATE_MINIMIZED, false),
+        }
+    }
+
+    fn focus(&self, hwnd: isize) -> anyhow::Result<()> {
+        match self.handle(hwnd)? {
+            ToplevelHandle::Wlr(handle) => {
+                let seat = self.seat.as_ref().context("合成器未提供wl_seat")?;
+                handle.activate(seat);
+                self.conn.flush()?;
+                Ok(())
+            }
+            ToplevelHandle::Plasma(_) => self.set_plasma_state(hwnd, PLASMA_STATE_ACTIVE, true),
+        }
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            backend: if self.plasma {
+                "wayland-plasma"
+            } else {
+                "wayland-wlr"
+            }
+            .to_string(),
+            can_set_topmost: self.plasma,
+            can_set_opacity: false,
+            can_pick_window: false,
+            can_click_through: false,
+            can_draw_border: false,
+            can_move_window: false,
+        }
+    }
+
+    fn watch(&self, sender: Sender<RawWindowEvent>) -> anyhow::Result<()> {
+        self.toplevels.lock().unwrap().sender = Some(sender);
+        Ok(())
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(WaylandState: ignore WlSeat);
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            let mut toplevels = state.toplevels.lock().unwrap();
+            toplevels.entry(toplevel.id(), ToplevelHandle::Wlr(toplevel.clone()));
+        }
+    }
+
+    event_created_child!(WaylandState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let mut toplevels = state.toplevels.lock().unwrap();
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                toplevels.remove(&handle.id());
+                handle.destroy();
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => toplevels.done(&handle.id()),
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
+                toplevels
+                    .entry(handle.id(), ToplevelHandle::Wlr(handle.clone()))
+                    .title = title;
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                toplevels
+                    .entry(handle.id(), ToplevelHandle::Wlr(handle.clone()))
+                    .app_id = app_id;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<OrgKdePlasmaWindowManagement, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        manager: &OrgKdePlasmaWindowManagement,
+        event: org_kde_plasma_window_management::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let window = match event {
+            org_kde_plasma_window_management::Event::Window { id }
+                if state.plasma_version < PLASMA_UUID_SINCE =>
+            {
+                manager.get_window(id, qh, ())
+            }
+            org_kde_plasma_window_management::Event::WindowWithUuid { uuid, .. } => {
+                manager.get_window_by_uuid(uuid, qh, ())
+            }
+            _ => return,
+        };
+        let mut toplevels = state.toplevels.lock().unwrap();
+        toplevels.entry(window.id(), ToplevelHandle::Plasma(window.clone()));
+    }
+}
+
+impl Dispatch<OrgKdePlasmaWindow, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        window: &OrgKdePlasmaWindow,
+        event: org_kde_plasma_window::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let mut toplevels = state.toplevels.lock().unwrap();
+        match event {
+            org_kde_plasma_window::Event::Unmapped => {
+                toplevels.remove(&window.id());
+                if window.version() >= 4 {
+                    window.destroy();
+                }
+                return;
+            }
+            org_kde_plasma_window::Event::InitialState => {
+                toplevels.done(&window.id());
+                return;
+            }
+            _ => {}
+        }
+
+        let toplevel = toplevels.entry(window.id(), ToplevelHandle::Plasma(window.clone()));
+        match event {
+            org_kde_plasma_window::Event::TitleChanged { title } => toplevel.title = title,
+            org_kde_plasma_window::Event::AppIdChanged { app_id } => toplevel.app_id = app_id,
+            org_kde_plasma_window::Event::PidChanged { pid } => toplevel.pid = Some(pid),
+            org_kde_plasma_window::Event::StateChanged { flags } => {
+                toplevel.keep_above = flags & PLASMA_STATE_KEEP_ABOVE != 0;
+                toplevel.skip_taskbar = flags & PLASMA_STATE_SKIP_TASKBAR != 0;
+            }
+            _ => return,
+        }
+        //KDE 协议没有 done 事件，初始状态之后的每次属性变化都直接上报；
+        //第 4 版之前没有 initial_state，收到属性即认为信息完整
+        if window.version() < 4 || toplevel.ready {
+            toplevels.done(&window.id());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 合成器异步推送窗口，最多等待 5 秒
+    fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+        for _ in 0..50 {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        false
+    }
+
+    /// 在无界面的 sway 中打开一个客户端（默认 foot，可用 `WAYLAND_TEST_CLIENT` 指定），检查能列出窗口但不能置顶
+    #[test]
+    #[ignore = "需要 wlroots 合成器：WLR_BACKENDS=headless sway & 设置 WAYLAND_DISPLAY 后 cargo test -- --ignored"]
+    fn list_toplevels_on_headless_sway() {
+        let backend = WaylandBackend::connect().unwrap();
+        let capabilities = backend.capabilities();
+        assert_eq!(capabilities.backend, "wayland-wlr");
+        assert!(!capabilities.can_set_topmost);
+
+        let before = backend.enum_windows(None).len();
+        let client = std::env::var("WAYLAND_TEST_CLIENT").unwrap_or_else(|_| "foot".to_string());
+        let mut child = std::process::Command::new(client).spawn().unwrap();
+        let listed = wait_until(|| backend.enum_windows(None).len() > before);
+        let windows = backend.enum_windows(None);
+        let _ = child.kill();
+        let _ = child.wait();
+
+        assert!(listed);
+        let window = windows.last().unwrap();
+        assert!(backend.window_info(window.hwnd).is_some());
+        assert!(!window.is_top_most);
+        assert!(backend.set_topmost(window.hwnd, true).is_err());
+    }
+}
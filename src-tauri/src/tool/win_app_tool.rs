@@ -1,19 +1,30 @@
 #![allow(dead_code)]
+use crate::tool::icon_cache::IconCache;
+use crate::tool::win_overlay;
+use crate::tool::window_backend::{
+    BackendCapabilities, BorderStyle, IconImage, OpacityStyle, RawWindowEvent, Rect, WindowBackend,
+    WindowInfo,
+};
 use anyhow::Context;
 use base64::{engine::general_purpose, Engine};
 use serde::Serialize;
 #[cfg(target_os = "windows")]
 use std::mem::{size_of, zeroed};
 use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use std::{ffi::OsStr, os::windows::ffi::OsStrExt, ptr::null_mut};
-use tauri::Manager;
 use windows::core::{Interface, PCWSTR, PWSTR};
 use windows::Win32::Foundation::{
-    CloseHandle, BOOL, HWND, LPARAM, LRESULT, MAX_PATH, SIZE, WPARAM,
+    CloseHandle, BOOL, COLORREF, HINSTANCE, HWND, LPARAM, LRESULT, MAX_PATH, POINT, RECT, SIZE,
+    WPARAM,
 };
+use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_CLOAKED};
 use windows::Win32::Graphics::Gdi::{
-    DeleteObject, GetDC, GetDIBits, GetObjectW, ReleaseDC, BITMAP, BITMAPINFO, BITMAPINFOHEADER,
-    BI_RGB, DIB_RGB_COLORS,
+    DeleteObject, EnumDisplayMonitors, GetDC, GetDIBits, GetMonitorInfoW, GetObjectW,
+    MonitorFromWindow, RedrawWindow, ReleaseDC, BITMAP, BITMAPINFO, BITMAPINFOHEADER, BI_RGB,
+    DIB_RGB_COLORS, HDC, HMONITOR, HRGN, MONITORINFO, MONITOR_DEFAULTTONEAREST, RDW_ALLCHILDREN,
+    RDW_ERASE, RDW_FRAME, RDW_INVALIDATE,
 };
 use windows::Win32::Storage::FileSystem::{
     GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW,
@@ -23,29 +34,37 @@ use windows::Win32::System::Com::{
     COINIT_APARTMENTTHREADED, STGM_READ,
 };
 use windows::Win32::System::Threading::{
-    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_FORMAT, PROCESS_QUERY_LIMITED_INFORMATION,
+    GetCurrentProcessId, OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_FORMAT,
+    PROCESS_QUERY_LIMITED_INFORMATION,
 };
+use windows::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
 use windows::Win32::UI::Shell::{
     IShellItem, IShellItemImageFactory, IShellLinkW, SHCreateItemFromParsingName, ShellLink,
     SIIGBF_BIGGERSIZEOK,
 };
 use windows::Win32::UI::WindowsAndMessaging::{
-    EnumWindows, GetWindowLongPtrW, GetWindowLongW, GetWindowThreadProcessId, IsIconic,
-    IsWindowVisible, SendMessageTimeoutW, SetForegroundWindow, SetWindowPos, ShowWindow,
-    GWL_EXSTYLE, GWL_STYLE, HWND_NOTOPMOST, HWND_TOPMOST, SMTO_ABORTIFHUNG, SWP_NOMOVE, SWP_NOSIZE,
-    SWP_SHOWWINDOW, SW_RESTORE, SW_SHOW, WM_GETTEXT, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_VISIBLE,
+    CallNextHookEx, CopyIcon, DispatchMessageW, EnumWindows, GetAncestor, GetClassNameW,
+    GetDesktopWindow, GetForegroundWindow, GetLayeredWindowAttributes, GetMessageW, GetWindow,
+    GetWindowLongPtrW, GetWindowLongW, GetWindowRect, GetWindowThreadProcessId, IsIconic, IsWindow,
+    IsWindowVisible, IsZoomed, LoadCursorW, PostQuitMessage, SendMessageTimeoutW,
+    SetForegroundWindow, SetLayeredWindowAttributes, SetSystemCursor, SetWindowLongPtrW,
+    SetWindowPos, SetWindowsHookExW, ShowWindow, SystemParametersInfoW, TranslateMessage,
+    UnhookWindowsHookEx, WindowFromPoint, CHILDID_SELF, EVENT_OBJECT_CREATE, EVENT_OBJECT_DESTROY,
+    EVENT_OBJECT_HIDE, EVENT_OBJECT_NAMECHANGE, EVENT_OBJECT_SHOW, GA_PARENT, GA_ROOT, GWL_EXSTYLE,
+    GWL_STYLE, GW_HWNDPREV, GW_OWNER, HCURSOR, HHOOK, HICON, HWND_NOTOPMOST, HWND_TOPMOST,
+    IDC_CROSS, KBDLLHOOKSTRUCT, LAYERED_WINDOW_ATTRIBUTES_FLAGS, LWA_ALPHA, MSG, MSLLHOOKSTRUCT,
+    OBJID_WINDOW, OCR_NORMAL, SMTO_ABORTIFHUNG, SPI_SETCURSORS, SWP_NOACTIVATE, SWP_NOMOVE,
+    SWP_NOSIZE, SWP_NOZORDER, SWP_SHOWWINDOW, SW_RESTORE, SW_SHOW,
+    SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS, WH_KEYBOARD_LL, WH_MOUSE_LL, WINEVENT_OUTOFCONTEXT,
+    WINEVENT_SKIPOWNPROCESS, WM_GETTEXT, WM_KEYDOWN, WM_LBUTTONDOWN, WM_LBUTTONUP, WS_EX_LAYERED,
+    WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_VISIBLE,
 };
 
-pub struct IconImage {
-    pub width: u32,
-    pub height: u32,
-    pub rgba: Vec<u8>, // RGBA8888
-}
 #[derive(Serialize)]
 pub struct AppMeta {
     pub name: String,
     pub path: String,
-    pub icon_png: String,
+    pub icon_key: String,
     pub display_name: String,
     pub is_top_most: bool,
 }
@@ -126,15 +145,7 @@ pub fn get_icon_base64(path: &str, size: i32) -> anyhow::Result<String> {
     Ok(b64)
 }
 pub fn get_png_base64(path: &str, size: i32) -> anyhow::Result<String> {
-    let icon = load_exe_icon(path, size)?;
-    let img =
-        image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(icon.width, icon.height, icon.rgba)
-            .ok_or_else(|| anyhow::anyhow!("image error"))?;
-
-    let mut png = Vec::new();
-    img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
-
-    Ok(base64::engine::general_purpose::STANDARD.encode(png))
+    load_exe_icon(path, size)?.into_png_base64()
 }
 /// 表示解析到的快捷方式信息
 #[derive(Debug, Clone)]
@@ -143,16 +154,16 @@ pub struct LnkInfo {
     pub target_path: Option<PathBuf>,
 }
 
-pub fn get_app_info_by_path(path: &str) -> anyhow::Result<AppMeta> {
+pub fn get_app_info_by_path(path: &str, icon_cache: &IconCache) -> anyhow::Result<AppMeta> {
     let ext = path.split(".").last().unwrap_or("").to_lowercase();
     match ext.as_str() {
-        "exe" => get_app_info(path),
+        "exe" => get_app_info(path, icon_cache),
         "lnk" => {
             let info = resolve_lnk(&PathBuf::from(path))?;
             if let Some(target) = info.clone().target_path {
                 if let Some(path) = target.to_str() {
                     if path.ends_with(".exe") {
-                        return get_app_info(path);
+                        return get_app_info(path, icon_cache);
                     }
                 }
             }
@@ -164,13 +175,13 @@ pub fn get_app_info_by_path(path: &str) -> anyhow::Result<AppMeta> {
     }
 }
 
-pub unsafe fn get_app_info_by_hwnd(hwnd: isize) -> anyhow::Result<AppMeta> {
+pub unsafe fn get_app_info_by_hwnd(hwnd: isize, icon_cache: &IconCache) -> anyhow::Result<AppMeta> {
     // PID
     let mut pid = 0;
     GetWindowThreadProcessId(HWND(hwnd), Some(&mut pid));
 
     if let Some(path) = get_process_exe_path(pid) {
-        let mut app_info = get_app_info_by_path(&path)?;
+        let mut app_info = get_app_info_by_path(&path, icon_cache)?;
         let is_top_most = is_window_topmost(HWND(hwnd));
         app_info.is_top_most = is_top_most;
         return Ok(app_info);
@@ -179,18 +190,17 @@ pub unsafe fn get_app_info_by_hwnd(hwnd: isize) -> anyhow::Result<AppMeta> {
     }
 }
 
-pub fn get_app_info(path: &str) -> anyhow::Result<AppMeta> {
+pub fn get_app_info(path: &str, icon_cache: &IconCache) -> anyhow::Result<AppMeta> {
     let exe_name = path.rsplit('\\').next().unwrap_or("").replace(".exe", "");
     let names = get_app_names(path);
     let display_name = names
         .file_description
         .or(names.product_name)
         .unwrap_or(exe_name.clone());
-    let icon_base64 = get_png_base64(path, 256)?;
     Ok(AppMeta {
         name: exe_name,
         path: path.to_string(),
-        icon_png: icon_base64,
+        icon_key: icon_cache.register(path),
         display_name,
         is_top_most: false,
     })
@@ -240,16 +250,6 @@ pub fn resolve_lnk(lnk_path: &PathBuf) -> anyhow::Result<LnkInfo> {
     }
 }
 
-#[derive(serde::Serialize, Debug, Default)]
-pub struct WindowInfo {
-    pub hwnd: isize,
-    pub title: String,
-    pub name: String,
-    pub display_name: String,
-    pub icon_png: String,
-    pub is_top_most: bool,
-}
-
 pub unsafe fn get_window_title_safe(hwnd: HWND) -> Option<String> {
     let mut buffer = [0u16; 256];
 
@@ -274,38 +274,58 @@ pub unsafe fn get_window_title_safe(hwnd: HWND) -> Option<String> {
     ))
 }
 
-unsafe extern "system" fn enum_windows_proc(hwnd: HWND, lparam: LPARAM) -> BOOL {
+/// 获取窗口类名
+pub unsafe fn get_window_class(hwnd: HWND) -> String {
+    let mut buffer = [0u16; 256];
+    let len = GetClassNameW(hwnd, &mut buffer);
+    String::from_utf16_lossy(&buffer[..len.max(0) as usize])
+}
+
+/// 获取单个顶层窗口的信息，不可见窗口、工具窗口及无标题窗口返回 None
+pub unsafe fn get_window_info(hwnd: HWND, icon_cache: &IconCache) -> Option<WindowInfo> {
     if !IsWindowVisible(hwnd).as_bool() {
-        return BOOL(1);
+        return None;
     }
     // 排除工具窗口 / 子窗口
     let style = GetWindowLongW(hwnd, GWL_STYLE);
     if style & WS_VISIBLE.0 as i32 == 0 {
-        return BOOL(1);
+        return None;
     }
 
     // 排除没有标题栏的窗口
     let ex = GetWindowLongW(hwnd, GWL_EXSTYLE);
     if ex & WS_EX_TOOLWINDOW.0 as i32 != 0 {
-        return BOOL(1);
+        return None;
     }
+
+    let title = get_window_title_safe(hwnd)?;
+    let mut pid = 0;
+    GetWindowThreadProcessId(hwnd, Some(&mut pid));
+    let app_info = get_app_info_by_hwnd(hwnd.0, icon_cache).ok()?;
+    Some(WindowInfo {
+        hwnd: hwnd.0,
+        pid,
+        title,
+        name: app_info.name,
+        display_name: app_info.display_name,
+        class: get_window_class(hwnd),
+        icon_key: app_info.icon_key,
+        is_top_most: app_info.is_top_most,
+        pin_expires_at: None,
+        pin_suspended: false,
+        pin_click_through: false,
+    })
+}
+
+unsafe extern "system" fn enum_windows_proc(hwnd: HWND, lparam: LPARAM) -> BOOL {
     let ctx = &mut *(lparam.0 as *mut EnumWindowsContext);
     if ctx.exclude_hwnd.is_some() && hwnd == ctx.exclude_hwnd.unwrap() {
         return BOOL(1);
     }
 
     let list = &mut *(ctx.list);
-    if let Some(title) = get_window_title_safe(hwnd) {
-        if let Ok(app_info) = get_app_info_by_hwnd(hwnd.0 as isize) {
-            list.push(WindowInfo {
-                hwnd: hwnd.0 as isize,
-                title,
-                name: app_info.name,
-                display_name: app_info.display_name,
-                icon_png: app_info.icon_png,
-                is_top_most: app_info.is_top_most,
-            });
-        }
+    if let Some(info) = get_window_info(hwnd, &*ctx.icon_cache) {
+        list.push(info);
     }
 
     BOOL(1)
@@ -314,13 +334,15 @@ unsafe extern "system" fn enum_windows_proc(hwnd: HWND, lparam: LPARAM) -> BOOL
 struct EnumWindowsContext {
     list: *mut Vec<WindowInfo>,
     exclude_hwnd: Option<HWND>,
+    icon_cache: *const IconCache,
 }
-pub fn enum_windows(hwnd_self: Option<HWND>) -> Vec<WindowInfo> {
+pub fn enum_windows(hwnd_self: Option<HWND>, icon_cache: &IconCache) -> Vec<WindowInfo> {
     let mut list = Vec::new();
 
     let mut ctx = EnumWindowsContext {
         list: &mut list as *mut _,
         exclude_hwnd: hwnd_self,
+        icon_cache: icon_cache as *const _,
     };
 
     unsafe {
@@ -334,66 +356,482 @@ pub fn enum_windows(hwnd_self: Option<HWND>) -> Vec<WindowInfo> {
     list
 }
 
-pub fn show_and_topmost(hwnd: isize, hwnd_self: Option<isize>) {
-    unsafe {
-        let hwnd = HWND(hwnd);
+/// SetWinEventHook 的回调没有用户数据参数，只能通过全局变量上报事件
+static WATCH_SENDER: Mutex<Option<Sender<RawWindowEvent>>> = Mutex::new(None);
+
+unsafe extern "system" fn win_event_proc(
+    _hook: HWINEVENTHOOK,
+    event: u32,
+    hwnd: HWND,
+    id_object: i32,
+    id_child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    //只关心顶层窗口本身，忽略窗口内的控件等对象
+    if hwnd.0 == 0 || id_object != OBJID_WINDOW.0 || id_child != CHILDID_SELF as i32 {
+        return;
+    }
+    //异步收到销毁事件时窗口已经不存在，无法再判断是否为顶层窗口；
+    //不在快照中的窗口（如子窗口）会被监听子系统忽略
+    if event != EVENT_OBJECT_DESTROY && GetAncestor(hwnd, GA_ROOT) != hwnd {
+        return;
+    }
+    let raw = match event {
+        EVENT_OBJECT_CREATE | EVENT_OBJECT_SHOW => RawWindowEvent::Created(hwnd.0),
+        EVENT_OBJECT_DESTROY => RawWindowEvent::Destroyed(hwnd.0),
+        //隐藏（如最小化到托盘）的窗口仍然存在，重新查询后按变化处理
+        EVENT_OBJECT_HIDE | EVENT_OBJECT_NAMECHANGE => RawWindowEvent::Changed(hwnd.0),
+        _ => return,
+    };
+    if let Some(sender) = WATCH_SENDER.lock().unwrap().as_ref() {
+        let _ = sender.send(raw);
+    }
+}
 
-        //如果最小化，先还原
-        if IsIconic(hwnd).as_bool() {
-            let _ = ShowWindow(hwnd, SW_RESTORE);
+/// 拾取窗口的进度，由低级鼠标、键盘钩子更新
+#[derive(Debug, Clone, Copy)]
+enum PickState {
+    Waiting,
+    Pressed(POINT),
+    /// 拾取结束，按 Esc 取消时为空
+    Done(Option<POINT>),
+}
+
+static PICK_STATE: Mutex<PickState> = Mutex::new(PickState::Waiting);
+
+const VK_ESCAPE: u32 = 0x1B;
+
+unsafe extern "system" fn pick_mouse_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 {
+        let info = &*(lparam.0 as *const MSLLHOOKSTRUCT);
+        let mut state = PICK_STATE.lock().unwrap();
+        //吞掉这次点击，避免点到目标窗口里的按钮
+        match wparam.0 as u32 {
+            WM_LBUTTONDOWN => {
+                *state = PickState::Pressed(info.pt);
+                return LRESULT(1);
+            }
+            WM_LBUTTONUP => {
+                if let PickState::Pressed(point) = *state {
+                    *state = PickState::Done(Some(point));
+                    PostQuitMessage(0);
+                    return LRESULT(1);
+                }
+            }
+            _ => {}
         }
+    }
+    CallNextHookEx(HHOOK::default(), code, wparam, lparam)
+}
 
-        //设置为置顶窗口
-        let _ = SetWindowPos(
-            hwnd,
-            HWND_TOPMOST,
-            0,
-            0,
-            0,
-            0,
-            SWP_NOMOVE | SWP_NOSIZE | SWP_SHOWWINDOW,
-        );
+unsafe extern "system" fn pick_keyboard_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 && wparam.0 as u32 == WM_KEYDOWN {
+        let info = &*(lparam.0 as *const KBDLLHOOKSTRUCT);
+        if info.vkCode == VK_ESCAPE {
+            *PICK_STATE.lock().unwrap() = PickState::Done(None);
+            PostQuitMessage(0);
+            return LRESULT(1);
+        }
+    }
+    CallNextHookEx(HHOOK::default(), code, wparam, lparam)
+}
+
+/// 安装低级钩子并等待点击，返回点击位置；钩子在安装它的线程的消息循环中回调
+unsafe fn pick_point() -> anyhow::Result<Option<POINT>> {
+    *PICK_STATE.lock().unwrap() = PickState::Waiting;
+    let mouse_hook =
+        SetWindowsHookExW(WH_MOUSE_LL, Some(pick_mouse_proc), HINSTANCE::default(), 0)?;
+    let keyboard_hook = match SetWindowsHookExW(
+        WH_KEYBOARD_LL,
+        Some(pick_keyboard_proc),
+        HINSTANCE::default(),
+        0,
+    ) {
+        Ok(hook) => hook,
+        Err(e) => {
+            let _ = UnhookWindowsHookEx(mouse_hook);
+            return Err(e.into());
+        }
+    };
+    let cursor = CrossCursor::set();
+    let mut msg = MSG::default();
+    while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+        let _ = TranslateMessage(&msg);
+        DispatchMessageW(&msg);
+    }
+    let _ = UnhookWindowsHookEx(mouse_hook);
+    let _ = UnhookWindowsHookEx(keyboard_hook);
+    drop(cursor);
+    let state = *PICK_STATE.lock().unwrap();
+    Ok(match state {
+        PickState::Done(point) => point,
+        _ => None,
+    })
+}
+
+/// 拾取期间把系统的普通光标换成十字光标，离开作用域（包括 panic 展开）时还原
+///
+/// 系统光标对所有程序生效，程序在拾取中途被结束时由 `Win32Backend::new` 在下次启动时还原
+struct CrossCursor;
+
+impl CrossCursor {
+    unsafe fn set() -> Self {
+        if let Ok(cross) = LoadCursorW(HINSTANCE::default(), IDC_CROSS) {
+            //SetSystemCursor 会销毁传入的光标，需要先复制一份
+            if let Ok(copy) = CopyIcon(HICON(cross.0)) {
+                let _ = SetSystemCursor(HCURSOR(copy.0), OCR_NORMAL);
+            }
+        }
+        CrossCursor
+    }
+}
+
+impl Drop for CrossCursor {
+    fn drop(&mut self) {
+        unsafe { restore_system_cursors() }
+    }
+}
+
+/// 按用户的光标方案重新加载系统光标，撤销 `CrossCursor`
+unsafe fn restore_system_cursors() {
+    let _ = SystemParametersInfoW(
+        SPI_SETCURSORS,
+        0,
+        None,
+        SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+    );
+}
+
+/// Win32 窗口后端
+pub struct Win32Backend {
+    icon_cache: Arc<IconCache>,
+}
+
+impl Win32Backend {
+    pub fn new(icon_cache: Arc<IconCache>) -> Self {
+        //上次运行可能在拾取窗口时被结束，留下了十字光标
+        unsafe { restore_system_cursors() };
+        Win32Backend { icon_cache }
+    }
+}
+
+impl WindowBackend for Win32Backend {
+    fn enum_windows(&self, exclude: Option<isize>) -> Vec<WindowInfo> {
+        enum_windows(exclude.map(HWND), &self.icon_cache)
+    }
 
-        //确保显示
-        let _ = ShowWindow(hwnd, SW_SHOW);
+    fn is_topmost(&self, hwnd: isize) -> bool {
+        is_window_topmost(HWND(hwnd))
+    }
 
-        //请求前台（不保证 100% 成功）
-        let _ = SetForegroundWindow(hwnd);
+    fn is_obscured(&self, hwnd: isize, ignore: &[isize]) -> bool {
+        is_window_obscured(HWND(hwnd), ignore)
+    }
 
-        //设置窗口再次置顶
-        if let Some(hwnd) = hwnd_self {
-            let _ = SetWindowPos(
+    fn set_topmost(&self, hwnd: isize, enable: bool) -> anyhow::Result<()> {
+        let insert_after = if enable { HWND_TOPMOST } else { HWND_NOTOPMOST };
+        unsafe {
+            SetWindowPos(
                 HWND(hwnd),
-                HWND_TOPMOST,
+                insert_after,
                 0,
                 0,
                 0,
                 0,
-                SWP_NOMOVE | SWP_NOSIZE | SWP_SHOWWINDOW,
+                //只改变 z 序，不抢占焦点；需要激活时由 show_and_topmost 负责
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE | SWP_SHOWWINDOW,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn restore(&self, hwnd: isize) -> anyhow::Result<()> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            if IsIconic(hwnd).as_bool() {
+                let _ = ShowWindow(hwnd, SW_RESTORE);
+            }
+        }
+        Ok(())
+    }
+
+    fn focus(&self, hwnd: isize) -> anyhow::Result<()> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            //确保显示
+            let _ = ShowWindow(hwnd, SW_SHOW);
+            SetForegroundWindow(hwnd).ok()?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            backend: "win32".to_string(),
+            can_set_topmost: true,
+            can_set_opacity: true,
+            can_pick_window: true,
+            can_click_through: true,
+            can_draw_border: true,
+            can_move_window: true,
+        }
+    }
+
+    fn window_info(&self, hwnd: isize) -> Option<WindowInfo> {
+        unsafe { get_window_info(HWND(hwnd), &self.icon_cache) }
+    }
+
+    fn window_exists(&self, hwnd: isize) -> bool {
+        unsafe { IsWindow(HWND(hwnd)).as_bool() }
+    }
+
+    fn load_icon(&self, path: &str, size: u32) -> anyhow::Result<IconImage> {
+        load_exe_icon(path, size as i32)
+    }
+
+    fn opacity(&self, hwnd: isize) -> Option<f64> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            let ex_style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32;
+            if ex_style & WS_EX_LAYERED.0 == 0 {
+                return Some(1.0);
+            }
+            let mut alpha = 255u8;
+            let mut flags = LAYERED_WINDOW_ATTRIBUTES_FLAGS(0);
+            GetLayeredWindowAttributes(hwnd, None, Some(&mut alpha), Some(&mut flags)).ok()?;
+            //只设置了颜色键的分层窗口整体不透明
+            if flags.0 & LWA_ALPHA.0 == 0 {
+                return Some(1.0);
+            }
+            Some(alpha as f64 / 255.0)
+        }
+    }
+
+    fn set_opacity(&self, hwnd: isize, opacity: f64) -> anyhow::Result<()> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            //只有分层窗口才能设置透明度
+            let ex_style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE);
+            if ex_style as u32 & WS_EX_LAYERED.0 == 0 {
+                SetWindowLongPtrW(hwnd, GWL_EXSTYLE, ex_style | WS_EX_LAYERED.0 as isize);
+            }
+            let alpha = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+            SetLayeredWindowAttributes(hwnd, COLORREF(0), alpha, LWA_ALPHA)?;
+        }
+        Ok(())
+    }
+
+    fn opacity_style(&self, hwnd: isize) -> Option<OpacityStyle> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            let ex_style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32;
+            if ex_style & WS_EX_LAYERED.0 == 0 {
+                return Some(OpacityStyle {
+                    opacity: 1.0,
+                    ..Default::default()
+                });
+            }
+            let mut color_key = COLORREF(0);
+            let mut alpha = 255u8;
+            let mut flags = LAYERED_WINDOW_ATTRIBUTES_FLAGS(0);
+            GetLayeredWindowAttributes(
+                hwnd,
+                Some(&mut color_key),
+                Some(&mut alpha),
+                Some(&mut flags),
+            )
+            .ok()?;
+            Some(OpacityStyle {
+                opacity: alpha as f64 / 255.0,
+                layered: true,
+                color_key: color_key.0,
+                flags: flags.0,
+            })
+        }
+    }
+
+    /// 原本不是分层窗口时去掉 `WS_EX_LAYERED`，否则还原原来的分层属性
+    fn restore_opacity(&self, hwnd: isize, style: &OpacityStyle) -> anyhow::Result<()> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            if style.layered {
+                let alpha = (style.opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+                SetLayeredWindowAttributes(
+                    hwnd,
+                    COLORREF(style.color_key),
+                    alpha,
+                    LAYERED_WINDOW_ATTRIBUTES_FLAGS(style.flags),
+                )?;
+            } else {
+                let ex_style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE);
+                SetWindowLongPtrW(hwnd, GWL_EXSTYLE, ex_style & !(WS_EX_LAYERED.0 as isize));
+                //去掉分层样式后需要重绘，否则窗口会短暂显示为黑色
+                let _ = RedrawWindow(
+                    hwnd,
+                    None,
+                    HRGN::default(),
+                    RDW_ERASE | RDW_INVALIDATE | RDW_FRAME | RDW_ALLCHILDREN,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// 分层窗口加上 `WS_EX_TRANSPARENT` 后不再接收鼠标输入
+    fn set_click_through(&self, hwnd: isize, enable: bool) -> anyhow::Result<()> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            let ex_style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE);
+            if !enable {
+                SetWindowLongPtrW(
+                    hwnd,
+                    GWL_EXSTYLE,
+                    ex_style & !(WS_EX_TRANSPARENT.0 as isize),
+                );
+                return Ok(());
+            }
+            let layered = ex_style as u32 & WS_EX_LAYERED.0 != 0;
+            SetWindowLongPtrW(
+                hwnd,
+                GWL_EXSTYLE,
+                ex_style | (WS_EX_LAYERED.0 | WS_EX_TRANSPARENT.0) as isize,
             );
+            //新的分层窗口在设置分层属性之前不会显示
+            if !layered {
+                SetLayeredWindowAttributes(hwnd, COLORREF(0), 255, LWA_ALPHA)?;
+            }
         }
+        Ok(())
     }
-}
 
-pub fn show_and_not_topmost(hwnd: isize) {
-    unsafe {
+    fn foreground_window(&self) -> Option<isize> {
+        unsafe {
+            let hwnd = GetForegroundWindow();
+            if hwnd.0 == 0 || DESKTOP_WINDOW_CLASSES.contains(&get_window_class(hwnd).as_str()) {
+                return None;
+            }
+            Some(hwnd.0)
+        }
+    }
+
+    fn window_rect(&self, hwnd: isize) -> Option<Rect> {
         let hwnd = HWND(hwnd);
+        let mut rect = RECT::default();
+        unsafe {
+            if IsIconic(hwnd).as_bool() || !IsWindowVisible(hwnd).as_bool() {
+                return None;
+            }
+            GetWindowRect(hwnd, &mut rect).ok()?;
+        }
+        Some(to_rect(&rect))
+    }
 
-        let _ = SetWindowPos(
-            hwnd,
-            HWND_NOTOPMOST,
-            0,
-            0,
-            0,
-            0,
-            SWP_NOMOVE | SWP_NOSIZE | SWP_SHOWWINDOW,
-        );
+    fn monitor_rect(&self, hwnd: isize) -> Option<Rect> {
+        Some(to_rect(&get_monitor_rect_for_window(HWND(hwnd))))
     }
-}
 
-pub fn cancel_all_topmost(hwnds: Vec<isize>) {
-    for hwnd in hwnds {
-        show_and_not_topmost(hwnd);
+    fn monitors(&self) -> Vec<Rect> {
+        let mut monitors: Vec<Rect> = Vec::new();
+        unsafe {
+            let _ = EnumDisplayMonitors(
+                HDC(0),
+                None,
+                Some(enum_monitor_proc),
+                LPARAM(&mut monitors as *mut Vec<Rect> as isize),
+            );
+        }
+        monitors
+    }
+
+    fn set_window_rect(&self, hwnd: isize, rect: &Rect) -> anyhow::Result<()> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            //最大化的窗口直接移动后仍是最大化状态，还原后再移动
+            if IsIconic(hwnd).as_bool() || IsZoomed(hwnd).as_bool() {
+                let _ = ShowWindow(hwnd, SW_RESTORE);
+            }
+            SetWindowPos(
+                hwnd,
+                HWND(0),
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn parent_window(&self, hwnd: isize) -> Option<isize> {
+        unsafe {
+            let hwnd = HWND(hwnd);
+            let parent = GetAncestor(hwnd, GA_PARENT);
+            if parent.0 != 0 && parent != GetDesktopWindow() {
+                return Some(parent.0);
+            }
+            let owner = GetWindow(hwnd, GW_OWNER);
+            (owner.0 != 0).then_some(owner.0)
+        }
+    }
+
+    /// Windows 11 修改系统边框的颜色，更早的系统用覆盖窗口画边框
+    fn draw_border(&self, hwnd: isize, style: &BorderStyle) -> anyhow::Result<()> {
+        if win_overlay::set_dwm_border_color(HWND(hwnd), Some(style.color)) {
+            return Ok(());
+        }
+        win_overlay::draw(hwnd, *style)
+    }
+
+    fn clear_border(&self, hwnd: isize) -> anyhow::Result<()> {
+        win_overlay::set_dwm_border_color(HWND(hwnd), None);
+        win_overlay::clear(hwnd)
+    }
+
+    fn capture_click(&self) -> anyhow::Result<Option<isize>> {
+        let point = std::thread::spawn(|| unsafe { pick_point() })
+            .join()
+            .map_err(|_| anyhow::anyhow!("拾取窗口失败"))??;
+        Ok(point
+            .map(|point| unsafe { WindowFromPoint(point) }.0)
+            .filter(|hwnd| *hwnd != 0))
+    }
+
+    fn watch(&self, sender: Sender<RawWindowEvent>) -> anyhow::Result<()> {
+        *WATCH_SENDER.lock().unwrap() = Some(sender);
+        //钩子需要在有消息循环的线程中安装
+        std::thread::spawn(|| unsafe {
+            let hooks = [
+                SetWinEventHook(
+                    EVENT_OBJECT_CREATE,
+                    EVENT_OBJECT_HIDE,
+                    None,
+                    Some(win_event_proc),
+                    0,
+                    0,
+                    WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+                ),
+                SetWinEventHook(
+                    EVENT_OBJECT_NAMECHANGE,
+                    EVENT_OBJECT_NAMECHANGE,
+                    None,
+                    Some(win_event_proc),
+                    0,
+                    0,
+                    WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+                ),
+            ];
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+            for hook in hooks {
+                let _ = UnhookWinEvent(hook);
+            }
+        });
+        Ok(())
     }
 }
 
@@ -570,13 +1008,106 @@ pub fn is_window_topmost(hwnd: HWND) -> bool {
     }
 }
 
-pub fn get_win32_hwnd_by_tauri_hwnd(app: &tauri::AppHandle, window_label: &str) -> Option<HWND> {
-    let hwnd_self: Option<HWND> = match app.get_webview_window(window_label) {
-        Some(window) => match window.hwnd().ok() {
-            Some(hwnd) => Some(HWND(hwnd.0 as isize)),
-            None => None,
-        },
-        None => None,
+/// 任务栏等系统窗口始终在最上层，不算作遮挡
+const SHELL_WINDOW_CLASSES: [&str; 3] = [
+    "Shell_TrayWnd",
+    "Shell_SecondaryTrayWnd",
+    "tooltips_class32",
+];
+
+/// 桌面窗口总是铺满显示器，不算作全屏程序
+const DESKTOP_WINDOW_CLASSES: [&str; 2] = ["Progman", "WorkerW"];
+
+/// 窗口所在显示器的完整区域（包含任务栏）
+unsafe extern "system" fn enum_monitor_proc(
+    monitor: HMONITOR,
+    _hdc: HDC,
+    _rect: *mut RECT,
+    lparam: LPARAM,
+) -> BOOL {
+    let monitors = &mut *(lparam.0 as *mut Vec<Rect>);
+    let mut info = MONITORINFO {
+        cbSize: size_of::<MONITORINFO>() as u32,
+        ..Default::default()
     };
-    hwnd_self
+    if GetMonitorInfoW(monitor, &mut info).as_bool() {
+        monitors.push(to_rect(&info.rcMonitor));
+    }
+    BOOL(1)
+}
+
+pub fn get_monitor_rect_for_window(hwnd: HWND) -> RECT {
+    unsafe {
+        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+
+        let mut info = MONITORINFO {
+            cbSize: size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+
+        let _ = GetMonitorInfoW(monitor, &mut info);
+        info.rcMonitor
+    }
+}
+
+fn to_rect(rect: &RECT) -> Rect {
+    Rect {
+        left: rect.left,
+        top: rect.top,
+        right: rect.right,
+        bottom: rect.bottom,
+    }
+}
+
+/// 判断窗口是否被 z 序在它之上的其它置顶窗口遮挡，`ignore` 中的窗口不算
+pub fn is_window_obscured(hwnd: HWND, ignore: &[isize]) -> bool {
+    unsafe {
+        let mut rect = RECT::default();
+        if GetWindowRect(hwnd, &mut rect).is_err() {
+            return false;
+        }
+        let self_pid = GetCurrentProcessId();
+        let mut above = GetWindow(hwnd, GW_HWNDPREV);
+        while above.0 != 0 {
+            if !ignore.contains(&above.0) && is_covering_window(above, &rect, self_pid) {
+                return true;
+            }
+            above = GetWindow(above, GW_HWNDPREV);
+        }
+        false
+    }
+}
+
+unsafe fn is_covering_window(hwnd: HWND, rect: &RECT, self_pid: u32) -> bool {
+    if !IsWindowVisible(hwnd).as_bool() || IsIconic(hwnd).as_bool() || !is_window_topmost(hwnd) {
+        return false;
+    }
+    //本程序自己的窗口不算
+    let mut pid = 0;
+    GetWindowThreadProcessId(hwnd, Some(&mut pid));
+    if pid == self_pid {
+        return false;
+    }
+    //被系统隐藏（cloaked）的 UWP 窗口虽然“可见”，但不会显示
+    let mut cloaked = 0u32;
+    let _ = DwmGetWindowAttribute(
+        hwnd,
+        DWMWA_CLOAKED,
+        &mut cloaked as *mut u32 as *mut _,
+        size_of::<u32>() as u32,
+    );
+    if cloaked != 0 {
+        return false;
+    }
+    if SHELL_WINDOW_CLASSES.contains(&get_window_class(hwnd).as_str()) {
+        return false;
+    }
+    let mut other = RECT::default();
+    if GetWindowRect(hwnd, &mut other).is_err() {
+        return false;
+    }
+    other.left < rect.right
+        && other.right > rect.left
+        && other.top < rect.bottom
+        && other.bottom > rect.top
 }
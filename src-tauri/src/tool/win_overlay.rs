@@ -0,0 +1,254 @@
+use crate::tool::error_tool;
+use crate::tool::window_backend::BorderStyle;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use windows::core::w;
+use windows::Win32::Foundation::{COLORREF, HINSTANCE, HWND, LPARAM, LRESULT, RECT, WPARAM};
+use windows::Win32::Graphics::Dwm::{
+    DwmGetWindowAttribute, DwmSetWindowAttribute, DWMWA_BORDER_COLOR, DWMWA_COLOR_DEFAULT,
+    DWMWA_EXTENDED_FRAME_BOUNDS,
+};
+use windows::Win32::Graphics::Gdi::{
+    BeginPaint, CombineRgn, CreateRectRgn, CreateSolidBrush, DeleteObject, EndPaint, FillRect,
+    InvalidateRect, SetWindowRgn, PAINTSTRUCT, RGN_DIFF,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Threading::GetCurrentThreadId;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetClientRect, GetMessageW,
+    GetWindowLongPtrW, GetWindowRect, IsIconic, IsWindowVisible, PeekMessageW, PostThreadMessageW,
+    RegisterClassW, SetLayeredWindowAttributes, SetWindowLongPtrW, SetWindowPos, ShowWindow,
+    TranslateMessage, GWLP_USERDATA, HWND_TOPMOST, LWA_ALPHA, MSG, PM_NOREMOVE, SWP_NOACTIVATE,
+    SWP_SHOWWINDOW, SW_HIDE, WM_APP, WM_PAINT, WNDCLASSW, WS_EX_LAYERED, WS_EX_NOACTIVATE,
+    WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP,
+};
+
+/// 投递给覆盖窗口线程的消息，lParam 为 `Box<OverlayCommand>` 的指针
+const WM_OVERLAY_COMMAND: u32 = WM_APP + 1;
+
+enum OverlayCommand {
+    Draw(isize, BorderStyle),
+    Clear(isize),
+}
+
+/// 覆盖窗口线程的线程 id，覆盖窗口都在该线程中创建，由它的消息循环处理绘制
+static OVERLAY_THREAD: Mutex<Option<u32>> = Mutex::new(None);
+
+/// Windows 11 可以直接修改系统边框的颜色，`color` 为空时还原为默认颜色；系统不支持时返回 false
+pub fn set_dwm_border_color(hwnd: HWND, color: Option<u32>) -> bool {
+    let value = color.map_or(DWMWA_COLOR_DEFAULT, |color| to_colorref(color).0);
+    unsafe {
+        DwmSetWindowAttribute(
+            hwnd,
+            DWMWA_BORDER_COLOR,
+            &value as *const u32 as *const _,
+            std::mem::size_of::<u32>() as u32,
+        )
+        .is_ok()
+    }
+}
+
+/// 在窗口周围显示覆盖窗口作为边框，已有时按窗口当前的位置和样式更新
+pub fn draw(hwnd: isize, style: BorderStyle) -> anyhow::Result<()> {
+    post(OverlayCommand::Draw(hwnd, style))
+}
+
+/// 销毁窗口的覆盖窗口
+pub fn clear(hwnd: isize) -> anyhow::Result<()> {
+    //还没有画过覆盖窗口时不需要启动线程
+    if OVERLAY_THREAD.lock().unwrap().is_none() {
+        return Ok(());
+    }
+    post(OverlayCommand::Clear(hwnd))
+}
+
+fn post(command: OverlayCommand) -> anyhow::Result<()> {
+    let thread = overlay_thread()?;
+    let command = Box::into_raw(Box::new(command));
+    let result = unsafe {
+        PostThreadMessageW(
+            thread,
+            WM_OVERLAY_COMMAND,
+            WPARAM(0),
+            LPARAM(command as isize),
+        )
+    };
+    if let Err(e) = result {
+        drop(unsafe { Box::from_raw(command) });
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// 覆盖窗口线程的 id，第一次调用时启动线程
+fn overlay_thread() -> anyhow::Result<u32> {
+    let mut thread = OVERLAY_THREAD.lock().unwrap();
+    if let Some(id) = *thread {
+        return Ok(id);
+    }
+    let (ready, receiver) = mpsc::channel();
+    std::thread::spawn(move || unsafe { overlay_loop(ready) });
+    let id = receiver
+        .recv()
+        .map_err(|_| anyhow::anyhow!("启动边框线程失败"))??;
+    *thread = Some(id);
+    Ok(id)
+}
+
+unsafe fn overlay_loop(ready: Sender<anyhow::Result<u32>>) {
+    //先创建消息队列，之后才能收到 PostThreadMessageW 投递的消息
+    let mut msg = MSG::default();
+    let _ = PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE);
+    if let Err(e) = register_overlay_class() {
+        let _ = ready.send(Err(e));
+        return;
+    }
+    let _ = ready.send(Ok(GetCurrentThreadId()));
+
+    let mut overlays: HashMap<isize, HWND> = HashMap::new();
+    while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+        if msg.hwnd.0 == 0 && msg.message == WM_OVERLAY_COMMAND {
+            let command = Box::from_raw(msg.lParam.0 as *mut OverlayCommand);
+            match *command {
+                OverlayCommand::Draw(hwnd, style) => {
+                    if let Err(e) = draw_overlay(&mut overlays, hwnd, &style) {
+                        error_tool::report_error(format!("绘制置顶边框失败：{}", e));
+                    }
+                }
+                OverlayCommand::Clear(hwnd) => {
+                    if let Some(overlay) = overlays.remove(&hwnd) {
+                        let _ = DestroyWindow(overlay);
+                    }
+                }
+            }
+            continue;
+        }
+        let _ = TranslateMessage(&msg);
+        DispatchMessageW(&msg);
+    }
+}
+
+unsafe fn register_overlay_class() -> anyhow::Result<()> {
+    let instance: HINSTANCE = GetModuleHandleW(None)?.into();
+    let class = WNDCLASSW {
+        lpfnWndProc: Some(overlay_wnd_proc),
+        hInstance: instance,
+        lpszClassName: w!("TopSetToolBorderOverlay"),
+        ..Default::default()
+    };
+    if RegisterClassW(&class) == 0 {
+        return Err(windows::core::Error::from_win32().into());
+    }
+    Ok(())
+}
+
+/// 覆盖窗口用 `GWLP_USERDATA` 中保存的颜色填满，窗口区域已裁剪为一圈边框
+unsafe extern "system" fn overlay_wnd_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg != WM_PAINT {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+    let mut paint = PAINTSTRUCT::default();
+    let hdc = BeginPaint(hwnd, &mut paint);
+    let mut rect = RECT::default();
+    if GetClientRect(hwnd, &mut rect).is_ok() {
+        let color = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as u32;
+        let brush = CreateSolidBrush(COLORREF(color));
+        FillRect(hdc, &rect, brush);
+        let _ = DeleteObject(brush);
+    }
+    let _ = EndPaint(hwnd, &paint);
+    LRESULT(0)
+}
+
+/// 分层且带 `WS_EX_TRANSPARENT` 的弹出窗口，鼠标点击会穿过边框
+unsafe fn create_overlay() -> anyhow::Result<HWND> {
+    let instance: HINSTANCE = GetModuleHandleW(None)?.into();
+    let overlay = CreateWindowExW(
+        WS_EX_LAYERED | WS_EX_TRANSPARENT | WS_EX_TOOLWINDOW | WS_EX_TOPMOST | WS_EX_NOACTIVATE,
+        w!("TopSetToolBorderOverlay"),
+        w!(""),
+        WS_POPUP,
+        0,
+        0,
+        0,
+        0,
+        None,
+        None,
+        instance,
+        None,
+    );
+    if overlay.0 == 0 {
+        return Err(windows::core::Error::from_win32().into());
+    }
+    SetLayeredWindowAttributes(overlay, COLORREF(0), 255, LWA_ALPHA)?;
+    Ok(overlay)
+}
+
+unsafe fn draw_overlay(
+    overlays: &mut HashMap<isize, HWND>,
+    hwnd: isize,
+    style: &BorderStyle,
+) -> anyhow::Result<()> {
+    let overlay = match overlays.get(&hwnd) {
+        Some(overlay) => *overlay,
+        None => {
+            let overlay = create_overlay()?;
+            overlays.insert(hwnd, overlay);
+            overlay
+        }
+    };
+    let target = HWND(hwnd);
+    if !IsWindowVisible(target).as_bool() || IsIconic(target).as_bool() {
+        let _ = ShowWindow(overlay, SW_HIDE);
+        return Ok(());
+    }
+    let bounds = frame_bounds(target)?;
+    let thickness = style.thickness as i32;
+    let width = bounds.right - bounds.left + 2 * thickness;
+    let height = bounds.bottom - bounds.top + 2 * thickness;
+
+    SetWindowLongPtrW(overlay, GWLP_USERDATA, to_colorref(style.color).0 as isize);
+    //窗口区域为外框减去内框，系统接管区域句柄，不需要释放
+    let region = CreateRectRgn(0, 0, width, height);
+    let inner = CreateRectRgn(thickness, thickness, width - thickness, height - thickness);
+    CombineRgn(region, region, inner, RGN_DIFF);
+    let _ = DeleteObject(inner);
+    SetWindowRgn(overlay, region, true);
+    SetWindowPos(
+        overlay,
+        HWND_TOPMOST,
+        bounds.left - thickness,
+        bounds.top - thickness,
+        width,
+        height,
+        SWP_NOACTIVATE | SWP_SHOWWINDOW,
+    )?;
+    let _ = InvalidateRect(overlay, None, true);
+    Ok(())
+}
+
+/// 窗口可见部分的区域，不包括 Windows 10 以后不可见的缩放边框
+unsafe fn frame_bounds(hwnd: HWND) -> anyhow::Result<RECT> {
+    let mut rect = RECT::default();
+    let result = DwmGetWindowAttribute(
+        hwnd,
+        DWMWA_EXTENDED_FRAME_BOUNDS,
+        &mut rect as *mut RECT as *mut _,
+        std::mem::size_of::<RECT>() as u32,
+    );
+    if result.is_err() {
+        GetWindowRect(hwnd, &mut rect)?;
+    }
+    Ok(rect)
+}
+
+/// 0xRRGGBB 转为 Windows 使用的 0x00BBGGRR
+fn to_colorref(color: u32) -> COLORREF {
+    COLORREF(((color & 0xff) << 16) | (color & 0xff00) | ((color >> 16) & 0xff))
+}
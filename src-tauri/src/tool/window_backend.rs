@@ -0,0 +1,467 @@
+use crate::tool::icon_cache::IconCache;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct IconImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>, // RGBA8888
+}
+
+impl IconImage {
+    pub fn into_png(self) -> anyhow::Result<Vec<u8>> {
+        let img =
+            image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(self.width, self.height, self.rgba)
+                .ok_or_else(|| anyhow::anyhow!("image error"))?;
+
+        let mut png = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+        Ok(png)
+    }
+
+    /// 缩放到 `size`×`size`，尺寸相同时原样返回
+    pub fn resized(self, size: u32) -> anyhow::Result<IconImage> {
+        if self.width == size && self.height == size {
+            return Ok(self);
+        }
+        let img =
+            image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(self.width, self.height, self.rgba)
+                .ok_or_else(|| anyhow::anyhow!("image error"))?;
+        let img = image::imageops::resize(&img, size, size, image::imageops::FilterType::Lanczos3);
+        Ok(IconImage {
+            width: size,
+            height: size,
+            rgba: img.into_raw(),
+        })
+    }
+
+    pub fn into_png_base64(self) -> anyhow::Result<String> {
+        Ok(base64::engine::general_purpose::STANDARD.encode(self.into_png()?))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct WindowInfo {
+    pub hwnd: isize,
+    /// 所属进程 id，未知时为 0
+    pub pid: u32,
+    pub title: String,
+    pub name: String,
+    pub display_name: String,
+    /// 窗口类名（Windows 窗口类、X11 WM_CLASS、Wayland app_id）
+    pub class: String,
+    /// 图标键，前端通过 `icon://<key>?size=32` 获取图标，为空表示没有图标
+    pub icon_key: String,
+    pub is_top_most: bool,
+    /// 定时置顶的到期时间，Unix 时间戳（毫秒），由置顶登记填充，后端始终为空
+    pub pin_expires_at: Option<u64>,
+    /// 置顶因全屏程序而暂停，由置顶登记填充
+    pub pin_suspended: bool,
+    /// 开启了鼠标穿透，由置顶登记填充
+    pub pin_click_through: bool,
+}
+
+/// 修改不透明度前窗口的原始样式，取消置顶或退出时据此还原
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct OpacityStyle {
+    pub opacity: f64,
+    /// Windows 下是否原本就是分层窗口，以及原始的颜色键和 `LWA_*` 标志
+    #[serde(default)]
+    pub layered: bool,
+    #[serde(default)]
+    pub color_key: u32,
+    #[serde(default)]
+    pub flags: u32,
+}
+
+/// 窗口后端支持的能力，部分平台（如部分 Wayland 合成器）无法修改置顶状态
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BackendCapabilities {
+    /// 后端名称，如 win32、x11、wayland-wlr、wayland-plasma
+    pub backend: String,
+    pub can_set_topmost: bool,
+    pub can_set_opacity: bool,
+    pub can_pick_window: bool,
+    pub can_click_through: bool,
+    pub can_draw_border: bool,
+    pub can_move_window: bool,
+}
+
+/// 置顶窗口高亮边框的样式
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorderStyle {
+    /// 颜色，0xRRGGBB
+    pub color: u32,
+    /// 粗细（像素）
+    pub thickness: u32,
+}
+
+/// 屏幕坐标系中的矩形区域，right、bottom 不包含在内
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    /// 是否完整覆盖 `other`
+    pub fn contains(&self, other: &Rect) -> bool {
+        self.left <= other.left
+            && self.top <= other.top
+            && self.right >= other.right
+            && self.bottom >= other.bottom
+    }
+}
+
+/// 平台钩子上报的原始窗口事件，只携带窗口标识，由 `WindowWatcher` 转换为完整的增量事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawWindowEvent {
+    Created(isize),
+    Destroyed(isize),
+    /// 标题、置顶状态等属性发生变化
+    Changed(isize),
+}
+
+/// 窗口操作后端，每个平台各自实现一份
+///
+/// 窗口统一使用 `isize` 作为标识（Windows 下即 HWND）
+pub trait WindowBackend: Send + Sync {
+    /// 枚举可见的顶层窗口，`exclude` 为需要排除的窗口（一般是本程序的主窗口）
+    fn enum_windows(&self, exclude: Option<isize>) -> Vec<WindowInfo>;
+
+    /// 查询单个窗口的信息，窗口不存在或不应出现在列表中时返回 None
+    fn window_info(&self, hwnd: isize) -> Option<WindowInfo>;
+
+    /// 判断窗口是否置顶
+    fn is_topmost(&self, hwnd: isize) -> bool;
+
+    /// 窗口虽然置顶，但被之后置顶的其它窗口遮挡，`ignore` 中的窗口（如本程序置顶的其它窗口）不算
+    ///
+    /// 无法获取 z 序的后端始终返回 false
+    fn is_obscured(&self, hwnd: isize, ignore: &[isize]) -> bool {
+        let _ = (hwnd, ignore);
+        false
+    }
+
+    /// 窗口是否仍然存在，隐藏的窗口也算存在
+    fn window_exists(&self, hwnd: isize) -> bool {
+        self.window_info(hwnd).is_some()
+    }
+
+    /// 设置或取消窗口置顶
+    fn set_topmost(&self, hwnd: isize, enable: bool) -> anyhow::Result<()>;
+
+    /// 窗口最小化时将其还原
+    fn restore(&self, hwnd: isize) -> anyhow::Result<()>;
+
+    /// 显示窗口并请求前台（不保证 100% 成功）
+    fn focus(&self, hwnd: isize) -> anyhow::Result<()>;
+
+    /// 后端支持的能力，供前端判断哪些操作可用
+    fn capabilities(&self) -> BackendCapabilities;
+
+    /// 提取程序图标，`path` 为登记到图标缓存中的程序路径，`size` 为请求的边长
+    fn load_icon(&self, path: &str, size: u32) -> anyhow::Result<IconImage> {
+        let _ = size;
+        Err(anyhow::anyhow!("无法获取图标：{}", path))
+    }
+
+    /// 窗口不透明度，范围 0.0~1.0，无法获取时返回 None
+    fn opacity(&self, hwnd: isize) -> Option<f64> {
+        let _ = hwnd;
+        None
+    }
+
+    /// 设置窗口不透明度，范围 0.0~1.0
+    fn set_opacity(&self, hwnd: isize, opacity: f64) -> anyhow::Result<()> {
+        let _ = (hwnd, opacity);
+        Err(anyhow::anyhow!("当前窗口后端不支持修改透明度"))
+    }
+
+    /// 修改不透明度前的原始样式，无法获取时返回 None
+    fn opacity_style(&self, hwnd: isize) -> Option<OpacityStyle> {
+        self.opacity(hwnd).map(|opacity| OpacityStyle {
+            opacity,
+            ..Default::default()
+        })
+    }
+
+    /// 还原 `opacity_style` 记录的原始样式
+    fn restore_opacity(&self, hwnd: isize, style: &OpacityStyle) -> anyhow::Result<()> {
+        self.set_opacity(hwnd, style.opacity)
+    }
+
+    /// 当前的前台窗口，桌面等系统窗口不算；无法获取时返回 None
+    fn foreground_window(&self) -> Option<isize> {
+        None
+    }
+
+    /// 窗口在屏幕上的区域，窗口最小化或隐藏时返回 None
+    fn window_rect(&self, hwnd: isize) -> Option<Rect> {
+        let _ = hwnd;
+        None
+    }
+
+    /// 窗口所在显示器的完整区域（包含任务栏）
+    fn monitor_rect(&self, hwnd: isize) -> Option<Rect> {
+        let _ = hwnd;
+        None
+    }
+
+    /// 所有显示器的完整区域，无法获取时为空
+    fn monitors(&self) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    /// 移动窗口并调整大小，`rect` 与 `window_rect` 的含义相同；最小化或最大化的窗口先还原
+    fn set_window_rect(&self, hwnd: isize, rect: &Rect) -> anyhow::Result<()> {
+        let _ = (hwnd, rect);
+        Err(anyhow::anyhow!("当前窗口后端不支持移动窗口"))
+    }
+
+    /// 窗口的父窗口，没有父窗口时为所有者窗口（对话框、工具面板等），都没有时返回 None
+    fn parent_window(&self, hwnd: isize) -> Option<isize> {
+        let _ = hwnd;
+        None
+    }
+
+    /// 开启或关闭鼠标穿透，开启后鼠标点击会落到下方的窗口上
+    fn set_click_through(&self, hwnd: isize, enable: bool) -> anyhow::Result<()> {
+        let _ = (hwnd, enable);
+        Err(anyhow::anyhow!("当前窗口后端不支持鼠标穿透"))
+    }
+
+    /// 在窗口周围绘制高亮边框，已有边框时按窗口当前的位置和样式重绘，窗口最小化或隐藏时隐藏边框
+    fn draw_border(&self, hwnd: isize, style: &BorderStyle) -> anyhow::Result<()> {
+        let _ = (hwnd, style);
+        Err(anyhow::anyhow!("当前窗口后端不支持绘制边框"))
+    }
+
+    /// 去掉窗口的高亮边框
+    fn clear_border(&self, hwnd: isize) -> anyhow::Result<()> {
+        let _ = hwnd;
+        Ok(())
+    }
+
+    /// 显示十字光标并等待用户点击，返回点击位置的窗口（可能是子窗口），按 Esc 取消时返回 None
+    fn capture_click(&self) -> anyhow::Result<Option<isize>> {
+        Err(anyhow::anyhow!("当前窗口后端不支持拾取窗口"))
+    }
+
+    /// 开始监听窗口的创建、销毁和属性变化，事件通过 `sender` 上报
+    ///
+    /// 不支持监听的后端返回错误，由调用方退回定时轮询
+    fn watch(&self, sender: Sender<RawWindowEvent>) -> anyhow::Result<()> {
+        let _ = sender;
+        Err(anyhow::anyhow!("当前窗口后端不支持监听窗口变化"))
+    }
+}
+
+/// 放入 tauri 托管状态中的窗口后端
+pub struct WindowBackendState(pub Arc<dyn WindowBackend>);
+
+/// 根据当前平台创建窗口后端
+pub fn create_backend(icon_cache: Arc<IconCache>) -> anyhow::Result<Arc<dyn WindowBackend>> {
+    #[cfg(target_os = "windows")]
+    {
+        Ok(Arc::new(super::win_app_tool::Win32Backend::new(icon_cache)))
+    }
+    #[cfg(target_os = "linux")]
+    {
+        //Wayland 会话优先使用 Wayland 协议，失败时退回 X11（XWayland）
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            match super::wayland_backend::WaylandBackend::connect() {
+                Ok(backend) => return Ok(Arc::new(backend)),
+                Err(e) => {
+                    super::error_tool::report_error(format!("连接Wayland失败，尝试X11：{}", e))
+                }
+            }
+        }
+        Ok(Arc::new(super::x11_backend::X11Backend::connect(
+            None, icon_cache,
+        )?))
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        let _ = icon_cache;
+        Err(anyhow::anyhow!("当前平台暂不支持窗口置顶"))
+    }
+}
+
+/// 调整不透明度时的步长
+pub const OPACITY_STEP: f64 = 0.1;
+/// 最低不透明度，避免窗口完全看不见后找不回来
+pub const MIN_OPACITY: f64 = 0.2;
+
+/// 把不透明度限制在 `MIN_OPACITY`~1.0，并取整到百分位，避免多次调整后浮点误差累积
+pub fn clamp_opacity(opacity: f64) -> f64 {
+    ((opacity * 100.0).round() / 100.0).clamp(MIN_OPACITY, 1.0)
+}
+
+/// 窗口是否覆盖了所在显示器的整个区域（全屏游戏、幻灯片放映等）
+pub fn is_fullscreen(backend: &dyn WindowBackend, hwnd: isize) -> bool {
+    match (backend.window_rect(hwnd), backend.monitor_rect(hwnd)) {
+        (Some(window), Some(monitor)) => window.contains(&monitor),
+        _ => false,
+    }
+}
+
+/// 查找父窗口时的最大层数，防止异常的父子关系形成环
+pub const MAX_WINDOW_DEPTH: usize = 32;
+
+/// 沿父窗口和所有者窗口向上查找，返回最顶层的窗口（相当于 Windows 的 `GA_ROOTOWNER`）
+pub fn resolve_root_window(backend: &dyn WindowBackend, hwnd: isize) -> isize {
+    let mut current = hwnd;
+    for _ in 0..MAX_WINDOW_DEPTH {
+        match backend.parent_window(current) {
+            Some(parent) if parent != current => current = parent,
+            _ => break,
+        }
+    }
+    current
+}
+
+/// 正在拾取窗口，同一时间只允许一次拾取
+static PICKING: AtomicBool = AtomicBool::new(false);
+
+/// 等待用户点击选择窗口，返回点击位置窗口的顶层窗口，按 Esc 取消时返回 None
+///
+/// `exclude` 为不允许选择的窗口（一般是本程序的主窗口）
+pub fn pick_window(
+    backend: &dyn WindowBackend,
+    exclude: Option<isize>,
+) -> anyhow::Result<Option<WindowInfo>> {
+    if PICKING.swap(true, Ordering::SeqCst) {
+        return Err(anyhow::anyhow!("正在拾取窗口"));
+    }
+    let result = backend.capture_click();
+    PICKING.store(false, Ordering::SeqCst);
+    let Some(hit) = result? else {
+        return Ok(None);
+    };
+    let root = resolve_root_window(backend, hit);
+    if Some(root) == exclude {
+        return Err(anyhow::anyhow!("不能选择本程序的窗口"));
+    }
+    backend
+        .window_info(root)
+        .map(Some)
+        .ok_or_else(|| anyhow::anyhow!("无法操作该窗口"))
+}
+
+pub fn list_windows(backend: &dyn WindowBackend, hwnd_self: Option<isize>) -> Vec<WindowInfo> {
+    backend.enum_windows(hwnd_self)
+}
+
+pub fn toggle_topmost(
+    backend: &dyn WindowBackend,
+    hwnd: isize,
+    enable: bool,
+    hwnd_self: Option<isize>,
+) -> anyhow::Result<()> {
+    if enable {
+        show_and_topmost(backend, hwnd, hwnd_self)
+    } else {
+        show_and_not_topmost(backend, hwnd)
+    }
+}
+
+pub fn show_and_topmost(
+    backend: &dyn WindowBackend,
+    hwnd: isize,
+    hwnd_self: Option<isize>,
+) -> anyhow::Result<()> {
+    //如果最小化，先还原
+    backend.restore(hwnd)?;
+
+    //设置为置顶窗口
+    backend.set_topmost(hwnd, true)?;
+
+    //请求前台 失败不影响置顶结果
+    let _ = backend.focus(hwnd);
+
+    //本程序窗口再次置顶 保证不被刚置顶的窗口盖住
+    if let Some(hwnd_self) = hwnd_self {
+        let _ = backend.set_topmost(hwnd_self, true);
+    }
+    Ok(())
+}
+
+pub fn show_and_not_topmost(backend: &dyn WindowBackend, hwnd: isize) -> anyhow::Result<()> {
+    backend.set_topmost(hwnd, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::fake_backend::FakeBackend;
+
+    fn window(hwnd: isize) -> WindowInfo {
+        WindowInfo {
+            hwnd,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn list_windows_excludes_self() {
+        let backend = FakeBackend::new(vec![window(1), window(2), window(3)]);
+        let hwnds: Vec<isize> = list_windows(&backend, Some(2))
+            .iter()
+            .map(|w| w.hwnd)
+            .collect();
+        assert_eq!(hwnds, vec![1, 3]);
+        assert_eq!(list_windows(&backend, None).len(), 3);
+    }
+
+    #[test]
+    fn toggle_topmost_restores_and_keeps_self_on_top() {
+        let backend = FakeBackend::new(vec![window(1), window(9)]);
+        backend.set_minimized(1, true).unwrap();
+        toggle_topmost(&backend, 1, true, Some(9)).unwrap();
+        assert!(backend.is_topmost(1));
+        assert!(!backend.is_minimized(1));
+        assert_eq!(backend.foreground(), Some(1));
+        assert!(backend.is_topmost(9));
+
+        toggle_topmost(&backend, 1, false, Some(9)).unwrap();
+        assert!(!backend.is_topmost(1));
+        assert!(backend.is_topmost(9));
+    }
+
+    #[test]
+    fn toggle_topmost_missing_window() {
+        let backend = FakeBackend::new(vec![window(1)]);
+        assert!(toggle_topmost(&backend, 2, true, None).is_err());
+        assert!(toggle_topmost(&backend, 2, false, None).is_err());
+    }
+
+    #[test]
+    fn resolve_root_window_follows_parents() {
+        let backend = FakeBackend::new(vec![window(1), window(7), window(8)]);
+        //对话框 5 属于 4，4 属于主窗口 1
+        backend.set_parent(5, 4);
+        backend.set_parent(4, 1);
+        assert_eq!(resolve_root_window(&backend, 5), 1);
+        assert_eq!(resolve_root_window(&backend, 1), 1);
+        //异常的父子关系形成环时也能结束
+        backend.set_parent(7, 8);
+        backend.set_parent(8, 7);
+        assert!([7, 8].contains(&resolve_root_window(&backend, 7)));
+
+        backend.set_click_target(Some(5));
+        assert_eq!(
+            pick_window(&backend, None).unwrap().map(|w| w.hwnd),
+            Some(1)
+        );
+        assert!(pick_window(&backend, Some(1)).is_err());
+        backend.set_click_target(None);
+        assert!(pick_window(&backend, None).unwrap().is_none());
+    }
+}
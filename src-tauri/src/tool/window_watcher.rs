@@ -0,0 +1,265 @@
+use crate::tool::window_backend::{RawWindowEvent, WindowBackend, WindowInfo};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 后端不支持监听时的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 推送给前端的窗口增量事件
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "event", content = "data")]
+pub enum WindowChangeEvent {
+    Added {
+        window: WindowInfo,
+    },
+    /// 窗口关闭或隐藏，不再出现在列表中
+    Removed {
+        hwnd: isize,
+    },
+    Changed {
+        window: WindowInfo,
+    },
+}
+
+/// 事件订阅者，返回 false 表示订阅已失效（如前端页面已刷新），将被移除
+type Listener = Box<dyn Fn(&WindowChangeEvent) -> bool + Send + Sync>;
+
+/// 窗口监听子系统
+///
+/// 维护一份已知窗口的快照，把平台钩子上报的原始事件转换为增量事件分发给订阅者
+pub struct WindowWatcher {
+    backend: Arc<dyn WindowBackend>,
+    /// 不监视的窗口（本程序的主窗口），无界面启动时主窗口之后才创建
+    exclude: Mutex<Option<isize>>,
+    snapshot: Mutex<HashMap<isize, WindowInfo>>,
+    /// 订阅者及其名称，同名的订阅只保留最新的一个
+    listeners: Mutex<Vec<(Option<String>, Listener)>>,
+}
+
+impl WindowWatcher {
+    pub fn new(backend: Arc<dyn WindowBackend>, exclude: Option<isize>) -> Self {
+        let snapshot = backend
+            .enum_windows(exclude)
+            .into_iter()
+            .map(|w| (w.hwnd, w))
+            .collect();
+        WindowWatcher {
+            backend,
+            exclude: Mutex::new(exclude),
+            snapshot: Mutex::new(snapshot),
+            listeners: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 创建并在后台线程中启动监听，后端不支持监听时退回定时轮询
+    pub fn start(backend: Arc<dyn WindowBackend>, exclude: Option<isize>) -> Arc<Self> {
+        let watcher = Arc::new(WindowWatcher::new(backend, exclude));
+        let (tx, rx) = mpsc::channel();
+        let watch_result = watcher.backend.watch(tx);
+
+        let worker = watcher.clone();
+        std::thread::spawn(move || match watch_result {
+            Ok(()) => {
+                while let Ok(event) = rx.recv() {
+                    worker.handle_raw_event(event);
+                }
+            }
+            Err(_) => loop {
+                std::thread::sleep(POLL_INTERVAL);
+                worker.resync();
+            },
+        });
+        watcher
+    }
+
+    pub fn subscribe<F>(&self, listener: F)
+    where
+        F: Fn(&WindowChangeEvent) -> bool + Send + Sync + 'static,
+    {
+        self.listeners
+            .lock()
+            .unwrap()
+            .push((None, Box::new(listener)));
+    }
+
+    /// 以 `name` 订阅，替换之前同名的订阅，用于页面刷新后重新订阅的前端
+    pub fn subscribe_as<F>(&self, name: &str, listener: F)
+    where
+        F: Fn(&WindowChangeEvent) -> bool + Send + Sync + 'static,
+    {
+        let mut listeners = self.listeners.lock().unwrap();
+        listeners.retain(|(n, _)| n.as_deref() != Some(name));
+        listeners.push((Some(name.to_string()), Box::new(listener)));
+    }
+
+    /// 修改不监视的窗口，已在快照中的该窗口按移除处理
+    pub fn set_exclude(&self, exclude: Option<isize>) {
+        *self.exclude.lock().unwrap() = exclude;
+        if let Some(change) = exclude.and_then(|hwnd| self.apply(hwnd, None)) {
+            self.dispatch(&change);
+        }
+    }
+
+    fn exclude(&self) -> Option<isize> {
+        *self.exclude.lock().unwrap()
+    }
+
+    /// 当前快照中的窗口
+    pub fn windows(&self) -> Vec<WindowInfo> {
+        let snapshot = self.snapshot.lock().unwrap();
+        let mut list: Vec<WindowInfo> = snapshot.values().cloned().collect();
+        list.sort_by_key(|w| w.hwnd);
+        list
+    }
+
+    /// 处理一条原始事件，返回由此产生的增量事件
+    pub fn handle_raw_event(&self, event: RawWindowEvent) -> Option<WindowChangeEvent> {
+        let hwnd = match event {
+            RawWindowEvent::Created(hwnd)
+            | RawWindowEvent::Destroyed(hwnd)
+            | RawWindowEvent::Changed(hwnd) => hwnd,
+        };
+        if Some(hwnd) == self.exclude() {
+            return None;
+        }
+        let info = match event {
+            RawWindowEvent::Destroyed(_) => None,
+            _ => self.backend.window_info(hwnd),
+        };
+        let change = self.apply(hwnd, info)?;
+        self.dispatch(&change);
+        Some(change)
+    }
+
+    /// 重新枚举所有窗口并与快照比较，返回产生的增量事件
+    pub fn resync(&self) -> Vec<WindowChangeEvent> {
+        let current: HashMap<isize, WindowInfo> = self
+            .backend
+            .enum_windows(self.exclude())
+            .into_iter()
+            .map(|w| (w.hwnd, w))
+            .collect();
+        let removed: Vec<isize> = {
+            let snapshot = self.snapshot.lock().unwrap();
+            snapshot
+                .keys()
+                .filter(|hwnd| !current.contains_key(hwnd))
+                .copied()
+                .collect()
+        };
+
+        let mut changes = Vec::new();
+        for hwnd in removed {
+            changes.extend(self.apply(hwnd, None));
+        }
+        let mut current: Vec<WindowInfo> = current.into_values().collect();
+        current.sort_by_key(|w| w.hwnd);
+        for info in current {
+            changes.extend(self.apply(info.hwnd, Some(info)));
+        }
+        for change in &changes {
+            self.dispatch(change);
+        }
+        changes
+    }
+
+    /// 用最新的窗口信息更新快照
+    fn apply(&self, hwnd: isize, info: Option<WindowInfo>) -> Option<WindowChangeEvent> {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        match info {
+            None => snapshot
+                .remove(&hwnd)
+                .map(|_| WindowChangeEvent::Removed { hwnd }),
+            Some(info) => match snapshot.insert(hwnd, info.clone()) {
+                None => Some(WindowChangeEvent::Added { window: info }),
+                Some(old) if old != info => Some(WindowChangeEvent::Changed { window: info }),
+                Some(_) => None,
+            },
+        }
+    }
+
+    fn dispatch(&self, change: &WindowChangeEvent) {
+        let mut listeners = self.listeners.lock().unwrap();
+        listeners.retain(|(_, listener)| listener(change));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::fake_backend::FakeBackend;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn subscribe_as_replaces_previous_listener() {
+        let backend = Arc::new(FakeBackend::new(Vec::new()));
+        let watcher = WindowWatcher::new(backend.clone(), None);
+        let (old, new) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let counter = old.clone();
+        watcher.subscribe_as("main", move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            true
+        });
+        let counter = new.clone();
+        watcher.subscribe_as("main", move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            true
+        });
+        backend.add_window(WindowInfo {
+            hwnd: 1,
+            ..Default::default()
+        });
+        watcher.handle_raw_event(RawWindowEvent::Created(1));
+        assert_eq!(old.load(Ordering::SeqCst), 0);
+        assert_eq!(new.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn hidden_window_leaves_list() {
+        let backend = Arc::new(FakeBackend::new(vec![WindowInfo {
+            hwnd: 1,
+            ..Default::default()
+        }]));
+        let watcher = WindowWatcher::new(backend.clone(), None);
+        assert_eq!(watcher.handle_raw_event(RawWindowEvent::Changed(1)), None);
+        backend.remove_window(1);
+        assert_eq!(
+            watcher.handle_raw_event(RawWindowEvent::Changed(1)),
+            Some(WindowChangeEvent::Removed { hwnd: 1 })
+        );
+        //销毁事件可能来自从未列出的窗口，忽略
+        assert_eq!(watcher.handle_raw_event(RawWindowEvent::Destroyed(7)), None);
+    }
+
+    #[test]
+    fn set_exclude_removes_window() {
+        let backend = Arc::new(FakeBackend::new(vec![
+            WindowInfo {
+                hwnd: 1,
+                ..Default::default()
+            },
+            WindowInfo {
+                hwnd: 2,
+                ..Default::default()
+            },
+        ]));
+        let watcher = WindowWatcher::new(backend.clone(), None);
+        assert_eq!(watcher.windows().len(), 2);
+        let removed = Arc::new(AtomicUsize::new(0));
+        let counter = removed.clone();
+        watcher.subscribe(move |event| {
+            if matches!(event, WindowChangeEvent::Removed { hwnd: 2 }) {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+            true
+        });
+        watcher.set_exclude(Some(2));
+        assert_eq!(removed.load(Ordering::SeqCst), 1);
+        assert!(watcher.resync().is_empty());
+        assert_eq!(watcher.handle_raw_event(RawWindowEvent::Changed(2)), None);
+        assert_eq!(watcher.windows().len(), 1);
+    }
+}
@@ -0,0 +1,912 @@
+use crate::tool::error_tool;
+use crate::tool::icon_cache::IconCache;
+use crate::tool::window_backend::{
+    BackendCapabilities, BorderStyle, IconImage, RawWindowEvent, Rect, WindowBackend, WindowInfo,
+    MAX_WINDOW_DEPTH,
+};
+use anyhow::Context;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::shape::{ConnectionExt as _, SK, SO};
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ClipOrdering, Colormap,
+    ConfigureWindowAux, ConnectionExt as _, CreateWindowAux, EventMask, GetPropertyReply, GrabMode,
+    GrabStatus, MapState, PropMode, Rectangle, StackMode, Window, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::CURRENT_TIME;
+
+x11rb::atom_manager! {
+    pub Atoms: AtomsCookie {
+        _NET_CLIENT_LIST,
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_ICON,
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_MAXIMIZED_VERT,
+        _NET_WM_STATE_MAXIMIZED_HORZ,
+        _NET_WM_STATE_SKIP_TASKBAR,
+        _NET_WM_WINDOW_OPACITY,
+        _NET_MOVERESIZE_WINDOW,
+        UTF8_STRING,
+    }
+}
+
+// _NET_WM_STATE 客户端消息中的动作
+const NET_WM_STATE_REMOVE: u32 = 0;
+const NET_WM_STATE_ADD: u32 = 1;
+// 消息来源：普通应用程序
+const SOURCE_APPLICATION: u32 = 1;
+// 消息来源：桌面工具（pager），窗口管理器会按原样移动窗口
+const SOURCE_PAGER: u32 = 2;
+// _NET_MOVERESIZE_WINDOW 的 StaticGravity，坐标为客户端窗口自身（不含窗口管理器边框）的位置
+const STATIC_GRAVITY: u32 = 10;
+// _NET_MOVERESIZE_WINDOW 中 x、y、width、height 均有效
+const MOVERESIZE_ALL: u32 = 0xf << 8;
+
+// cursor 字体中的十字光标
+const XC_CROSSHAIR: u16 = 34;
+const XK_ESCAPE: u32 = 0xff1b;
+const LEFT_BUTTON: u8 = 1;
+
+/// 保留的原始图标尺寸上限，超过该尺寸的图标只在没有更小的图标时使用
+const ICON_SIZE: u32 = 256;
+
+/// 基于 EWMH 的 X11 窗口后端
+///
+/// 窗口标识即 X11 的 window id，依赖窗口管理器维护 `_NET_CLIENT_LIST`
+pub struct X11Backend {
+    conn: RustConnection,
+    root: Window,
+    colormap: Colormap,
+    atoms: Atoms,
+    display: Option<String>,
+    icon_cache: Arc<IconCache>,
+    /// 程序路径 → 窗口提供的原始图标，请求图标时按尺寸缩放
+    icons: Mutex<HashMap<String, IconImage>>,
+    /// 客户端窗口 → 绘制高亮边框的覆盖窗口
+    borders: Mutex<HashMap<Window, Window>>,
+}
+
+impl X11Backend {
+    /// 连接 X server，`display` 为空时使用环境变量 `DISPLAY`
+    pub fn connect(display: Option<&str>, icon_cache: Arc<IconCache>) -> anyhow::Result<Self> {
+        let (conn, screen_num) = x11rb::connect(display).context("连接X11失败")?;
+        let screen = &conn.setup().roots[screen_num];
+        let (root, colormap) = (screen.root, screen.default_colormap);
+        let atoms = Atoms::new(&conn)?.reply()?;
+        Ok(X11Backend {
+            conn,
+            root,
+            colormap,
+            atoms,
+            display: display.map(str::to_string),
+            icon_cache,
+            icons: Mutex::new(HashMap::new()),
+            borders: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn get_property(&self, window: Window, property: u32, ty: u32) -> Option<GetPropertyReply> {
+        self.conn
+            .get_property(false, window, property, ty, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()
+    }
+
+    fn get_cardinals(&self, window: Window, property: u32, ty: AtomEnum) -> Vec<u32> {
+        self.get_property(window, property, ty.into())
+            .and_then(|reply| reply.value32().map(|v| v.collect()))
+            .unwrap_or_default()
+    }
+
+    fn client_list(&self) -> Vec<Window> {
+        self.get_cardinals(self.root, self.atoms._NET_CLIENT_LIST, AtomEnum::WINDOW)
+    }
+
+    fn wm_states(&self, window: Window) -> Vec<u32> {
+        self.get_cardinals(window, self.atoms._NET_WM_STATE, AtomEnum::ATOM)
+    }
+
+    fn window_title(&self, window: Window) -> Option<String> {
+        let title = self
+            .get_property(window, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING)
+            .filter(|reply| !reply.value.is_empty())
+            .or_else(|| {
+                self.get_property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())
+            })
+            .map(|reply| String::from_utf8_lossy(&reply.value).to_string())?;
+        if title.is_empty() {
+            None
+        } else {
+            Some(title)
+        }
+    }
+
+    fn window_pid(&self, window: Window) -> Option<u32> {
+        self.get_cardinals(window, self.atoms._NET_WM_PID, AtomEnum::CARDINAL)
+            .first()
+            .copied()
+    }
+
+    /// WM_CLASS 由 "instance\0class\0" 组成，取 class 作为显示名称
+    fn window_class(&self, window: Window) -> Option<String> {
+        let reply =
+            self.get_property(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?;
+        reply
+            .value
+            .split(|b| *b == 0)
+            .filter(|part| !part.is_empty())
+            .nth(1)
+            .map(|class| String::from_utf8_lossy(class).to_string())
+    }
+
+    /// 读取 `_NET_WM_ICON`，格式为若干组 [宽, 高, 宽*高 个 ARGB 像素]
+    fn window_icon(&self, window: Window) -> Option<IconImage> {
+        let data = self.get_cardinals(window, self.atoms._NET_WM_ICON, AtomEnum::CARDINAL);
+        let mut best: Option<(u32, u32, &[u32])> = None;
+        let mut rest = data.as_slice();
+        while rest.len() > 2 {
+            let (width, height) = (rest[0], rest[1]);
+            let len = (width as usize).checked_mul(height as usize)?;
+            if width == 0 || height == 0 || rest.len() < 2 + len {
+                break;
+            }
+            let pixels = &rest[2..2 + len];
+            rest = &rest[2 + len..];
+            //优先选择不超过 ICON_SIZE 的最大图标
+            let better = match best {
+                None => true,
+                Some((w, _, _)) if w > ICON_SIZE => width < w,
+                Some((w, _, _)) => width > w && width <= ICON_SIZE,
+            };
+            if better {
+                best = Some((width, height, pixels));
+            }
+        }
+
+        let (width, height, pixels) = best?;
+        // ARGB → RGBA
+        let rgba = pixels
+            .iter()
+            .flat_map(|px| {
+                let [b, g, r, a] = px.to_le_bytes();
+                [r, g, b, a]
+            })
+            .collect();
+        Some(IconImage {
+            width,
+            height,
+            rgba,
+        })
+    }
+
+    fn read_window_info(&self, window: Window) -> Option<WindowInfo> {
+        let states = self.wm_states(window);
+        //排除不在任务栏显示的工具窗口
+        if states.contains(&self.atoms._NET_WM_STATE_SKIP_TASKBAR) {
+            return None;
+        }
+        let title = self.window_title(window)?;
+        let pid = self.window_pid(window);
+        //排除本程序自身的窗口
+        if pid == Some(std::process::id()) {
+            return None;
+        }
+
+        let exe_path = pid.and_then(|pid| std::fs::read_link(format!("/proc/{}/exe", pid)).ok());
+        let name = exe_path
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let class = self.window_class(window).unwrap_or_default();
+        let display_name = if class.is_empty() {
+            name.clone()
+        } else {
+            class.clone()
+        };
+        //同一程序的多个窗口共用一份图标，只在第一次遇到该程序时读取
+        let icon_key = match exe_path.as_deref().and_then(Path::to_str) {
+            Some(path) => {
+                let mut icons = self.icons.lock().unwrap();
+                if !icons.contains_key(path) {
+                    if let Some(icon) = self.window_icon(window) {
+                        icons.insert(path.to_string(), icon);
+                    }
+                }
+                if icons.contains_key(path) {
+                    self.icon_cache.register(path)
+                } else {
+                    String::new()
+                }
+            }
+            None => String::new(),
+        };
+
+        Some(WindowInfo {
+            hwnd: window as isize,
+            pid: pid.unwrap_or(0),
+            title,
+            name,
+            display_name,
+            class,
+            icon_key,
+            is_top_most: states.contains(&self.atoms._NET_WM_STATE_ABOVE),
+            pin_expires_at: None,
+            pin_suspended: false,
+            pin_click_through: false,
+        })
+    }
+
+    /// 监听根窗口的 `_NET_CLIENT_LIST` 及各窗口标题、状态的 PropertyNotify 事件
+    fn watch_loop(&self, sender: Sender<RawWindowEvent>) -> anyhow::Result<()> {
+        let property_change =
+            ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        self.conn
+            .change_window_attributes(self.root, &property_change)?;
+        let mut known: HashSet<Window> = HashSet::new();
+        for window in self.client_list() {
+            self.conn
+                .change_window_attributes(window, &property_change)?;
+            known.insert(window);
+        }
+        self.conn.flush()?;
+
+        let watched_atoms = [
+            self.atoms._NET_WM_NAME,
+            AtomEnum::WM_NAME.into(),
+            self.atoms._NET_WM_STATE,
+        ];
+        loop {
+            let Event::PropertyNotify(event) = self.conn.wait_for_event()? else {
+                continue;
+            };
+            let mut events = Vec::new();
+            if event.window == self.root {
+                if event.atom != self.atoms._NET_CLIENT_LIST {
+                    continue;
+                }
+                let current: HashSet<Window> = self.client_list().into_iter().collect();
+                for window in current.difference(&known) {
+                    //窗口可能已经销毁，忽略错误
+                    let _ = self
+                        .conn
+                        .change_window_attributes(*window, &property_change);
+                    events.push(RawWindowEvent::Created(*window as isize));
+                }
+                for window in known.difference(&current) {
+                    events.push(RawWindowEvent::Destroyed(*window as isize));
+                }
+                self.conn.flush()?;
+                known = current;
+            } else if watched_atoms.contains(&event.atom) {
+                events.push(RawWindowEvent::Changed(event.window as isize));
+            }
+            for raw in events {
+                if sender.send(raw).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// 抓取鼠标并显示十字光标，等待左键点击，返回点击位置的根窗口子窗口（一般是窗口管理器的边框）
+    fn pick_loop(&self) -> anyhow::Result<Option<Window>> {
+        let font = self.conn.generate_id()?;
+        self.conn.open_font(font, b"cursor")?;
+        let cursor = self.conn.generate_id()?;
+        self.conn.create_glyph_cursor(
+            cursor,
+            font,
+            font,
+            XC_CROSSHAIR,
+            XC_CROSSHAIR + 1,
+            0,
+            0,
+            0,
+            u16::MAX,
+            u16::MAX,
+            u16::MAX,
+        )?;
+        let grab = self
+            .conn
+            .grab_pointer(
+                false,
+                self.root,
+                EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                x11rb::NONE,
+                cursor,
+                CURRENT_TIME,
+            )?
+            .reply()?;
+        let result = if grab.status == GrabStatus::SUCCESS {
+            self.wait_for_click()
+        } else {
+            Err(anyhow::anyhow!("无法抓取鼠标，可能有其它程序正在使用"))
+        };
+        let _ = self.conn.ungrab_keyboard(CURRENT_TIME);
+        let _ = self.conn.ungrab_pointer(CURRENT_TIME);
+        let _ = self.conn.free_cursor(cursor);
+        let _ = self.conn.close_font(font);
+        self.conn.flush()?;
+        result
+    }
+
+    fn wait_for_click(&self) -> anyhow::Result<Option<Window>> {
+        //抓取键盘失败时只是无法用 Esc 取消
+        let _ = self
+            .conn
+            .grab_keyboard(
+                false,
+                self.root,
+                CURRENT_TIME,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )?
+            .reply();
+        let escape = self.escape_keycode();
+        let mut pressed = None;
+        loop {
+            match self.conn.wait_for_event()? {
+                Event::ButtonPress(event) if event.detail == LEFT_BUTTON => {
+                    pressed = Some(event.child);
+                }
+                Event::ButtonRelease(event) if event.detail == LEFT_BUTTON => {
+                    if let Some(window) = pressed {
+                        return Ok(Some(window).filter(|window| *window != x11rb::NONE));
+                    }
+                }
+                Event::KeyPress(event) if Some(event.detail) == escape => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    fn escape_keycode(&self) -> Option<u8> {
+        let setup = self.conn.setup();
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let mapping = self
+            .conn
+            .get_keyboard_mapping(min, max - min + 1)
+            .ok()?
+            .reply()
+            .ok()?;
+        let per_keycode = (mapping.keysyms_per_keycode as usize).max(1);
+        mapping
+            .keysyms
+            .chunks(per_keycode)
+            .position(|keysyms| keysyms.contains(&XK_ESCAPE))
+            .map(|index| min + index as u8)
+    }
+
+    /// 窗口管理器为客户端窗口创建的边框，即根窗口下包含该窗口的子窗口；没有边框时返回窗口本身
+    fn frame_window(&self, window: Window) -> Window {
+        let mut current = window;
+        for _ in 0..MAX_WINDOW_DEPTH {
+            match self
+                .conn
+                .query_tree(current)
+                .ok()
+                .and_then(|c| c.reply().ok())
+            {
+                Some(tree) if tree.parent != self.root && tree.parent != x11rb::NONE => {
+                    current = tree.parent;
+                }
+                _ => break,
+            }
+        }
+        current
+    }
+
+    /// 0xRRGGBB 颜色在默认颜色表中的像素值
+    fn alloc_pixel(&self, color: u32) -> anyhow::Result<u32> {
+        let channel = |shift: u32| ((color >> shift) & 0xff) as u16 * 257;
+        let reply = self
+            .conn
+            .alloc_color(self.colormap, channel(16), channel(8), channel(0))?
+            .reply()?;
+        Ok(reply.pixel)
+    }
+
+    /// 在窗口管理器的边框下查找 `_NET_CLIENT_LIST` 中的客户端窗口
+    fn find_client(&self, frame: Window) -> Option<Window> {
+        let clients: HashSet<Window> = self.client_list().into_iter().collect();
+        let mut queue = VecDeque::from([frame]);
+        while let Some(window) = queue.pop_front() {
+            if clients.contains(&window) {
+                return Some(window);
+            }
+            if let Ok(tree) = self.conn.query_tree(window).ok()?.reply() {
+                queue.extend(tree.children);
+            }
+        }
+        None
+    }
+
+    /// 向根窗口发送 EWMH 客户端消息，由窗口管理器处理
+    fn send_client_message(
+        &self,
+        window: Window,
+        message_type: u32,
+        data: [u32; 5],
+    ) -> anyhow::Result<()> {
+        let event = ClientMessageEvent::new(32, window, message_type, data);
+        self.conn.send_event(
+            false,
+            self.root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+impl WindowBackend for X11Backend {
+    fn enum_windows(&self, exclude: Option<isize>) -> Vec<WindowInfo> {
+        self.client_list()
+            .into_iter()
+            .filter(|window| Some(*window as isize) != exclude)
+            .filter_map(|window| self.read_window_info(window))
+            .collect()
+    }
+
+    fn is_topmost(&self, hwnd: isize) -> bool {
+        self.wm_states(hwnd as Window)
+            .contains(&self.atoms._NET_WM_STATE_ABOVE)
+    }
+
+    fn set_topmost(&self, hwnd: isize, enable: bool) -> anyhow::Result<()> {
+        let action = if enable {
+            NET_WM_STATE_ADD
+        } else {
+            NET_WM_STATE_REMOVE
+        };
+        self.send_client_message(
+            hwnd as Window,
+            self.atoms._NET_WM_STATE,
+            [
+                action,
+                self.atoms._NET_WM_STATE_ABOVE,
+                0,
+                SOURCE_APPLICATION,
+                0,
+            ],
+        )
+    }
+
+    fn restore(&self, hwnd: isize) -> anyhow::Result<()> {
+        //按 ICCCM 约定，映射窗口即可将其从最小化状态还原
+        self.conn.map_window(hwnd as Window)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn focus(&self, hwnd: isize) -> anyhow::Result<()> {
+        self.send_client_message(
+            hwnd as Window,
+            self.atoms._NET_ACTIVE_WINDOW,
+            [SOURCE_APPLICATION, CURRENT_TIME, 0, 0, 0],
+        )
+    }
+
+    fn opacity(&self, hwnd: isize) -> Option<f64> {
+        //没有该属性表示完全不透明
+        let opacity = self
+            .get_cardinals(
+                hwnd as Window,
+                self.atoms._NET_WM_WINDOW_OPACITY,
+                AtomEnum::CARDINAL,
+            )
+            .first()
+            .copied()
+            .unwrap_or(u32::MAX);
+        Some(opacity as f64 / u32::MAX as f64)
+    }
+
+    /// 通过 `_NET_WM_WINDOW_OPACITY` 设置，需要混成器支持
+    fn set_opacity(&self, hwnd: isize, opacity: f64) -> anyhow::Result<()> {
+        let window = hwnd as Window;
+        if opacity >= 1.0 {
+            self.conn
+                .delete_property(window, self.atoms._NET_WM_WINDOW_OPACITY)?;
+        } else {
+            let value = (opacity.max(0.0) * u32::MAX as f64).round() as u32;
+            self.conn.change_property32(
+                PropMode::REPLACE,
+                window,
+                self.atoms._NET_WM_WINDOW_OPACITY,
+                AtomEnum::CARDINAL,
+                &[value],
+            )?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn foreground_window(&self) -> Option<isize> {
+        self.get_cardinals(self.root, self.atoms._NET_ACTIVE_WINDOW, AtomEnum::WINDOW)
+            .first()
+            .filter(|window| **window != 0)
+            .map(|window| *window as isize)
+    }
+
+    fn window_rect(&self, hwnd: isize) -> Option<Rect> {
+        let window = hwnd as Window;
+        //最小化的窗口会被取消映射
+        let attributes = self.conn.get_window_attributes(window).ok()?.reply().ok()?;
+        if attributes.map_state != MapState::VIEWABLE {
+            return None;
+        }
+        let geometry = self.conn.get_geometry(window).ok()?.reply().ok()?;
+        //窗口坐标相对于父窗口（一般是窗口管理器的边框），换算为根窗口坐标
+        let origin = self
+            .conn
+            .translate_coordinates(window, self.root, 0, 0)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(Rect {
+            left: origin.dst_x as i32,
+            top: origin.dst_y as i32,
+            right: origin.dst_x as i32 + geometry.width as i32,
+            bottom: origin.dst_y as i32 + geometry.height as i32,
+        })
+    }
+
+    fn monitor_rect(&self, hwnd: isize) -> Option<Rect> {
+        let rect = self.window_rect(hwnd)?;
+        let (x, y) = ((rect.left + rect.right) / 2, (rect.top + rect.bottom) / 2);
+        self.monitors()
+            .into_iter()
+            .find(|m| m.left <= x && x < m.right && m.top <= y && y < m.bottom)
+    }
+
+    fn monitors(&self) -> Vec<Rect> {
+        let Some(reply) = self
+            .conn
+            .randr_get_monitors(self.root, true)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+        else {
+            return Vec::new();
+        };
+        reply
+            .monitors
+            .iter()
+            .map(|m| Rect {
+                left: m.x as i32,
+                top: m.y as i32,
+                right: m.x as i32 + m.width as i32,
+                bottom: m.y as i32 + m.height as i32,
+            })
+            .collect()
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            backend: "x11".to_string(),
+            can_set_topmost: true,
+            can_set_opacity: true,
+            can_pick_window: true,
+            can_click_through: true,
+            can_draw_border: true,
+            can_move_window: true,
+        }
+    }
+
+    fn window_info(&self, hwnd: isize) -> Option<WindowInfo> {
+        self.read_window_info(hwnd as Window)
+    }
+
+    fn window_exists(&self, hwnd: isize) -> bool {
+        //取消映射（最小化、隐藏）的窗口仍然存在，窗口销毁后查询会返回 BadWindow
+        self.conn
+            .get_window_attributes(hwnd as Window)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .is_some()
+    }
+
+    fn load_icon(&self, path: &str, size: u32) -> anyhow::Result<IconImage> {
+        let icon = self.icons.lock().unwrap().get(path).cloned();
+        icon.context("窗口没有图标")?.resized(size)
+    }
+
+    fn set_window_rect(&self, hwnd: isize, rect: &Rect) -> anyhow::Result<()> {
+        let window = hwnd as Window;
+        self.restore(hwnd)?;
+        //最大化的窗口不能移动，先取消最大化
+        self.send_client_message(
+            window,
+            self.atoms._NET_WM_STATE,
+            [
+                NET_WM_STATE_REMOVE,
+                self.atoms._NET_WM_STATE_MAXIMIZED_VERT,
+                self.atoms._NET_WM_STATE_MAXIMIZED_HORZ,
+                SOURCE_PAGER,
+                0,
+            ],
+        )?;
+        self.send_client_message(
+            window,
+            self.atoms._NET_MOVERESIZE_WINDOW,
+            [
+                STATIC_GRAVITY | MOVERESIZE_ALL | (SOURCE_PAGER << 12),
+                rect.left as u32,
+                rect.top as u32,
+                (rect.right - rect.left).max(1) as u32,
+                (rect.bottom - rect.top).max(1) as u32,
+            ],
+        )
+    }
+
+    /// 对话框等临时窗口通过 WM_TRANSIENT_FOR 指向所属的主窗口
+    fn parent_window(&self, hwnd: isize) -> Option<isize> {
+        self.get_cardinals(
+            hwnd as Window,
+            AtomEnum::WM_TRANSIENT_FOR.into(),
+            AtomEnum::WINDOW,
+        )
+        .first()
+        .filter(|owner| **owner != x11rb::NONE && **owner != self.root)
+        .map(|owner| *owner as isize)
+    }
+
+    /// 把窗口及其边框的输入区域设为空，鼠标事件会落到下方的窗口；关闭时还原为默认的输入区域
+    fn set_click_through(&self, hwnd: isize, enable: bool) -> anyhow::Result<()> {
+        let client = hwnd as Window;
+        let mut windows = vec![client];
+        let frame = self.frame_window(client);
+        if frame != client {
+            windows.push(frame);
+        }
+        for window in windows {
+            if enable {
+                self.conn.shape_rectangles(
+                    SO::SET,
+                    SK::INPUT,
+                    ClipOrdering::UNSORTED,
+                    window,
+                    0,
+                    0,
+                    &[],
+                )?;
+            } else {
+                self.conn
+                    .shape_mask(SO::SET, SK::INPUT, window, 0, 0, x11rb::NONE)?;
+            }
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// 在窗口管理器的边框外放置一个覆盖窗口，外形裁剪为一圈边框，输入区域为空，不影响鼠标操作
+    fn draw_border(&self, hwnd: isize, style: &BorderStyle) -> anyhow::Result<()> {
+        let client = hwnd as Window;
+        let frame = self.frame_window(client);
+        let mut borders = self.borders.lock().unwrap();
+        let viewable =
+            self.conn.get_window_attributes(frame)?.reply()?.map_state == MapState::VIEWABLE;
+        if !viewable {
+            if let Some(overlay) = borders.get(&client) {
+                self.conn.unmap_window(*overlay)?;
+                self.conn.flush()?;
+            }
+            return Ok(());
+        }
+        let geometry = self.conn.get_geometry(frame)?.reply()?;
+        let origin = self
+            .conn
+            .translate_coordinates(frame, self.root, 0, 0)?
+            .reply()?;
+        let thickness = style.thickness as i16;
+        let x = origin.dst_x - thickness;
+        let y = origin.dst_y - thickness;
+        let width = geometry.width + 2 * (geometry.border_width + style.thickness as u16);
+        let height = geometry.height + 2 * (geometry.border_width + style.thickness as u16);
+        let pixel = self.alloc_pixel(style.color)?;
+
+        let overlay = match borders.get(&client) {
+            Some(overlay) => *overlay,
+            None => {
+                let overlay = self.conn.generate_id()?;
+                self.conn.create_window(
+                    x11rb::COPY_DEPTH_FROM_PARENT,
+                    overlay,
+                    self.root,
+                    x,
+                    y,
+                    width,
+                    height,
+                    0,
+                    WindowClass::INPUT_OUTPUT,
+                    x11rb::COPY_FROM_PARENT,
+                    &CreateWindowAux::new().override_redirect(1),
+                )?;
+                self.conn.shape_rectangles(
+                    SO::SET,
+                    SK::INPUT,
+                    ClipOrdering::UNSORTED,
+                    overlay,
+                    0,
+                    0,
+                    &[],
+                )?;
+                borders.insert(client, overlay);
+                overlay
+            }
+        };
+        self.conn.change_window_attributes(
+            overlay,
+            &ChangeWindowAttributesAux::new().background_pixel(pixel),
+        )?;
+        self.conn.configure_window(
+            overlay,
+            &ConfigureWindowAux::new()
+                .x(x as i32)
+                .y(y as i32)
+                .width(width as u32)
+                .height(height as u32)
+                .stack_mode(StackMode::ABOVE),
+        )?;
+        self.conn.shape_rectangles(
+            SO::SET,
+            SK::BOUNDING,
+            ClipOrdering::UNSORTED,
+            overlay,
+            0,
+            0,
+            &frame_rectangles(width, height, style.thickness as u16),
+        )?;
+        self.conn.map_window(overlay)?;
+        //修改背景色后需要重绘
+        self.conn.clear_area(false, overlay, 0, 0, 0, 0)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn clear_border(&self, hwnd: isize) -> anyhow::Result<()> {
+        if let Some(overlay) = self.borders.lock().unwrap().remove(&(hwnd as Window)) {
+            self.conn.destroy_window(overlay)?;
+            self.conn.flush()?;
+        }
+        Ok(())
+    }
+
+    fn capture_click(&self) -> anyhow::Result<Option<isize>> {
+        //抓取期间需要独占事件队列，使用单独的连接
+        let picker = X11Backend::connect(self.display.as_deref(), self.icon_cache.clone())?;
+        let frame = picker.pick_loop()?;
+        Ok(frame.map(|frame| self.find_client(frame).unwrap_or(frame) as isize))
+    }
+
+    fn watch(&self, sender: Sender<RawWindowEvent>) -> anyhow::Result<()> {
+        //事件循环独占一个连接，避免阻塞查询请求
+        let watcher = X11Backend::connect(self.display.as_deref(), self.icon_cache.clone())?;
+        std::thread::spawn(move || {
+            if let Err(e) = watcher.watch_loop(sender) {
+                error_tool::report_error(format!("X11窗口监听失败：{}", e));
+            }
+        });
+        Ok(())
+    }
+}
+
+/// 宽 `width`、高 `height` 的区域中，沿四边粗为 `thickness` 的一圈
+fn frame_rectangles(width: u16, height: u16, thickness: u16) -> [Rectangle; 4] {
+    let inner_height = height.saturating_sub(2 * thickness);
+    [
+        Rectangle {
+            x: 0,
+            y: 0,
+            width,
+            height: thickness,
+        },
+        Rectangle {
+            x: 0,
+            y: height.saturating_sub(thickness) as i16,
+            width,
+            height: thickness,
+        },
+        Rectangle {
+            x: 0,
+            y: thickness as i16,
+            width: thickness,
+            height: inner_height,
+        },
+        Rectangle {
+            x: width.saturating_sub(thickness) as i16,
+            y: thickness as i16,
+            width: thickness,
+            height: inner_height,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 在另一个连接上创建带标题的普通窗口并映射，交给窗口管理器管理
+    fn create_client(conn: &RustConnection, root: Window, title: &str) -> Window {
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            200,
+            100,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            title.as_bytes(),
+        )
+        .unwrap();
+        conn.map_window(window).unwrap();
+        conn.flush().unwrap();
+        window
+    }
+
+    /// 窗口管理器异步处理请求，最多等待 5 秒
+    fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+        for _ in 0..50 {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        false
+    }
+
+    #[test]
+    #[ignore = "需要 Xvfb 和支持 EWMH 的窗口管理器：Xvfb :99 & DISPLAY=:99 openbox & DISPLAY=:99 cargo test -- --ignored"]
+    fn list_and_toggle_above_on_xvfb() {
+        let backend = X11Backend::connect(None, Arc::new(IconCache::in_memory())).unwrap();
+        let (conn, screen_num) = x11rb::connect(None).unwrap();
+        let root = conn.setup().roots[screen_num].root;
+        let first = create_client(&conn, root, "top-set-tool test 1") as isize;
+        let second = create_client(&conn, root, "top-set-tool test 2") as isize;
+
+        assert!(wait_until(|| {
+            let windows = backend.enum_windows(None);
+            [first, second]
+                .iter()
+                .all(|hwnd| windows.iter().any(|w| w.hwnd == *hwnd))
+        }));
+        let info = backend.window_info(first).unwrap();
+        assert_eq!(info.title, "top-set-tool test 1");
+        assert!(!info.is_top_most);
+
+        backend.set_topmost(first, true).unwrap();
+        assert!(wait_until(|| backend.is_topmost(first)));
+        assert!(!backend.is_topmost(second));
+        assert!(backend.window_info(first).unwrap().is_top_most);
+
+        backend.set_topmost(first, false).unwrap();
+        assert!(wait_until(|| !backend.is_topmost(first)));
+    }
+}